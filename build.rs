@@ -0,0 +1,27 @@
+use alow_scanner::Scanner;
+use std::{env, path::PathBuf};
+
+/// Protocols generated into `alow::protocol`.
+const PROTOCOLS: &[&str] = &[
+    "protocols/wayland.xml",
+    "protocols/xdg-shell.xml",
+    "protocols/xdg-output-unstable-v1.xml",
+];
+
+fn main() {
+    let out = PathBuf::from(env::var_os("OUT_DIR").expect("OUT_DIR not set")).join("protocol.rs");
+
+    let mut scanner = Scanner::new("crate");
+
+    for path in PROTOCOLS {
+        println!("cargo:rerun-if-changed={path}");
+
+        if let Err(err) = scanner.protocol_file(path) {
+            panic!("could not parse {path}: {err}");
+        }
+    }
+
+    if let Err(err) = scanner.write(&out) {
+        panic!("could not generate protocol code: {err}");
+    }
+}
@@ -0,0 +1,930 @@
+use crate::{
+    Error,
+    protocol::{Arg, ArgKind, Description, Enum, Interface, Message, Protocol},
+};
+
+/// Rust keywords that need to be escaped when used as identifiers.
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "gen", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+    "pub", "ref", "return", "static", "struct", "super", "trait", "true", "type", "unsafe", "use",
+    "where", "while", "abstract", "become", "box", "do", "final", "macro", "override", "priv",
+    "try", "typeof", "unsized", "virtual", "yield",
+];
+
+pub(crate) fn generate(
+    crate_path: &str,
+    protocols: &[Protocol],
+    externals: &[(String, Protocol)],
+) -> Result<String, Error> {
+    let cx = Context {
+        crate_path,
+        protocols,
+        externals,
+    };
+
+    let mut out = Writer::default();
+    out.line("// Generated by alow-scanner, do not edit.");
+
+    for protocol in protocols {
+        out.line("");
+        out.open(format!("pub mod {} {{", protocol.name));
+
+        for interface in &protocol.interfaces {
+            cx.interface(&mut out, interface)?;
+        }
+
+        out.close("}");
+    }
+
+    Ok(out.buf)
+}
+
+/// Indenting line writer.
+#[derive(Default)]
+struct Writer {
+    buf: String,
+    indent: usize,
+}
+
+impl Writer {
+    fn line(&mut self, line: impl AsRef<str>) {
+        let line = line.as_ref();
+
+        if !line.is_empty() {
+            self.buf.extend(std::iter::repeat_n("    ", self.indent));
+            self.buf.push_str(line);
+        }
+
+        self.buf.push('\n');
+    }
+
+    /// Writes a line and indents the following ones.
+    fn open(&mut self, line: impl AsRef<str>) {
+        self.line(line);
+        self.indent += 1;
+    }
+
+    /// Dedents and writes a line.
+    fn close(&mut self, line: impl AsRef<str>) {
+        self.indent -= 1;
+        self.line(line);
+    }
+
+    fn doc(&mut self, description: Option<&Description>) {
+        let Some(description) = description else {
+            return;
+        };
+
+        if !description.summary.is_empty() {
+            self.line(format!("/// {}", description.summary));
+        }
+
+        let mut item_indent = None;
+        let text: Vec<_> = description
+            .text
+            .lines()
+            .map(|line| {
+                let trimmed = line.trim();
+                let indent = line.len() - line.trim_start().len();
+
+                if trimmed.starts_with("- ") || trimmed.starts_with("* ") {
+                    item_indent = Some(indent);
+                    return trimmed.to_owned();
+                }
+
+                // Continuation lines of list items stay indented
+                match item_indent {
+                    Some(item) if indent > item && !trimmed.is_empty() => format!("  {trimmed}"),
+                    _ => {
+                        item_indent = None;
+                        trimmed.to_owned()
+                    }
+                }
+            })
+            .collect();
+        let start = text.iter().position(|line| !line.is_empty());
+        let end = text.iter().rposition(|line| !line.is_empty());
+
+        if let (Some(start), Some(end)) = (start, end) {
+            if !description.summary.is_empty() {
+                self.line("///");
+            }
+
+            for line in &text[start..=end] {
+                match line.is_empty() {
+                    true => self.line("///"),
+                    false => self.line(format!("/// {line}")),
+                }
+            }
+        }
+    }
+
+    fn summary(&mut self, summary: Option<&str>) {
+        if let Some(summary) = summary {
+            self.line(format!("/// {summary}"));
+        }
+    }
+}
+
+/// Which side of an interface a set of messages belongs to.
+#[derive(Clone, Copy)]
+enum Direction {
+    Request,
+    Event,
+}
+
+impl Direction {
+    fn type_name(self) -> &'static str {
+        match self {
+            Direction::Request => "Request",
+            Direction::Event => "Event",
+        }
+    }
+
+    fn field(self) -> &'static str {
+        match self {
+            Direction::Request => "requests",
+            Direction::Event => "events",
+        }
+    }
+}
+
+/// How an integer argument maps to an enum, if at all.
+enum EnumKind {
+    None,
+    Enum(String),
+    Bitfield(String),
+}
+
+struct Context<'a> {
+    crate_path: &'a str,
+    protocols: &'a [Protocol],
+    externals: &'a [(String, Protocol)],
+}
+
+impl Context<'_> {
+    fn interface(&self, out: &mut Writer, interface: &Interface) -> Result<(), Error> {
+        let c = self.crate_path;
+
+        out.line("");
+        out.doc(interface.description.as_ref());
+        out.open(format!("pub mod {} {{", interface.name));
+        out.line("#![allow(unused_imports)]");
+        out.line("");
+        out.line(format!("use {c}::protocol::{{Args, MessageGroup, WEnum}};"));
+        out.line(format!(
+            "use {c}::wire::{{Argument, ArgumentKind, Fixed, Interface, Message, MessageDesc, ObjectId, WireError}};"
+        ));
+        out.line("use std::os::fd::OwnedFd;");
+        out.line("");
+
+        out.line(format!(
+            "/// Highest supported version of `{}`.",
+            interface.name
+        ));
+        out.line(format!("pub const VERSION: u32 = {};", interface.version));
+        out.line("");
+
+        out.line(format!(
+            "/// Descriptor of the `{}` interface.",
+            interface.name
+        ));
+        out.open("pub static INTERFACE: Interface = Interface {");
+        out.line(format!("name: \"{}\",", interface.name));
+        out.line("version: VERSION,");
+        self.descs(out, "requests", &interface.requests)?;
+        self.descs(out, "events", &interface.events)?;
+        out.close("};");
+
+        for enumeration in &interface.enums {
+            self.enumeration(out, enumeration);
+        }
+
+        self.messages(out, interface, Direction::Request, &interface.requests)?;
+        self.messages(out, interface, Direction::Event, &interface.events)?;
+
+        out.close("}");
+
+        Ok(())
+    }
+
+    fn descs(&self, out: &mut Writer, field: &str, messages: &[Message]) -> Result<(), Error> {
+        if messages.is_empty() {
+            out.line(format!("{field}: &[],"));
+            return Ok(());
+        }
+
+        out.open(format!("{field}: &["));
+
+        for message in messages {
+            let mut signature = Vec::new();
+            let mut interfaces = Vec::new();
+            let mut child = None;
+
+            for arg in &message.args {
+                match (arg.kind, &arg.interface) {
+                    (ArgKind::NewId, Some(interface)) => {
+                        signature.push("NewId");
+                        interfaces.push(Some(interface));
+                        child = Some(interface);
+                    }
+                    // Untyped new_id is preceded by the interface name and version
+                    (ArgKind::NewId, None) => {
+                        signature.extend(["String", "Uint", "NewId"]);
+                        interfaces.extend([None, None, None]);
+                    }
+                    (ArgKind::Object, interface) => {
+                        signature.push("Object");
+                        interfaces.push(interface.as_ref());
+                    }
+                    (kind, _) => {
+                        signature.push(kind_name(kind));
+                        interfaces.push(None);
+                    }
+                }
+            }
+
+            let signature: Vec<_> = signature
+                .iter()
+                .map(|kind| format!("ArgumentKind::{kind}"))
+                .collect();
+
+            let interfaces = interfaces
+                .into_iter()
+                .map(|interface| match interface {
+                    Some(interface) => Ok(format!(
+                        "Some(&{}::INTERFACE)",
+                        self.interface_path(interface)?
+                    )),
+                    None => Ok("None".into()),
+                })
+                .collect::<Result<Vec<String>, Error>>()?;
+
+            let child = match child {
+                Some(interface) => format!("Some(&{}::INTERFACE)", self.interface_path(interface)?),
+                None => "None".into(),
+            };
+
+            out.open("MessageDesc {");
+            out.line(format!("name: \"{}\",", message.name));
+            out.line(format!("since: {},", message.since));
+            out.line(format!("signature: &[{}],", signature.join(", ")));
+            out.line(format!("interfaces: &[{}],", interfaces.join(", ")));
+            out.line(format!("child_interface: {child},"));
+            out.line(format!("is_destructor: {},", message.is_destructor));
+            out.close("},");
+        }
+
+        out.close("],");
+
+        Ok(())
+    }
+
+    fn enumeration(&self, out: &mut Writer, enumeration: &Enum) {
+        let name = type_name(&enumeration.name);
+
+        out.line("");
+
+        if enumeration.bitfield {
+            out.open(format!(
+                "{}::protocol::bitflags::bitflags! {{",
+                self.crate_path
+            ));
+            out.doc(enumeration.description.as_ref());
+            out.line("#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]");
+            out.open(format!("pub struct {name}: u32 {{"));
+
+            for entry in &enumeration.entries {
+                out.summary(entry.summary.as_deref());
+                out.line(format!(
+                    "const {} = {};",
+                    const_name(&entry.name),
+                    entry.value
+                ));
+            }
+
+            out.close("}");
+            out.close("}");
+
+            return;
+        }
+
+        out.doc(enumeration.description.as_ref());
+
+        if !enumeration.entries.is_empty() {
+            out.line("#[repr(u32)]");
+        }
+
+        out.line("#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]");
+        out.open(format!("pub enum {name} {{"));
+
+        for entry in &enumeration.entries {
+            out.summary(entry.summary.as_deref());
+            out.line(format!("{} = {},", type_name(&entry.name), entry.value));
+        }
+
+        out.close("}");
+        out.line("");
+
+        out.open(format!("impl TryFrom<u32> for {name} {{"));
+        out.line("type Error = u32;");
+        out.line("");
+        out.open("fn try_from(value: u32) -> Result<Self, u32> {");
+        out.open("match value {");
+
+        for entry in &enumeration.entries {
+            out.line(format!(
+                "{} => Ok(Self::{}),",
+                entry.value,
+                type_name(&entry.name)
+            ));
+        }
+
+        out.line("_ => Err(value),");
+        out.close("}");
+        out.close("}");
+        out.close("}");
+        out.line("");
+
+        out.open(format!("impl From<{name}> for u32 {{"));
+        out.open(format!("fn from(value: {name}) -> u32 {{"));
+
+        match enumeration.entries.is_empty() {
+            true => out.line("match value {}"),
+            false => out.line("value as u32"),
+        }
+
+        out.close("}");
+        out.close("}");
+    }
+
+    fn messages(
+        &self,
+        out: &mut Writer,
+        interface: &Interface,
+        direction: Direction,
+        messages: &[Message],
+    ) -> Result<(), Error> {
+        let ty = direction.type_name();
+
+        out.line("");
+        out.line(format!(
+            "/// {}s of the `{}` interface.",
+            ty, interface.name
+        ));
+        out.line("#[derive(Debug)]");
+        out.open(format!("pub enum {ty} {{"));
+
+        for message in messages {
+            out.doc(message.description.as_ref());
+
+            if message.args.is_empty() {
+                out.line(format!("{},", type_name(&message.name)));
+                continue;
+            }
+
+            out.open(format!("{} {{", type_name(&message.name)));
+
+            for arg in &message.args {
+                if arg.kind == ArgKind::NewId && arg.interface.is_none() {
+                    out.line("/// interface of the new object");
+                    out.line("interface: String,");
+                    out.line("/// version of the new object");
+                    out.line("version: u32,");
+                }
+
+                out.summary(arg.summary.as_deref());
+                out.line(format!(
+                    "{}: {},",
+                    field_name(&arg.name),
+                    self.arg_type(interface, arg)?
+                ));
+            }
+
+            out.close("},");
+        }
+
+        out.close("}");
+        out.line("");
+
+        out.open(format!("impl MessageGroup for {ty} {{"));
+
+        out.open("fn interface() -> &'static Interface {");
+        out.line("&INTERFACE");
+        out.close("}");
+        out.line("");
+
+        if messages.is_empty() {
+            out.open("fn opcode(&self) -> u16 {");
+            out.line("match *self {}");
+            out.close("}");
+            out.line("");
+            out.open("fn desc(&self) -> &'static MessageDesc {");
+            out.line("match *self {}");
+            out.close("}");
+            out.line("");
+            out.open("fn from_message(message: Message) -> Result<Self, WireError> {");
+            out.open("Err(WireError::UnknownMessage {");
+            out.line("object: message.object,");
+            out.line("opcode: message.opcode,");
+            out.close("})");
+            out.close("}");
+            out.line("");
+            out.open("fn into_message(self, _object: ObjectId) -> Message {");
+            out.line("match self {}");
+            out.close("}");
+            out.close("}");
+
+            return Ok(());
+        }
+
+        out.open("fn opcode(&self) -> u16 {");
+        out.open("match *self {");
+
+        for (opcode, message) in messages.iter().enumerate() {
+            let pattern = match message.args.is_empty() {
+                true => "",
+                false => " { .. }",
+            };
+
+            out.line(format!(
+                "Self::{}{pattern} => {opcode},",
+                type_name(&message.name)
+            ));
+        }
+
+        out.close("}");
+        out.close("}");
+        out.line("");
+
+        out.open("fn desc(&self) -> &'static MessageDesc {");
+        out.line(format!(
+            "&INTERFACE.{}[self.opcode() as usize]",
+            direction.field()
+        ));
+        out.close("}");
+        out.line("");
+
+        out.open("fn from_message(message: Message) -> Result<Self, WireError> {");
+        match messages.iter().any(|message| !message.args.is_empty()) {
+            true => out.line("let mut args = Args::new(message.args);"),
+            false => out.line("let args = Args::new(message.args);"),
+        }
+
+        out.line("");
+        out.open("let value = match message.opcode {");
+
+        for (opcode, message) in messages.iter().enumerate() {
+            let name = type_name(&message.name);
+
+            if message.args.is_empty() {
+                out.line(format!("{opcode} => Self::{name},"));
+                continue;
+            }
+
+            out.open(format!("{opcode} => Self::{name} {{"));
+
+            for arg in &message.args {
+                if arg.kind == ArgKind::NewId && arg.interface.is_none() {
+                    out.line("interface: args.string()?,");
+                    out.line("version: args.uint()?,");
+                }
+
+                out.line(format!(
+                    "{}: {},",
+                    field_name(&arg.name),
+                    self.parse_expr(interface, arg)?
+                ));
+            }
+
+            out.close("},");
+        }
+
+        out.open("opcode => {");
+        out.open("return Err(WireError::UnknownMessage {");
+        out.line("object: message.object,");
+        out.line("opcode,");
+        out.close("});");
+        out.close("}");
+        out.close("};");
+        out.line("");
+        out.line("args.finish()?;");
+        out.line("");
+        out.line("Ok(value)");
+        out.close("}");
+        out.line("");
+
+        out.open("fn into_message(self, object: ObjectId) -> Message {");
+        out.line("let opcode = self.opcode();");
+        out.line("");
+        out.open("let args = match self {");
+
+        for message in messages {
+            let name = type_name(&message.name);
+
+            if message.args.is_empty() {
+                out.line(format!("Self::{name} => Vec::new(),"));
+                continue;
+            }
+
+            let mut bindings = Vec::new();
+            let mut values = Vec::new();
+
+            for arg in &message.args {
+                if arg.kind == ArgKind::NewId && arg.interface.is_none() {
+                    bindings.extend(["interface".to_owned(), "version".to_owned()]);
+                    values.push("Argument::String(Some(interface))".to_owned());
+                    values.push("Argument::Uint(version)".to_owned());
+                }
+
+                let binding = field_name(&arg.name);
+                values.push(self.emit_expr(interface, arg, &binding)?);
+                bindings.push(binding);
+            }
+
+            out.open(format!(
+                "Self::{name} {{ {} }} => vec![",
+                bindings.join(", ")
+            ));
+
+            for value in values {
+                out.line(format!("{value},"));
+            }
+
+            out.close("],");
+        }
+
+        out.close("};");
+        out.line("");
+        out.line("Message::new(object, opcode, args)");
+        out.close("}");
+        out.close("}");
+
+        Ok(())
+    }
+
+    fn arg_type(&self, interface: &Interface, arg: &Arg) -> Result<String, Error> {
+        let ty = match arg.kind {
+            ArgKind::Int | ArgKind::Uint => match self.enum_kind(interface, arg)? {
+                EnumKind::Enum(path) => format!("WEnum<{path}>"),
+                EnumKind::Bitfield(path) => path,
+                EnumKind::None if arg.kind == ArgKind::Int => "i32".into(),
+                EnumKind::None => "u32".into(),
+            },
+            ArgKind::Fixed => "Fixed".into(),
+            ArgKind::String if arg.allow_null => "Option<String>".into(),
+            ArgKind::String => "String".into(),
+            ArgKind::Object if arg.allow_null => "Option<ObjectId>".into(),
+            ArgKind::Object | ArgKind::NewId => "ObjectId".into(),
+            ArgKind::Array => "Vec<u8>".into(),
+            ArgKind::Fd => "OwnedFd".into(),
+        };
+
+        Ok(ty)
+    }
+
+    fn parse_expr(&self, interface: &Interface, arg: &Arg) -> Result<String, Error> {
+        let expr = match arg.kind {
+            ArgKind::Int | ArgKind::Uint => {
+                let raw = match arg.kind {
+                    ArgKind::Int => "args.int()? as u32",
+                    _ => "args.uint()?",
+                };
+
+                match self.enum_kind(interface, arg)? {
+                    EnumKind::Enum(_) => format!("WEnum::from_raw({raw})"),
+                    EnumKind::Bitfield(path) => format!("{path}::from_bits_retain({raw})"),
+                    EnumKind::None if arg.kind == ArgKind::Int => "args.int()?".into(),
+                    EnumKind::None => "args.uint()?".into(),
+                }
+            }
+            ArgKind::Fixed => "args.fixed()?".into(),
+            ArgKind::String if arg.allow_null => "args.opt_string()?".into(),
+            ArgKind::String => "args.string()?".into(),
+            ArgKind::Object if arg.allow_null => "args.opt_object()?".into(),
+            ArgKind::Object => "args.object()?".into(),
+            ArgKind::NewId => "args.new_id()?".into(),
+            ArgKind::Array => "args.array()?".into(),
+            ArgKind::Fd => "args.fd()?".into(),
+        };
+
+        Ok(expr)
+    }
+
+    fn emit_expr(&self, interface: &Interface, arg: &Arg, binding: &str) -> Result<String, Error> {
+        let expr = match arg.kind {
+            ArgKind::Int | ArgKind::Uint => {
+                let raw = match self.enum_kind(interface, arg)? {
+                    EnumKind::Enum(_) => format!("{binding}.into_raw()"),
+                    EnumKind::Bitfield(_) => format!("{binding}.bits()"),
+                    EnumKind::None => binding.to_owned(),
+                };
+
+                match (arg.kind, arg.enumeration.is_some()) {
+                    (ArgKind::Int, true) => format!("Argument::Int({raw} as i32)"),
+                    (ArgKind::Int, false) => format!("Argument::Int({raw})"),
+                    _ => format!("Argument::Uint({raw})"),
+                }
+            }
+            ArgKind::Fixed => format!("Argument::Fixed({binding})"),
+            ArgKind::String if arg.allow_null => format!("Argument::String({binding})"),
+            ArgKind::String => format!("Argument::String(Some({binding}))"),
+            ArgKind::Object if arg.allow_null => format!("Argument::Object({binding})"),
+            ArgKind::Object => format!("Argument::Object(Some({binding}))"),
+            ArgKind::NewId => format!("Argument::NewId({binding})"),
+            ArgKind::Array => format!("Argument::Array({binding})"),
+            ArgKind::Fd => format!("Argument::Fd({binding})"),
+        };
+
+        Ok(expr)
+    }
+
+    /// Resolves the enum an integer argument refers to.
+    fn enum_kind(&self, interface: &Interface, arg: &Arg) -> Result<EnumKind, Error> {
+        let Some(reference) = &arg.enumeration else {
+            return Ok(EnumKind::None);
+        };
+
+        let (owner, name) = match reference.split_once('.') {
+            Some((owner, name)) => (owner, name),
+            None => (interface.name.as_str(), reference.as_str()),
+        };
+
+        let enumeration = self
+            .find_interface(owner)
+            .and_then(|owner| owner.enums.iter().find(|e| e.name == name))
+            .ok_or_else(|| Error::UnknownEnum(reference.clone()))?;
+
+        let path = match owner == interface.name {
+            true => type_name(name),
+            false => format!("{}::{}", self.interface_path(owner)?, type_name(name)),
+        };
+
+        match enumeration.bitfield {
+            true => Ok(EnumKind::Bitfield(path)),
+            false => Ok(EnumKind::Enum(path)),
+        }
+    }
+
+    fn find_interface(&self, name: &str) -> Option<&Interface> {
+        let local = self.protocols.iter();
+        let external = self.externals.iter().map(|(_, protocol)| protocol);
+
+        local
+            .chain(external)
+            .flat_map(|protocol| &protocol.interfaces)
+            .find(|interface| interface.name == name)
+    }
+
+    /// Returns the path of an interface module, relative to another interface module.
+    fn interface_path(&self, name: &str) -> Result<String, Error> {
+        let defines = |protocol: &Protocol| protocol.interfaces.iter().any(|i| i.name == name);
+
+        if let Some(protocol) = self.protocols.iter().find(|p| defines(p)) {
+            return Ok(format!("super::super::{}::{name}", protocol.name));
+        }
+
+        if let Some((path, _)) = self.externals.iter().find(|(_, p)| defines(p)) {
+            return Ok(format!("{path}::{name}"));
+        }
+
+        Err(Error::UnknownInterface(name.into()))
+    }
+}
+
+fn kind_name(kind: ArgKind) -> &'static str {
+    match kind {
+        ArgKind::Int => "Int",
+        ArgKind::Uint => "Uint",
+        ArgKind::Fixed => "Fixed",
+        ArgKind::String => "String",
+        ArgKind::Object => "Object",
+        ArgKind::NewId => "NewId",
+        ArgKind::Array => "Array",
+        ArgKind::Fd => "Fd",
+    }
+}
+
+/// Converts `snake_case` into `CamelCase`.
+fn type_name(name: &str) -> String {
+    let mut out: String = name
+        .split('_')
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect();
+
+    if out.starts_with(|c: char| c.is_ascii_digit()) {
+        out.insert(0, '_');
+    }
+
+    out
+}
+
+/// Converts `snake_case` into `SCREAMING_CASE`.
+fn const_name(name: &str) -> String {
+    let mut out = name.to_uppercase();
+
+    if out.starts_with(|c: char| c.is_ascii_digit()) {
+        out.insert(0, '_');
+    }
+
+    out
+}
+
+fn field_name(name: &str) -> String {
+    match KEYWORDS.contains(&name) {
+        true => format!("r#{name}"),
+        false => name.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Scanner;
+
+    const PROTOCOL: &str = r#"<protocol name="test">
+  <interface name="test_manager" version="2">
+    <description summary="manages &lt;things&gt;">
+      Some items:
+      - first item,
+        continued
+      - second
+
+      Back to &quot;text&quot;.
+    </description>
+
+    <request name="create" since="2">
+      <arg name="id" type="new_id" interface="test_thing"/>
+      <arg name="parent" type="object" interface="test_thing" allow-null="true"/>
+      <arg name="name" type="string" allow-null="true"/>
+      <arg name="mode" type="uint" enum="mode"/>
+      <arg name="caps" type="uint" enum="test_thing.caps"/>
+    </request>
+
+    <request name="bind">
+      <arg name="type" type="new_id"/>
+    </request>
+
+    <enum name="mode">
+      <entry name="fill" value="0"/>
+      <entry name="2x" value="1"/>
+    </enum>
+  </interface>
+
+  <interface name="test_thing" version="1">
+    <enum name="caps" bitfield="true">
+      <entry name="read" value="1"/>
+      <entry name="write" value="2"/>
+    </enum>
+  </interface>
+</protocol>"#;
+
+    fn generate() -> String {
+        Scanner::new("crate")
+            .protocol(PROTOCOL)
+            .unwrap()
+            .generate()
+            .unwrap()
+    }
+
+    fn contains_lines(code: &str, expected: &[&str]) -> bool {
+        let lines: Vec<_> = code.lines().map(str::trim).collect();
+        lines
+            .windows(expected.len())
+            .any(|window| window == expected)
+    }
+
+    #[test]
+    fn since() {
+        let code = generate();
+
+        assert!(contains_lines(&code, &["name: \"create\",", "since: 2,"]));
+        assert!(contains_lines(&code, &["name: \"bind\",", "since: 1,"]));
+    }
+
+    #[test]
+    fn nullable_args() {
+        let code = generate();
+
+        assert!(code.contains("parent: Option<ObjectId>,"));
+        assert!(code.contains("name: Option<String>,"));
+        assert!(code.contains("id: ObjectId,"));
+        assert!(code.contains("parent: args.opt_object()?,"));
+        assert!(code.contains("Argument::Object(parent)"));
+    }
+
+    #[test]
+    fn untyped_new_id() {
+        let code = generate();
+
+        assert!(code.contains(
+            "signature: &[ArgumentKind::String, ArgumentKind::Uint, ArgumentKind::NewId],"
+        ));
+        assert!(code.contains("r#type: ObjectId,"));
+        assert!(code.contains("interfaces: &[None, None, None],"));
+    }
+
+    #[test]
+    fn arg_interfaces() {
+        let code = generate();
+
+        assert!(code.contains(
+            "interfaces: &[Some(&super::super::test::test_thing::INTERFACE), \
+             Some(&super::super::test::test_thing::INTERFACE), None, None, None],"
+        ));
+    }
+
+    #[test]
+    fn enums() {
+        let code = generate();
+
+        assert!(code.contains("mode: WEnum<Mode>,"));
+        assert!(code.contains("_2x = 1,"));
+        assert!(code.contains("1 => Ok(Self::_2x),"));
+        assert!(code.contains("caps: super::super::test::test_thing::Caps,"));
+        assert!(code.contains("crate::protocol::bitflags::bitflags! {"));
+        assert!(contains_lines(
+            &code,
+            &["const READ = 1;", "const WRITE = 2;"]
+        ));
+    }
+
+    #[test]
+    fn unknown_enum() {
+        let xml = PROTOCOL.replace("test_thing.caps", "test_thing.flags");
+        let result = Scanner::new("crate").protocol(&xml).unwrap().generate();
+
+        assert!(matches!(
+            result,
+            Err(crate::Error::UnknownEnum(name)) if name == "test_thing.flags",
+        ));
+    }
+
+    #[test]
+    fn description() {
+        let code = generate();
+
+        assert!(contains_lines(
+            &code,
+            &[
+                "/// manages <things>",
+                "///",
+                "/// Some items:",
+                "/// - first item,",
+                "///   continued",
+                "/// - second",
+                "///",
+                "/// Back to \"text\".",
+                "pub mod test_manager {",
+            ],
+        ));
+    }
+
+    #[test]
+    fn list_continuation() {
+        let xml = r#"<protocol name="test">
+  <interface name="test_list" version="1">
+    <description summary="lists">
+      Items:
+      - a long item
+        continued below
+      * another one
+          indented further
+      Not part of the list.
+    </description>
+  </interface>
+</protocol>"#;
+
+        let code = Scanner::new("crate")
+            .protocol(xml)
+            .unwrap()
+            .generate()
+            .unwrap();
+
+        let docs: Vec<_> = code
+            .lines()
+            .map(str::trim)
+            .filter(|line| line.starts_with("///"))
+            .collect();
+
+        assert_eq!(
+            docs[..7],
+            [
+                "/// lists",
+                "///",
+                "/// Items:",
+                "/// - a long item",
+                "///   continued below",
+                "/// * another one",
+                "///   indented further",
+            ],
+        );
+        assert_eq!(docs[7], "/// Not part of the list.");
+    }
+}
@@ -0,0 +1,90 @@
+mod generate;
+mod protocol;
+mod xml;
+
+use protocol::Protocol;
+use std::{fs, io, path::Path};
+use thiserror::Error;
+
+/// Errors returned by [`Scanner`].
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("could not read or write file: {0}")]
+    Io(#[source] io::Error),
+    #[error("malformed XML at line {line}: {message}")]
+    Xml { line: usize, message: String },
+    #[error("invalid protocol at line {line}: {message}")]
+    Protocol { line: usize, message: String },
+    #[error("unknown interface `{0}`")]
+    UnknownInterface(String),
+    #[error("unknown enum `{0}`")]
+    UnknownEnum(String),
+}
+
+/// Generates Rust types for Wayland protocol XML files.
+///
+/// Every protocol becomes a module containing one module per interface, with
+/// the interface descriptor, `Request` and `Event` enums and its enum and
+/// bitfield types. Intended to be used from a build script:
+///
+/// ```no_run
+/// # fn main() -> Result<(), alow_scanner::Error> {
+/// let out = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("protocols.rs");
+///
+/// alow_scanner::Scanner::new("::alow")
+///     .external("::alow::protocol::wayland", &std::fs::read_to_string("wayland.xml").unwrap())?
+///     .protocol_file("protocols/my-protocol.xml")?
+///     .write(out)
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Scanner {
+    crate_path: String,
+    protocols: Vec<Protocol>,
+    externals: Vec<(String, Protocol)>,
+}
+
+impl Scanner {
+    /// Creates a scanner.
+    ///
+    /// `crate_path` is the path to the alow crate in generated code, such as
+    /// `::alow`, or `crate` inside alow itself.
+    pub fn new(crate_path: &str) -> Self {
+        Scanner {
+            crate_path: crate_path.into(),
+            protocols: Vec::new(),
+            externals: Vec::new(),
+        }
+    }
+
+    /// Adds a protocol to generate code for.
+    pub fn protocol(&mut self, xml: &str) -> Result<&mut Self, Error> {
+        self.protocols.push(Protocol::parse(xml)?);
+        Ok(self)
+    }
+
+    /// Adds a protocol file to generate code for.
+    pub fn protocol_file(&mut self, path: impl AsRef<Path>) -> Result<&mut Self, Error> {
+        self.protocol(&fs::read_to_string(path).map_err(Error::Io)?)
+    }
+
+    /// Adds a protocol whose code is generated elsewhere.
+    ///
+    /// Its interfaces and enums can be referenced by the generated protocols and
+    /// are resolved under `module_path`, e.g. `::alow::protocol::wayland`.
+    pub fn external(&mut self, module_path: &str, xml: &str) -> Result<&mut Self, Error> {
+        self.externals
+            .push((module_path.into(), Protocol::parse(xml)?));
+        Ok(self)
+    }
+
+    /// Generates the code for all added protocols.
+    pub fn generate(&self) -> Result<String, Error> {
+        generate::generate(&self.crate_path, &self.protocols, &self.externals)
+    }
+
+    /// Generates the code and writes it to a file.
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        fs::write(path, self.generate()?).map_err(Error::Io)
+    }
+}
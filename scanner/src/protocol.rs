@@ -0,0 +1,392 @@
+use crate::{
+    Error,
+    xml::{self, Element},
+};
+
+/// A parsed protocol file.
+#[derive(Debug)]
+pub(crate) struct Protocol {
+    pub name: String,
+    pub interfaces: Vec<Interface>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Interface {
+    pub name: String,
+    pub version: u32,
+    pub description: Option<Description>,
+    pub requests: Vec<Message>,
+    pub events: Vec<Message>,
+    pub enums: Vec<Enum>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Description {
+    pub summary: String,
+    pub text: String,
+}
+
+#[derive(Debug)]
+pub(crate) struct Message {
+    pub name: String,
+    pub is_destructor: bool,
+    pub since: u32,
+    pub description: Option<Description>,
+    pub args: Vec<Arg>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Arg {
+    pub name: String,
+    pub kind: ArgKind,
+    pub interface: Option<String>,
+    pub allow_null: bool,
+    /// Referenced enum, either `name` or `interface.name`.
+    pub enumeration: Option<String>,
+    pub summary: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ArgKind {
+    Int,
+    Uint,
+    Fixed,
+    String,
+    Object,
+    NewId,
+    Array,
+    Fd,
+}
+
+#[derive(Debug)]
+pub(crate) struct Enum {
+    pub name: String,
+    pub bitfield: bool,
+    pub description: Option<Description>,
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Entry {
+    pub name: String,
+    pub value: u32,
+    pub summary: Option<String>,
+}
+
+impl Protocol {
+    /// Parses a protocol XML document.
+    pub fn parse(input: &str) -> Result<Self, Error> {
+        let root = xml::parse(input)?;
+
+        if root.name != "protocol" {
+            return Err(invalid(&root, "root element must be `protocol`"));
+        }
+
+        let interfaces = root
+            .elements()
+            .filter(|element| element.name == "interface")
+            .map(parse_interface)
+            .collect::<Result<_, _>>()?;
+
+        Ok(Protocol {
+            name: required(&root, "name")?.to_owned(),
+            interfaces,
+        })
+    }
+}
+
+fn parse_interface(element: &Element) -> Result<Interface, Error> {
+    let mut interface = Interface {
+        name: required(element, "name")?.to_owned(),
+        version: number(element, required(element, "version")?)?,
+        description: description(element),
+        requests: Vec::new(),
+        events: Vec::new(),
+        enums: Vec::new(),
+    };
+
+    for child in element.elements() {
+        match child.name.as_str() {
+            "request" => interface.requests.push(parse_message(child)?),
+            "event" => interface.events.push(parse_message(child)?),
+            "enum" => interface.enums.push(parse_enum(child)?),
+            _ => {}
+        }
+    }
+
+    Ok(interface)
+}
+
+fn parse_message(element: &Element) -> Result<Message, Error> {
+    let since = match element.attr("since") {
+        Some(since) => number(element, since)?,
+        None => 1,
+    };
+
+    let args = element
+        .elements()
+        .filter(|child| child.name == "arg")
+        .map(parse_arg)
+        .collect::<Result<_, _>>()?;
+
+    Ok(Message {
+        name: required(element, "name")?.to_owned(),
+        is_destructor: element.attr("type") == Some("destructor"),
+        since,
+        description: description(element),
+        args,
+    })
+}
+
+fn parse_arg(element: &Element) -> Result<Arg, Error> {
+    let kind = match required(element, "type")? {
+        "int" => ArgKind::Int,
+        "uint" => ArgKind::Uint,
+        "fixed" => ArgKind::Fixed,
+        "string" => ArgKind::String,
+        "object" => ArgKind::Object,
+        "new_id" => ArgKind::NewId,
+        "array" => ArgKind::Array,
+        "fd" => ArgKind::Fd,
+        other => {
+            return Err(invalid(
+                element,
+                &format!("unknown argument type `{other}`"),
+            ));
+        }
+    };
+
+    Ok(Arg {
+        name: required(element, "name")?.to_owned(),
+        kind,
+        interface: element.attr("interface").map(str::to_owned),
+        allow_null: element.attr("allow-null") == Some("true"),
+        enumeration: element.attr("enum").map(str::to_owned),
+        summary: element.attr("summary").map(str::to_owned),
+    })
+}
+
+fn parse_enum(element: &Element) -> Result<Enum, Error> {
+    let entries = element
+        .elements()
+        .filter(|child| child.name == "entry")
+        .map(|child| {
+            Ok(Entry {
+                name: required(child, "name")?.to_owned(),
+                value: number(child, required(child, "value")?)?,
+                summary: child.attr("summary").map(str::to_owned),
+            })
+        })
+        .collect::<Result<_, Error>>()?;
+
+    Ok(Enum {
+        name: required(element, "name")?.to_owned(),
+        bitfield: element.attr("bitfield") == Some("true"),
+        description: description(element),
+        entries,
+    })
+}
+
+fn description(element: &Element) -> Option<Description> {
+    let child = element
+        .elements()
+        .find(|child| child.name == "description")?;
+
+    Some(Description {
+        summary: child.attr("summary").unwrap_or_default().to_owned(),
+        text: child.text(),
+    })
+}
+
+fn required<'a>(element: &'a Element, name: &str) -> Result<&'a str, Error> {
+    element
+        .attr(name)
+        .ok_or_else(|| invalid(element, &format!("missing attribute `{name}`")))
+}
+
+/// Parses a decimal or `0x` prefixed hexadecimal number.
+fn number(element: &Element, value: &str) -> Result<u32, Error> {
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+
+    parsed.map_err(|_| invalid(element, &format!("invalid number `{value}`")))
+}
+
+fn invalid(element: &Element, message: &str) -> Error {
+    Error::Protocol {
+        line: element.line,
+        message: format!("<{}>: {message}", element.name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROTOCOL: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<protocol name="test">
+  <!-- comments are skipped -->
+  <interface name="test_manager" version="4">
+    <description summary="a &quot;test&quot; manager">
+      Creates &lt;things&gt; &amp; more.
+    </description>
+
+    <request name="destroy" type="destructor"/>
+
+    <request name="create" since="3">
+      <arg name="id" type="new_id" interface="test_thing"/>
+      <arg name="parent" type="object" interface="test_thing" allow-null="true"/>
+      <arg name="mode" type="uint" enum="mode"/>
+    </request>
+
+    <request name="bind">
+      <arg name="id" type="new_id" allow-null="true"/>
+    </request>
+
+    <event name="done" since="0x2">
+      <arg name="caps" type="uint" enum="test_thing.caps" summary="a &amp; b"/>
+    </event>
+
+    <enum name="mode">
+      <entry name="fill" value="0"/>
+      <entry name="fit" value="0x10" summary="keep &apos;aspect&apos;"/>
+    </enum>
+  </interface>
+
+  <interface name="test_thing" version="1">
+    <enum name="caps" bitfield="true">
+      <entry name="read" value="1"/>
+      <entry name="write" value="2"/>
+    </enum>
+  </interface>
+</protocol>
+"#;
+
+    fn parse(xml: &str) -> Result<Protocol, Error> {
+        Protocol::parse(xml)
+    }
+
+    #[test]
+    fn interfaces() {
+        let protocol = parse(PROTOCOL).unwrap();
+        let names: Vec<_> = protocol.interfaces.iter().map(|i| &i.name).collect();
+
+        assert_eq!(protocol.name, "test");
+        assert_eq!(names, ["test_manager", "test_thing"]);
+        assert_eq!(protocol.interfaces[0].version, 4);
+    }
+
+    #[test]
+    fn since() {
+        let protocol = parse(PROTOCOL).unwrap();
+        let manager = &protocol.interfaces[0];
+
+        assert_eq!(manager.requests[0].since, 1);
+        assert!(manager.requests[0].is_destructor);
+        assert_eq!(manager.requests[1].since, 3);
+        assert!(!manager.requests[1].is_destructor);
+        assert_eq!(manager.events[0].since, 2);
+    }
+
+    #[test]
+    fn invalid_since() {
+        let xml = PROTOCOL.replace(r#"since="3""#, r#"since="three""#);
+
+        assert!(matches!(
+            parse(&xml),
+            Err(Error::Protocol { line: 11, message }) if message.contains("`three`"),
+        ));
+    }
+
+    #[test]
+    fn nullable_args() {
+        let protocol = parse(PROTOCOL).unwrap();
+        let manager = &protocol.interfaces[0];
+        let create = &manager.requests[1].args;
+
+        assert_eq!(create[0].kind, ArgKind::NewId);
+        assert_eq!(create[0].interface.as_deref(), Some("test_thing"));
+        assert!(!create[0].allow_null);
+
+        assert_eq!(create[1].kind, ArgKind::Object);
+        assert!(create[1].allow_null);
+
+        let bind = &manager.requests[2].args[0];
+        assert_eq!(bind.kind, ArgKind::NewId);
+        assert_eq!(bind.interface, None);
+        assert!(bind.allow_null);
+    }
+
+    #[test]
+    fn enums() {
+        let protocol = parse(PROTOCOL).unwrap();
+        let manager = &protocol.interfaces[0];
+
+        let mode = &manager.enums[0];
+        assert!(!mode.bitfield);
+        assert_eq!(mode.entries[0].value, 0);
+        assert_eq!(mode.entries[1].value, 16);
+        assert_eq!(
+            manager.requests[1].args[2].enumeration.as_deref(),
+            Some("mode")
+        );
+
+        let caps = &protocol.interfaces[1].enums[0];
+        assert!(caps.bitfield);
+        assert_eq!(
+            caps.entries.iter().map(|e| e.value).collect::<Vec<_>>(),
+            [1, 2]
+        );
+        assert_eq!(
+            manager.events[0].args[0].enumeration.as_deref(),
+            Some("test_thing.caps"),
+        );
+    }
+
+    #[test]
+    fn escaping() {
+        let protocol = parse(PROTOCOL).unwrap();
+        let manager = &protocol.interfaces[0];
+        let description = manager.description.as_ref().unwrap();
+
+        assert_eq!(description.summary, r#"a "test" manager"#);
+        assert_eq!(description.text.trim(), "Creates <things> & more.");
+        assert_eq!(manager.events[0].args[0].summary.as_deref(), Some("a & b"));
+        assert_eq!(
+            manager.enums[0].entries[1].summary.as_deref(),
+            Some("keep 'aspect'"),
+        );
+    }
+
+    #[test]
+    fn unknown_entity() {
+        let xml = PROTOCOL.replace("&amp; more", "&nbsp; more");
+
+        assert!(matches!(
+            parse(&xml),
+            Err(Error::Xml { message, .. }) if message.contains("&nbsp;"),
+        ));
+    }
+
+    #[test]
+    fn unknown_argument_type() {
+        let xml = PROTOCOL.replace(r#"type="uint" enum="mode""#, r#"type="float""#);
+
+        assert!(matches!(
+            parse(&xml),
+            Err(Error::Protocol { message, .. }) if message.contains("`float`"),
+        ));
+    }
+
+    #[test]
+    fn missing_version() {
+        let xml = PROTOCOL.replace(r#"name="test_thing" version="1""#, r#"name="test_thing""#);
+
+        assert!(matches!(
+            parse(&xml),
+            Err(Error::Protocol { line: 31, message }) if message.contains("`version`"),
+        ));
+    }
+}
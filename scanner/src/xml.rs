@@ -0,0 +1,266 @@
+use crate::Error;
+
+/// An XML element with its attributes and children.
+#[derive(Debug)]
+pub(crate) struct Element {
+    pub name: String,
+    pub attrs: Vec<(String, String)>,
+    pub children: Vec<Node>,
+    /// Line of the start tag, for error reporting.
+    pub line: usize,
+}
+
+#[derive(Debug)]
+pub(crate) enum Node {
+    Element(Element),
+    Text(String),
+}
+
+impl Element {
+    /// Returns the value of an attribute.
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns an iterator over child elements.
+    pub fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|node| match node {
+            Node::Element(element) => Some(element),
+            Node::Text(_) => None,
+        })
+    }
+
+    /// Returns the concatenated text content.
+    pub fn text(&self) -> String {
+        self.children
+            .iter()
+            .filter_map(|node| match node {
+                Node::Text(text) => Some(text.as_str()),
+                Node::Element(_) => None,
+            })
+            .collect()
+    }
+}
+
+/// Parses a document and returns its root element.
+///
+/// Only the subset of XML used by protocol files is supported: no DTD
+/// processing and no namespaces.
+pub(crate) fn parse(input: &str) -> Result<Element, Error> {
+    let mut parser = Parser { input, pos: 0 };
+
+    parser.skip_misc()?;
+    let root = parser.element()?;
+    parser.skip_misc()?;
+
+    if !parser.rest().is_empty() {
+        return Err(parser.error("unexpected content after the root element"));
+    }
+
+    Ok(root)
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn line(&self) -> usize {
+        self.input[..self.pos].matches('\n').count() + 1
+    }
+
+    fn error(&self, message: &str) -> Error {
+        Error::Xml {
+            line: self.line(),
+            message: message.into(),
+        }
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        match self.rest().starts_with(token) {
+            true => {
+                self.pos += token.len();
+                true
+            }
+            false => false,
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), Error> {
+        match self.eat(token) {
+            true => Ok(()),
+            false => Err(self.error(&format!("expected `{token}`"))),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Skips everything up to and including `end`.
+    fn skip_past(&mut self, end: &str) -> Result<&'a str, Error> {
+        let rest = self.rest();
+        let index = rest
+            .find(end)
+            .ok_or_else(|| self.error(&format!("unterminated construct, expected `{end}`")))?;
+
+        self.pos += index + end.len();
+        Ok(&rest[..index])
+    }
+
+    /// Skips whitespace, comments, processing instructions and doctype declarations.
+    fn skip_misc(&mut self) -> Result<(), Error> {
+        loop {
+            self.skip_whitespace();
+
+            if self.eat("<!--") {
+                self.skip_past("-->")?;
+            } else if self.eat("<?") {
+                self.skip_past("?>")?;
+            } else if self.eat("<!DOCTYPE") {
+                self.skip_past(">")?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn name(&mut self) -> Result<&'a str, Error> {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '-' | ':' | '.')))
+            .unwrap_or(rest.len());
+
+        if len == 0 {
+            return Err(self.error("expected a name"));
+        }
+
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+
+    fn element(&mut self) -> Result<Element, Error> {
+        let line = self.line();
+
+        self.expect("<")?;
+        let name = self.name()?.to_owned();
+
+        let mut attrs = Vec::new();
+
+        loop {
+            self.skip_whitespace();
+
+            if self.eat("/>") {
+                return Ok(Element {
+                    name,
+                    attrs,
+                    children: Vec::new(),
+                    line,
+                });
+            }
+
+            if self.eat(">") {
+                break;
+            }
+
+            let key = self.name()?.to_owned();
+            self.skip_whitespace();
+            self.expect("=")?;
+            self.skip_whitespace();
+
+            let quote = match self.rest().chars().next() {
+                Some(quote @ ('"' | '\'')) => quote,
+                _ => return Err(self.error("expected a quoted attribute value")),
+            };
+
+            self.pos += 1;
+            let raw = self.skip_past(&quote.to_string())?;
+            attrs.push((key, self.unescape(raw)?));
+        }
+
+        let mut children = Vec::new();
+
+        loop {
+            if self.eat("</") {
+                let end = self.name()?;
+
+                if end != name {
+                    return Err(self.error(&format!("expected `</{name}>`, found `</{end}>`")));
+                }
+
+                self.skip_whitespace();
+                self.expect(">")?;
+
+                return Ok(Element {
+                    name,
+                    attrs,
+                    children,
+                    line,
+                });
+            }
+
+            if self.eat("<!--") {
+                self.skip_past("-->")?;
+            } else if self.eat("<![CDATA[") {
+                let text = self.skip_past("]]>")?;
+                children.push(Node::Text(text.to_owned()));
+            } else if self.eat("<?") {
+                self.skip_past("?>")?;
+            } else if self.rest().starts_with('<') {
+                children.push(Node::Element(self.element()?));
+            } else if self.rest().is_empty() {
+                return Err(self.error(&format!("unterminated element `{name}`")));
+            } else {
+                let rest = self.rest();
+                let len = rest.find('<').unwrap_or(rest.len());
+                self.pos += len;
+                children.push(Node::Text(self.unescape(&rest[..len])?));
+            }
+        }
+    }
+
+    /// Replaces entity and character references.
+    fn unescape(&self, raw: &str) -> Result<String, Error> {
+        let mut out = String::with_capacity(raw.len());
+        let mut rest = raw;
+
+        while let Some(index) = rest.find('&') {
+            out.push_str(&rest[..index]);
+            rest = &rest[index + 1..];
+
+            let end = rest
+                .find(';')
+                .ok_or_else(|| self.error("unterminated entity reference"))?;
+
+            let c = match &rest[..end] {
+                "lt" => '<',
+                "gt" => '>',
+                "amp" => '&',
+                "quot" => '"',
+                "apos" => '\'',
+                entity => entity
+                    .strip_prefix("#x")
+                    .map(|hex| u32::from_str_radix(hex, 16))
+                    .or_else(|| entity.strip_prefix('#').map(str::parse))
+                    .and_then(Result::ok)
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| self.error(&format!("unknown entity `&{entity};`")))?,
+            };
+
+            out.push(c);
+            rest = &rest[end + 1..];
+        }
+
+        out.push_str(rest);
+        Ok(out)
+    }
+}
@@ -0,0 +1,507 @@
+use crate::{
+    env,
+    object_map::{Object, ObjectMap, ObjectMapError, Side},
+    protocol::{MessageGroup, wayland::wl_display},
+    wire::{self, Argument, Interface, Message, ObjectId, WireError},
+};
+use rustix::net::{AddressFamily, SocketType, sockopt};
+use std::{io, os::fd::OwnedFd, path::PathBuf};
+use thiserror::Error;
+use tokio::net::UnixStream;
+
+/// Errors returned by [`Connection`].
+#[derive(Debug, Error)]
+pub enum ConnectionError {
+    #[error("XDG_RUNTIME_DIR not set or invalid")]
+    RuntimeDirInvalid,
+    #[error("WAYLAND_SOCKET is not a connected Unix stream socket")]
+    InvalidSocketFd,
+    #[error("could not connect to socket: {0}")]
+    Connect(#[source] io::Error),
+    #[error("message for unknown object {0}")]
+    UnknownObject(ObjectId),
+    #[error("object {0} is not a {1}")]
+    InterfaceMismatch(ObjectId, &'static str),
+    #[error(transparent)]
+    ObjectMap(#[from] ObjectMapError),
+    #[error(transparent)]
+    Wire(#[from] WireError),
+}
+
+/// Client connection to a Wayland compositor.
+///
+/// Tracks the objects of the client so that events can be decoded, and
+/// handles `wl_display.delete_id` by itself.
+#[derive(Debug)]
+pub struct Connection {
+    connection: wire::Connection,
+    objects: ObjectMap,
+}
+
+impl Connection {
+    /// Connects to the compositor given by the environment.
+    ///
+    /// `WAYLAND_SOCKET` takes precedence, it can only be used once per process
+    /// and is left in the environment. Otherwise `WAYLAND_DISPLAY` is used,
+    /// defaulting to `wayland-0`.
+    pub async fn connect() -> Result<Self, ConnectionError> {
+        if let Ok(fd) = std::env::var("WAYLAND_SOCKET") {
+            let fd = fd
+                .parse()
+                .ok()
+                .and_then(env::take_inherited_fd)
+                .ok_or(ConnectionError::InvalidSocketFd)?;
+
+            return Self::from_owned_fd(fd);
+        }
+
+        let name = std::env::var("WAYLAND_DISPLAY").unwrap_or_else(|_| "wayland-0".into());
+
+        Self::connect_to(&name).await
+    }
+
+    /// Connects to a socket by name, relative to `XDG_RUNTIME_DIR` unless absolute.
+    pub async fn connect_to(name: &str) -> Result<Self, ConnectionError> {
+        let mut path = PathBuf::from(name);
+
+        if !path.is_absolute() {
+            let dir = env::xdg_runtime_dir().ok_or(ConnectionError::RuntimeDirInvalid)?;
+            path = dir.join(path);
+        }
+
+        let stream = UnixStream::connect(path)
+            .await
+            .map_err(ConnectionError::Connect)?;
+
+        Ok(Self::from_stream(stream))
+    }
+
+    /// Wraps a connected socket, such as one received through `WAYLAND_SOCKET`.
+    pub fn from_owned_fd(fd: OwnedFd) -> Result<Self, ConnectionError> {
+        let valid = sockopt::socket_domain(&fd).is_ok_and(|domain| domain == AddressFamily::UNIX)
+            && sockopt::socket_type(&fd).is_ok_and(|kind| kind == SocketType::STREAM);
+
+        if !valid {
+            return Err(ConnectionError::InvalidSocketFd);
+        }
+
+        let stream = std::os::unix::net::UnixStream::from(fd);
+        stream
+            .set_nonblocking(true)
+            .map_err(ConnectionError::Connect)?;
+
+        let stream = UnixStream::from_std(stream).map_err(ConnectionError::Connect)?;
+
+        Ok(Self::from_stream(stream))
+    }
+
+    /// Wraps a connected stream.
+    pub fn from_stream(stream: UnixStream) -> Self {
+        let mut objects = ObjectMap::new(Side::Client);
+
+        let display = Object {
+            interface: &wl_display::INTERFACE,
+            version: 1,
+        };
+
+        objects
+            .insert_new(display)
+            .expect("object map of a new connection is empty");
+
+        Connection {
+            connection: wire::Connection::new(stream),
+            objects,
+        }
+    }
+
+    /// Returns the objects of the client.
+    pub fn objects(&self) -> &ObjectMap {
+        &self.objects
+    }
+
+    /// Allocates an id for an object created by a request.
+    pub fn create_object(
+        &mut self,
+        interface: &'static Interface,
+        version: u32,
+    ) -> Result<ObjectId, ConnectionError> {
+        Ok(self.objects.insert_new(Object { interface, version })?)
+    }
+
+    /// Queues a request to `object`.
+    ///
+    /// Ids for `new_id` arguments come from [`Connection::create_object`].
+    /// Destructors turn `object` into a zombie until the server deletes it.
+    pub fn queue<R: MessageGroup>(
+        &mut self,
+        object: ObjectId,
+        request: R,
+    ) -> Result<(), ConnectionError> {
+        let target = self
+            .objects
+            .get(object)
+            .ok_or(ConnectionError::UnknownObject(object))?;
+
+        if target.interface != R::interface() {
+            return Err(ConnectionError::InterfaceMismatch(
+                object,
+                R::interface().name,
+            ));
+        }
+
+        let destructor = request.desc().is_destructor;
+        self.connection.queue(request.into_message(object))?;
+
+        if destructor {
+            self.objects.remove(object);
+        }
+
+        Ok(())
+    }
+
+    /// Writes out all queued requests.
+    pub async fn flush(&mut self) -> Result<(), ConnectionError> {
+        Ok(self.connection.flush().await?)
+    }
+
+    /// Queues a request and flushes it.
+    pub async fn send<R: MessageGroup>(
+        &mut self,
+        object: ObjectId,
+        request: R,
+    ) -> Result<(), ConnectionError> {
+        self.queue(object, request)?;
+        self.flush().await
+    }
+
+    /// Receives the next event.
+    ///
+    /// Objects created by the event already exist. Events to destroyed
+    /// objects are discarded.
+    pub async fn recv(&mut self) -> Result<Message, ConnectionError> {
+        loop {
+            let raw = self.connection.read_raw().await?;
+            let id = raw.header.object;
+
+            let (object, live) = match self.objects.get(id) {
+                Some(object) => (*object, true),
+                None => match self.objects.zombie(id) {
+                    Some(object) => (*object, false),
+                    None => return Err(ConnectionError::UnknownObject(id)),
+                },
+            };
+
+            let desc = object
+                .interface
+                .events
+                .get(raw.header.opcode as usize)
+                .ok_or(WireError::UnknownMessage {
+                    object: id,
+                    opcode: raw.header.opcode,
+                })?;
+
+            // Decoded even when discarded, to consume its file descriptors
+            let message = raw.decode(desc.signature, self.connection.fds_mut())?;
+
+            if let Some(child) = desc.child_interface {
+                for arg in &message.args {
+                    if let Argument::NewId(new) = arg {
+                        let new_object = Object {
+                            interface: child,
+                            version: object.version,
+                        };
+
+                        self.objects.insert_at(*new, new_object)?;
+                    }
+                }
+            }
+
+            if !live {
+                continue;
+            }
+
+            if desc.is_destructor {
+                self.objects.remove(id);
+            }
+
+            if object.interface == &wl_display::INTERFACE
+                && desc.name == "delete_id"
+                && let Some(Argument::Uint(deleted)) = message.args.first()
+                && let Some(deleted) = ObjectId::new(*deleted)
+            {
+                // Ids that are not awaiting deletion are ignored, like libwayland does
+                let _ = self.objects.delete_id(deleted);
+            }
+
+            return Ok(message);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        protocol::wayland::{wl_callback, wl_compositor, wl_output, wl_registry, wl_surface},
+        server::{
+            client::{ClientId, Resource},
+            display::Display,
+            socket::{WaylandSocket, socket_pair},
+        },
+        wire::MAX_QUEUED_FDS,
+    };
+    use std::{fs::File, time::Duration};
+
+    struct Fixture {
+        display: Display<()>,
+        client_id: ClientId,
+        client: Connection,
+    }
+
+    impl Fixture {
+        fn new(name: &str) -> Self {
+            let name = format!("alow-test-{}-{name}", std::process::id());
+            let socket =
+                WaylandSocket::with_name_in_dir(&std::env::temp_dir(), name.into()).unwrap();
+            let mut display = Display::new(socket);
+
+            display.create_global(&wl_output::INTERFACE, 2, |_, _, _| Ok(()));
+
+            let (server, client) = socket_pair().unwrap();
+            let client_id = display.handle_mut().insert_client(server);
+            let client = Connection::from_owned_fd(client.into_fd()).unwrap();
+
+            Fixture {
+                display,
+                client_id,
+                client,
+            }
+        }
+
+        /// Dispatches requests until the client receives an event.
+        async fn recv(&mut self) -> Message {
+            let state = &mut ();
+
+            loop {
+                tokio::select! {
+                    message = self.client.recv() => return message.unwrap(),
+                    _ = self.display.dispatch(state) => {}
+                }
+            }
+        }
+
+        /// Dispatches requests until the client wrote all of its own.
+        async fn flush(&mut self) -> Result<(), ConnectionError> {
+            let state = &mut ();
+
+            loop {
+                tokio::select! {
+                    result = self.client.flush() => return result,
+                    _ = self.display.dispatch(state) => {}
+                }
+            }
+        }
+
+        async fn sync(&mut self) -> ObjectId {
+            let callback = self
+                .client
+                .create_object(&wl_callback::INTERFACE, 1)
+                .unwrap();
+
+            self.client
+                .send(ObjectId::DISPLAY, wl_display::Request::Sync { callback })
+                .await
+                .unwrap();
+
+            callback
+        }
+    }
+
+    #[tokio::test]
+    async fn sync() {
+        let mut fixture = Fixture::new("sync");
+        let callback = fixture.sync().await;
+
+        let done = fixture.recv().await;
+        assert_eq!(done.object, callback);
+        assert!(matches!(
+            wl_callback::Event::from_message(done),
+            Ok(wl_callback::Event::Done { .. }),
+        ));
+        assert!(fixture.client.objects().zombie(callback).is_some());
+
+        let delete = fixture.recv().await;
+        assert!(matches!(
+            wl_display::Event::from_message(delete),
+            Ok(wl_display::Event::DeleteId { id }) if id == callback.get(),
+        ));
+        assert!(fixture.client.objects().zombie(callback).is_none());
+    }
+
+    #[tokio::test]
+    async fn registry() {
+        let mut fixture = Fixture::new("registry");
+        let registry = fixture
+            .client
+            .create_object(&wl_registry::INTERFACE, 1)
+            .unwrap();
+
+        fixture
+            .client
+            .send(
+                ObjectId::DISPLAY,
+                wl_display::Request::GetRegistry { registry },
+            )
+            .await
+            .unwrap();
+
+        let global = fixture.recv().await;
+        assert_eq!(global.object, registry);
+
+        let Ok(wl_registry::Event::Global {
+            name,
+            interface,
+            version,
+        }) = wl_registry::Event::from_message(global)
+        else {
+            panic!("expected wl_registry.global");
+        };
+
+        assert_eq!(interface, "wl_output");
+        assert_eq!(version, 2);
+
+        let output = fixture
+            .client
+            .create_object(&wl_output::INTERFACE, 2)
+            .unwrap();
+
+        let bind = wl_registry::Request::Bind {
+            name,
+            interface,
+            version,
+            id: output,
+        };
+
+        fixture.client.queue(registry, bind).unwrap();
+
+        // The round trip completes without a protocol error
+        let callback = fixture.sync().await;
+        assert_eq!(fixture.recv().await.object, callback);
+
+        let resource = Resource::new(fixture.client_id, output);
+        assert_eq!(fixture.display.handle().version(resource), 2);
+    }
+
+    #[tokio::test]
+    async fn delete_live_id() {
+        let mut fixture = Fixture::new("delete-live-id");
+        let registry = fixture
+            .client
+            .create_object(&wl_registry::INTERFACE, 1)
+            .unwrap();
+
+        fixture
+            .client
+            .send(
+                ObjectId::DISPLAY,
+                wl_display::Request::GetRegistry { registry },
+            )
+            .await
+            .unwrap();
+
+        fixture.recv().await;
+
+        // The server destroys an object the client still considers live
+        let resource = Resource::new(fixture.client_id, registry);
+        fixture.display.handle_mut().destroy_object(resource);
+        fixture.display.handle_mut().flush(fixture.client_id);
+
+        let delete = fixture.recv().await;
+        assert!(matches!(
+            wl_display::Event::from_message(delete),
+            Ok(wl_display::Event::DeleteId { id }) if id == registry.get(),
+        ));
+        assert!(fixture.client.objects().get(registry).is_some());
+    }
+
+    #[tokio::test]
+    async fn unconsumed_fds() {
+        let mut fixture = Fixture::new("unconsumed-fds");
+        fixture
+            .display
+            .create_global(&wl_compositor::INTERFACE, 1, |_, _, _| Ok(()));
+
+        let registry = fixture
+            .client
+            .create_object(&wl_registry::INTERFACE, 1)
+            .unwrap();
+
+        fixture
+            .client
+            .send(
+                ObjectId::DISPLAY,
+                wl_display::Request::GetRegistry { registry },
+            )
+            .await
+            .unwrap();
+
+        let name = loop {
+            if let Ok(wl_registry::Event::Global {
+                name, interface, ..
+            }) = wl_registry::Event::from_message(fixture.recv().await)
+                && interface == "wl_compositor"
+            {
+                break name;
+            }
+        };
+
+        let compositor = fixture
+            .client
+            .create_object(&wl_compositor::INTERFACE, 1)
+            .unwrap();
+        let surface = fixture
+            .client
+            .create_object(&wl_surface::INTERFACE, 1)
+            .unwrap();
+
+        let bind = wl_registry::Request::Bind {
+            name,
+            interface: "wl_compositor".into(),
+            version: 1,
+            id: compositor,
+        };
+
+        fixture.client.queue(registry, bind).unwrap();
+        fixture
+            .client
+            .queue(
+                compositor,
+                wl_compositor::Request::CreateSurface { id: surface },
+            )
+            .unwrap();
+
+        // `commit` takes no fd, so the server never consumes these
+        for _ in 0..=MAX_QUEUED_FDS {
+            let fd = File::open("/dev/null").unwrap().into();
+            let mut commit = wl_surface::Request::Commit.into_message(surface);
+            commit.args.push(Argument::Fd(fd));
+
+            fixture.client.connection.queue(commit).unwrap();
+
+            // The server may hang up before reading everything
+            if fixture.flush().await.is_err() {
+                break;
+            }
+        }
+
+        let error = tokio::time::timeout(Duration::from_secs(5), fixture.recv())
+            .await
+            .expect("client was not disconnected");
+
+        assert!(matches!(
+            wl_display::Event::from_message(error),
+            Ok(wl_display::Event::Error { object_id, .. }) if object_id == surface,
+        ));
+        assert!(fixture.display.handle().client(fixture.client_id).is_none());
+    }
+}
@@ -0,0 +1,39 @@
+use rustix::io as rio;
+use std::{
+    env,
+    os::fd::{FromRawFd, OwnedFd, RawFd},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+/// Inherited file descriptors already taken by this process.
+static TAKEN_FDS: Mutex<Vec<RawFd>> = Mutex::new(Vec::new());
+
+/// Returns the `XDG_RUNTIME_DIR` directory, if set to an absolute path.
+pub(crate) fn xdg_runtime_dir() -> Option<PathBuf> {
+    let dir = env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from)?;
+
+    dir.is_absolute().then_some(dir)
+}
+
+/// Takes ownership of a file descriptor inherited through the environment.
+///
+/// Returns `None` if it is not open or was already taken. The descriptor is
+/// made close-on-exec so that it is not leaked to children.
+pub(crate) fn take_inherited_fd(fd: RawFd) -> Option<OwnedFd> {
+    let mut taken = TAKEN_FDS.lock().unwrap_or_else(|err| err.into_inner());
+
+    // SAFETY: querying the flags of an arbitrary descriptor has no side effects
+    if fd < 0 || taken.contains(&fd) || unsafe { libc::fcntl(fd, libc::F_GETFD) } < 0 {
+        return None;
+    }
+
+    taken.push(fd);
+
+    // SAFETY: the descriptor is open, was handed to this process, and the
+    // check above ensures it is not owned twice
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    rio::fcntl_setfd(&fd, rio::FdFlags::CLOEXEC).ok()?;
+
+    Some(fd)
+}
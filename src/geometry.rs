@@ -0,0 +1,128 @@
+use std::ops::{Add, Sub};
+
+/// A position, in surface-local or global coordinates.
+///
+/// Arithmetic saturates, coordinates sent by clients may be arbitrarily large.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Point {
+    /// Creates a point.
+    pub const fn new(x: i32, y: i32) -> Self {
+        Point { x, y }
+    }
+}
+
+impl Add for Point {
+    type Output = Point;
+
+    fn add(self, other: Point) -> Point {
+        Point::new(
+            self.x.saturating_add(other.x),
+            self.y.saturating_add(other.y),
+        )
+    }
+}
+
+impl Sub for Point {
+    type Output = Point;
+
+    fn sub(self, other: Point) -> Point {
+        Point::new(
+            self.x.saturating_sub(other.x),
+            self.y.saturating_sub(other.y),
+        )
+    }
+}
+
+/// Dimensions of a rectangle.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Size {
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Size {
+    /// Creates a size.
+    pub const fn new(width: i32, height: i32) -> Self {
+        Size { width, height }
+    }
+
+    /// Returns `true` if the size covers no area.
+    pub fn is_empty(self) -> bool {
+        self.width <= 0 || self.height <= 0
+    }
+}
+
+/// An axis-aligned rectangle.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Rectangle {
+    pub loc: Point,
+    pub size: Size,
+}
+
+impl Rectangle {
+    /// Creates a rectangle from its top-left corner and size.
+    pub const fn new(x: i32, y: i32, width: i32, height: i32) -> Self {
+        Rectangle {
+            loc: Point::new(x, y),
+            size: Size::new(width, height),
+        }
+    }
+
+    /// Creates a rectangle from its edges, the right and bottom ones excluded.
+    pub fn from_edges(left: i32, top: i32, right: i32, bottom: i32) -> Self {
+        Rectangle::new(
+            left,
+            top,
+            right.saturating_sub(left),
+            bottom.saturating_sub(top),
+        )
+    }
+
+    /// Returns the first column to the right of the rectangle.
+    pub fn right(self) -> i32 {
+        self.loc.x.saturating_add(self.size.width)
+    }
+
+    /// Returns the first row below the rectangle.
+    pub fn bottom(self) -> i32 {
+        self.loc.y.saturating_add(self.size.height)
+    }
+
+    /// Returns `true` if the rectangle covers no area.
+    pub fn is_empty(self) -> bool {
+        self.size.is_empty()
+    }
+
+    /// Returns the area covered by both rectangles, if any.
+    pub fn intersection(self, other: Rectangle) -> Option<Rectangle> {
+        let rect = Rectangle::from_edges(
+            self.loc.x.max(other.loc.x),
+            self.loc.y.max(other.loc.y),
+            self.right().min(other.right()),
+            self.bottom().min(other.bottom()),
+        );
+
+        (!rect.is_empty()).then_some(rect)
+    }
+
+    /// Returns `true` if `point` lies within the rectangle.
+    pub fn contains(self, point: Point) -> bool {
+        point.x >= self.loc.x
+            && point.y >= self.loc.y
+            && point.x < self.right()
+            && point.y < self.bottom()
+    }
+
+    /// Returns the rectangle moved by `offset`.
+    pub fn translate(self, offset: Point) -> Self {
+        Rectangle {
+            loc: self.loc + offset,
+            size: self.size,
+        }
+    }
+}
@@ -1 +1,9 @@
+pub mod client;
+mod env;
+pub mod geometry;
+pub mod object_map;
+pub mod protocol;
 pub mod server;
+#[cfg(test)]
+mod testing;
+pub mod wire;
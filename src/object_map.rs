@@ -0,0 +1,367 @@
+use crate::wire::{Interface, ObjectId};
+use thiserror::Error;
+
+/// First id of the range allocated by the server.
+pub const SERVER_ID_START: u32 = 0xFF00_0000;
+
+/// Last id of the range allocated by the client.
+pub const CLIENT_ID_END: u32 = SERVER_ID_START - 1;
+
+/// Errors returned by [`ObjectMap`].
+#[derive(Debug, Error)]
+pub enum ObjectMapError {
+    #[error("object id {0} is outside the peer's range")]
+    OutOfRange(ObjectId),
+    #[error("object id {0} is not the next available id")]
+    NotSequential(ObjectId),
+    #[error("object id {0} is already in use")]
+    InUse(ObjectId),
+    #[error("object id {0} is not awaiting deletion")]
+    NotZombie(ObjectId),
+    #[error("object id range exhausted")]
+    Exhausted,
+}
+
+/// Side of a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Server,
+    Client,
+}
+
+impl Side {
+    /// Returns the side that allocates the given id.
+    pub fn allocating(id: ObjectId) -> Side {
+        match id.get() >= SERVER_ID_START {
+            true => Side::Server,
+            false => Side::Client,
+        }
+    }
+}
+
+/// A live protocol object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Object {
+    pub interface: &'static Interface,
+    pub version: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Entry {
+    Vacant,
+    Live(Object),
+    /// Destroyed locally, the id stays reserved until the peer acknowledges it.
+    Zombie(Object),
+}
+
+/// Map of the protocol objects of one connection.
+///
+/// Client-allocated ids run from 1 to [`CLIENT_ID_END`], server-allocated ids
+/// start at [`SERVER_ID_START`]. Peer-allocated ids must be used in order: a
+/// new id is either a free slot or the one just past the highest.
+#[derive(Debug)]
+pub struct ObjectMap {
+    side: Side,
+    client: Vec<Entry>,
+    server: Vec<Entry>,
+}
+
+impl ObjectMap {
+    /// Creates an empty map for the given side of the connection.
+    pub fn new(side: Side) -> Self {
+        ObjectMap {
+            side,
+            client: Vec::new(),
+            server: Vec::new(),
+        }
+    }
+
+    /// Returns the side this map belongs to.
+    pub fn side(&self) -> Side {
+        self.side
+    }
+
+    /// Returns the live object with the given id.
+    pub fn get(&self, id: ObjectId) -> Option<&Object> {
+        match self.entry(id) {
+            Some(Entry::Live(object)) => Some(object),
+            _ => None,
+        }
+    }
+
+    /// Returns the destroyed object still awaiting `wl_display.delete_id`.
+    ///
+    /// Messages from the peer may still arrive for it and must be decoded and dropped.
+    pub fn zombie(&self, id: ObjectId) -> Option<&Object> {
+        match self.entry(id) {
+            Some(Entry::Zombie(object)) => Some(object),
+            _ => None,
+        }
+    }
+
+    /// Inserts an object at an id allocated by the peer.
+    pub fn insert_at(&mut self, id: ObjectId, object: Object) -> Result<(), ObjectMapError> {
+        if Side::allocating(id) == self.side {
+            return Err(ObjectMapError::OutOfRange(id));
+        }
+
+        let (entries, index) = self.slot(id);
+        let len = entries.len();
+
+        match entries.get_mut(index) {
+            Some(entry @ Entry::Vacant) => *entry = Entry::Live(object),
+            Some(_) => return Err(ObjectMapError::InUse(id)),
+            None if index == len => entries.push(Entry::Live(object)),
+            None => return Err(ObjectMapError::NotSequential(id)),
+        }
+
+        Ok(())
+    }
+
+    /// Inserts an object at a newly allocated id from the local range.
+    pub fn insert_new(&mut self, object: Object) -> Result<ObjectId, ObjectMapError> {
+        let (entries, base, end) = match self.side {
+            Side::Server => (&mut self.server, SERVER_ID_START, u32::MAX),
+            Side::Client => (&mut self.client, 1, CLIENT_ID_END),
+        };
+
+        // Reuse the lowest free slot
+        let index = match entries
+            .iter()
+            .position(|entry| matches!(entry, Entry::Vacant))
+        {
+            Some(index) => index,
+            None => entries.len(),
+        };
+
+        let id = u32::try_from(index)
+            .ok()
+            .and_then(|index| base.checked_add(index))
+            .filter(|id| *id <= end)
+            .and_then(ObjectId::new)
+            .ok_or(ObjectMapError::Exhausted)?;
+
+        match entries.get_mut(index) {
+            Some(entry) => *entry = Entry::Live(object),
+            None => entries.push(Entry::Live(object)),
+        }
+
+        Ok(id)
+    }
+
+    /// Removes a live object.
+    ///
+    /// On the client, locally allocated ids become zombies until the server sends
+    /// `wl_display.delete_id`. On the server, the id is free again and a client
+    /// allocated id must be acknowledged by sending `wl_display.delete_id`.
+    pub fn remove(&mut self, id: ObjectId) -> Option<Object> {
+        let local = Side::allocating(id) == self.side;
+        let zombie = self.side == Side::Client && local;
+        let (entries, index) = self.slot(id);
+
+        let entry = entries.get_mut(index)?;
+        let Entry::Live(object) = *entry else {
+            return None;
+        };
+
+        *entry = match zombie {
+            true => Entry::Zombie(object),
+            false => Entry::Vacant,
+        };
+
+        // Keep the table compact, peer ranges must keep their length so that
+        // new ids are checked against the highest id ever used
+        while local && let Some(Entry::Vacant) = entries.last() {
+            entries.pop();
+        }
+
+        Some(object)
+    }
+
+    /// Handles a `wl_display.delete_id` acknowledgement, freeing a zombie id.
+    pub fn delete_id(&mut self, id: ObjectId) -> Result<(), ObjectMapError> {
+        let (entries, index) = self.slot(id);
+
+        match entries.get_mut(index) {
+            Some(entry @ Entry::Zombie(_)) => *entry = Entry::Vacant,
+            _ => return Err(ObjectMapError::NotZombie(id)),
+        }
+
+        while let Some(Entry::Vacant) = entries.last() {
+            entries.pop();
+        }
+
+        Ok(())
+    }
+
+    /// Returns an iterator over live objects.
+    pub fn iter(&self) -> impl Iterator<Item = (ObjectId, &Object)> {
+        let client = self.client.iter().zip(1..);
+        let server = self.server.iter().zip(SERVER_ID_START..);
+
+        client.chain(server).filter_map(|(entry, id)| match entry {
+            Entry::Live(object) => Some((ObjectId::new(id)?, object)),
+            _ => None,
+        })
+    }
+
+    /// Removes all objects, returning the live ones.
+    pub fn clear(&mut self) -> Vec<(ObjectId, Object)> {
+        let live = self.iter().map(|(id, object)| (id, *object)).collect();
+        self.client.clear();
+        self.server.clear();
+        live
+    }
+
+    fn entry(&self, id: ObjectId) -> Option<&Entry> {
+        match Side::allocating(id) {
+            Side::Server => self.server.get((id.get() - SERVER_ID_START) as usize),
+            Side::Client => self.client.get((id.get() - 1) as usize),
+        }
+    }
+
+    fn slot(&mut self, id: ObjectId) -> (&mut Vec<Entry>, usize) {
+        match Side::allocating(id) {
+            Side::Server => (&mut self.server, (id.get() - SERVER_ID_START) as usize),
+            Side::Client => (&mut self.client, (id.get() - 1) as usize),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::wayland::{wl_callback, wl_surface};
+
+    const CALLBACK: Object = Object {
+        interface: &wl_callback::INTERFACE,
+        version: 1,
+    };
+
+    const SURFACE: Object = Object {
+        interface: &wl_surface::INTERFACE,
+        version: 6,
+    };
+
+    fn id(id: u32) -> ObjectId {
+        ObjectId::new(id).unwrap()
+    }
+
+    #[test]
+    fn out_of_range() {
+        let mut server = ObjectMap::new(Side::Server);
+        assert!(matches!(
+            server.insert_at(id(SERVER_ID_START), SURFACE),
+            Err(ObjectMapError::OutOfRange(_)),
+        ));
+        assert!(matches!(
+            server.insert_at(id(u32::MAX), SURFACE),
+            Err(ObjectMapError::OutOfRange(_)),
+        ));
+
+        let mut client = ObjectMap::new(Side::Client);
+        assert!(matches!(
+            client.insert_at(id(1), SURFACE),
+            Err(ObjectMapError::OutOfRange(_)),
+        ));
+        assert!(matches!(
+            client.insert_at(id(CLIENT_ID_END), SURFACE),
+            Err(ObjectMapError::OutOfRange(_)),
+        ));
+    }
+
+    #[test]
+    fn not_sequential() {
+        let mut map = ObjectMap::new(Side::Server);
+        map.insert_at(id(1), SURFACE).unwrap();
+
+        assert!(matches!(
+            map.insert_at(id(3), SURFACE),
+            Err(ObjectMapError::NotSequential(_)),
+        ));
+        assert!(matches!(
+            map.insert_at(id(1), SURFACE),
+            Err(ObjectMapError::InUse(_)),
+        ));
+
+        map.insert_at(id(2), SURFACE).unwrap();
+        map.insert_at(id(3), SURFACE).unwrap();
+    }
+
+    #[test]
+    fn reuse_freed_peer_id() {
+        let mut map = ObjectMap::new(Side::Server);
+        map.insert_at(id(1), SURFACE).unwrap();
+        map.insert_at(id(2), SURFACE).unwrap();
+
+        // The server frees client ids right away, the range keeps its length
+        assert_eq!(map.remove(id(2)), Some(SURFACE));
+        assert!(matches!(
+            map.insert_at(id(4), CALLBACK),
+            Err(ObjectMapError::NotSequential(_)),
+        ));
+
+        map.insert_at(id(2), CALLBACK).unwrap();
+        map.insert_at(id(3), CALLBACK).unwrap();
+        assert_eq!(map.get(id(2)), Some(&CALLBACK));
+    }
+
+    #[test]
+    fn server_ids() {
+        let mut map = ObjectMap::new(Side::Server);
+
+        assert_eq!(map.insert_new(CALLBACK).unwrap(), id(SERVER_ID_START));
+        assert_eq!(map.insert_new(CALLBACK).unwrap(), id(SERVER_ID_START + 1));
+
+        map.remove(id(SERVER_ID_START));
+        assert_eq!(map.zombie(id(SERVER_ID_START)), None);
+        assert_eq!(map.insert_new(SURFACE).unwrap(), id(SERVER_ID_START));
+    }
+
+    #[test]
+    fn client_zombie() {
+        let mut map = ObjectMap::new(Side::Client);
+        let surface = map.insert_new(SURFACE).unwrap();
+        assert_eq!(surface, id(1));
+
+        assert_eq!(map.remove(surface), Some(SURFACE));
+        assert_eq!(map.get(surface), None);
+        assert_eq!(map.zombie(surface), Some(&SURFACE));
+
+        // The id is not reused until the server acknowledges it
+        assert_eq!(map.insert_new(CALLBACK).unwrap(), id(2));
+        assert_eq!(map.remove(surface), None);
+        assert_eq!(map.zombie(surface), Some(&SURFACE));
+
+        map.delete_id(surface).unwrap();
+        assert_eq!(map.zombie(surface), None);
+        assert_eq!(map.insert_new(CALLBACK).unwrap(), surface);
+    }
+
+    #[test]
+    fn delete_id_not_zombie() {
+        let mut map = ObjectMap::new(Side::Client);
+        let callback = map.insert_new(CALLBACK).unwrap();
+
+        assert!(matches!(
+            map.delete_id(callback),
+            Err(ObjectMapError::NotZombie(_)),
+        ));
+        assert!(matches!(
+            map.delete_id(id(5)),
+            Err(ObjectMapError::NotZombie(_)),
+        ));
+        assert_eq!(map.get(callback), Some(&CALLBACK));
+    }
+
+    #[test]
+    fn clear() {
+        let mut map = ObjectMap::new(Side::Client);
+        let surface = map.insert_new(SURFACE).unwrap();
+        let callback = map.insert_new(CALLBACK).unwrap();
+        map.remove(callback);
+
+        assert_eq!(map.clear(), [(surface, SURFACE)]);
+        assert_eq!(map.iter().count(), 0);
+    }
+}
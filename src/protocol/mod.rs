@@ -0,0 +1,162 @@
+use crate::wire::{Argument, Fixed, Interface, Message, MessageDesc, ObjectId, WireError};
+use std::{os::fd::OwnedFd, vec};
+
+#[doc(hidden)]
+pub use bitflags;
+
+include!(concat!(env!("OUT_DIR"), "/protocol.rs"));
+
+/// Typed requests or events of one interface.
+///
+/// Implemented by the generated `Request` and `Event` enums.
+pub trait MessageGroup: Sized {
+    /// Returns the interface the messages belong to.
+    fn interface() -> &'static Interface;
+
+    /// Returns the opcode of this message.
+    fn opcode(&self) -> u16;
+
+    /// Returns the static description of this message.
+    fn desc(&self) -> &'static MessageDesc;
+
+    /// Returns the version this message was introduced in.
+    fn since(&self) -> u32 {
+        self.desc().since
+    }
+
+    /// Converts a decoded message.
+    fn from_message(message: Message) -> Result<Self, WireError>;
+
+    /// Converts into a message sent to or from `object`.
+    fn into_message(self, object: ObjectId) -> Message;
+}
+
+/// Value of an enum argument, which may be unknown to this side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WEnum<T> {
+    Value(T),
+    Unknown(u32),
+}
+
+impl<T: TryFrom<u32>> WEnum<T> {
+    /// Interprets a raw protocol value.
+    pub fn from_raw(raw: u32) -> Self {
+        match T::try_from(raw) {
+            Ok(value) => WEnum::Value(value),
+            Err(_) => WEnum::Unknown(raw),
+        }
+    }
+}
+
+impl<T: Into<u32>> WEnum<T> {
+    /// Returns the raw protocol value.
+    pub fn into_raw(self) -> u32 {
+        match self {
+            WEnum::Value(value) => value.into(),
+            WEnum::Unknown(raw) => raw,
+        }
+    }
+}
+
+impl<T> WEnum<T> {
+    /// Returns the known value, or the raw value if unknown.
+    pub fn into_result(self) -> Result<T, u32> {
+        match self {
+            WEnum::Value(value) => Ok(value),
+            WEnum::Unknown(raw) => Err(raw),
+        }
+    }
+}
+
+impl<T> From<T> for WEnum<T> {
+    fn from(value: T) -> Self {
+        WEnum::Value(value)
+    }
+}
+
+/// Typed access to decoded arguments, used by generated code.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct Args(vec::IntoIter<Argument>);
+
+impl Args {
+    pub fn new(args: Vec<Argument>) -> Self {
+        Args(args.into_iter())
+    }
+
+    fn next(&mut self) -> Result<Argument, WireError> {
+        self.0.next().ok_or(WireError::MissingArgument)
+    }
+
+    pub fn int(&mut self) -> Result<i32, WireError> {
+        match self.next()? {
+            Argument::Int(value) => Ok(value),
+            _ => Err(WireError::UnexpectedArgument),
+        }
+    }
+
+    pub fn uint(&mut self) -> Result<u32, WireError> {
+        match self.next()? {
+            Argument::Uint(value) => Ok(value),
+            _ => Err(WireError::UnexpectedArgument),
+        }
+    }
+
+    pub fn fixed(&mut self) -> Result<Fixed, WireError> {
+        match self.next()? {
+            Argument::Fixed(value) => Ok(value),
+            _ => Err(WireError::UnexpectedArgument),
+        }
+    }
+
+    pub fn opt_string(&mut self) -> Result<Option<String>, WireError> {
+        match self.next()? {
+            Argument::String(value) => Ok(value),
+            _ => Err(WireError::UnexpectedArgument),
+        }
+    }
+
+    pub fn string(&mut self) -> Result<String, WireError> {
+        self.opt_string()?.ok_or(WireError::NullArgument)
+    }
+
+    pub fn opt_object(&mut self) -> Result<Option<ObjectId>, WireError> {
+        match self.next()? {
+            Argument::Object(value) => Ok(value),
+            _ => Err(WireError::UnexpectedArgument),
+        }
+    }
+
+    pub fn object(&mut self) -> Result<ObjectId, WireError> {
+        self.opt_object()?.ok_or(WireError::NullArgument)
+    }
+
+    pub fn new_id(&mut self) -> Result<ObjectId, WireError> {
+        match self.next()? {
+            Argument::NewId(value) => Ok(value),
+            _ => Err(WireError::UnexpectedArgument),
+        }
+    }
+
+    pub fn array(&mut self) -> Result<Vec<u8>, WireError> {
+        match self.next()? {
+            Argument::Array(value) => Ok(value),
+            _ => Err(WireError::UnexpectedArgument),
+        }
+    }
+
+    pub fn fd(&mut self) -> Result<OwnedFd, WireError> {
+        match self.next()? {
+            Argument::Fd(value) => Ok(value),
+            _ => Err(WireError::UnexpectedArgument),
+        }
+    }
+
+    /// Checks that all arguments were consumed.
+    pub fn finish(mut self) -> Result<(), WireError> {
+        match self.0.next() {
+            Some(_) => Err(WireError::UnexpectedArgument),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use super::{client::Resource, display::DisplayHandle};
+use crate::protocol::wayland::wl_callback;
+
+/// A `wl_callback` awaiting completion, as created by `wl_display.sync` or
+/// `wl_surface.frame`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Callback(Resource);
+
+impl Callback {
+    /// Wraps a `wl_callback` object.
+    pub fn new(resource: Resource) -> Self {
+        Callback(resource)
+    }
+
+    /// Returns the callback object.
+    pub fn resource(&self) -> Resource {
+        self.0
+    }
+
+    /// Sends `wl_callback.done` with `data`, usually a timestamp in
+    /// milliseconds or a serial, and destroys the callback.
+    pub fn done(self, handle: &mut DisplayHandle, data: u32) {
+        handle.send_event(
+            self.0,
+            wl_callback::Event::Done {
+                callback_data: data,
+            },
+        );
+        handle.destroy_object(self.0);
+    }
+}
@@ -0,0 +1,229 @@
+use super::credentials::Credentials;
+use crate::{
+    object_map::{ObjectMap, Side},
+    wire::{Message, ObjectId, RawMessage, ReadHalf, WriteHalf},
+};
+use std::{
+    collections::VecDeque,
+    fmt,
+    os::fd::OwnedFd,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+use tokio::{
+    sync::mpsc,
+    task::JoinHandle,
+    time::{self, Instant},
+};
+
+/// How long queued events are still written after a client is disconnected.
+const DISCONNECT_LINGER: Duration = Duration::from_secs(1);
+
+/// Identifier of a connected client, unique for the lifetime of a display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ClientId(pub(crate) u64);
+
+impl fmt::Display for ClientId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// A protocol object of a specific client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Resource {
+    pub client: ClientId,
+    pub id: ObjectId,
+}
+
+impl Resource {
+    /// Creates a resource.
+    pub fn new(client: ClientId, id: ObjectId) -> Self {
+        Resource { client, id }
+    }
+}
+
+/// Events sent from the client tasks to the display.
+#[derive(Debug)]
+pub(crate) enum ClientEvent {
+    /// A framed request, with the file descriptors received since the previous one.
+    Message(RawMessage, Vec<OwnedFd>),
+    /// The connection was closed or broke.
+    Disconnected,
+}
+
+/// Encoded events handed to the writer task.
+#[derive(Debug)]
+struct Batch {
+    data: Vec<u8>,
+    fds: Vec<OwnedFd>,
+}
+
+/// A connected client.
+#[derive(Debug)]
+pub struct Client {
+    id: ClientId,
+    credentials: Credentials,
+    pub(crate) objects: ObjectMap,
+
+    /// Received file descriptors not yet consumed by a request.
+    pub(crate) fds: VecDeque<OwnedFd>,
+    /// Registry objects receiving global announcements.
+    pub(crate) registries: Vec<ObjectId>,
+    /// Events encoded since the last flush.
+    batch: Batch,
+    /// Bytes handed to the writer task and not yet written.
+    backlog: Arc<AtomicUsize>,
+    /// Set while the client is above the high-water mark.
+    pub(crate) congested: bool,
+    outgoing: mpsc::UnboundedSender<Batch>,
+
+    reader: JoinHandle<()>,
+}
+
+impl Client {
+    /// Spawns the tasks driving the connection.
+    pub(crate) fn spawn(
+        id: ClientId,
+        credentials: Credentials,
+        reader: ReadHalf,
+        writer: WriteHalf,
+        events: mpsc::Sender<(ClientId, ClientEvent)>,
+    ) -> Self {
+        let (outgoing, outgoing_rx) = mpsc::unbounded_channel();
+        let backlog = Arc::new(AtomicUsize::new(0));
+
+        let reader = tokio::spawn(read_loop(id, reader, events));
+        tokio::spawn(write_loop(writer, outgoing_rx, backlog.clone()));
+
+        Client {
+            id,
+            credentials,
+            objects: ObjectMap::new(Side::Server),
+            fds: VecDeque::new(),
+            registries: Vec::new(),
+            batch: Batch {
+                data: Vec::new(),
+                fds: Vec::new(),
+            },
+            backlog,
+            congested: false,
+            outgoing,
+            reader,
+        }
+    }
+
+    /// Returns the id of the client.
+    pub fn id(&self) -> ClientId {
+        self.id
+    }
+
+    /// Returns the credentials of the client process.
+    pub fn credentials(&self) -> &Credentials {
+        &self.credentials
+    }
+
+    /// Returns the objects of the client.
+    pub fn objects(&self) -> &ObjectMap {
+        &self.objects
+    }
+
+    /// Returns the number of bytes queued for the client and not yet written.
+    pub fn pending_bytes(&self) -> usize {
+        self.batch.data.len() + self.backlog.load(Ordering::Relaxed)
+    }
+
+    /// Queues a message until the next flush.
+    pub(crate) fn send(&mut self, message: Message) {
+        // Messages that cannot be encoded are dropped
+        let _ = message.encode(&mut self.batch.data, &mut self.batch.fds);
+    }
+
+    /// Hands the queued messages to the writer task.
+    pub(crate) fn flush(&mut self) {
+        if self.batch.data.is_empty() {
+            return;
+        }
+
+        let batch = Batch {
+            data: std::mem::take(&mut self.batch.data),
+            fds: std::mem::take(&mut self.batch.fds),
+        };
+
+        self.backlog.fetch_add(batch.data.len(), Ordering::Relaxed);
+
+        // The writer only stops once the connection broke, the reader reports that
+        let _ = self.outgoing.send(batch);
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        // Events sent right before a disconnect, like errors, are still delivered
+        self.flush();
+
+        // The writer finishes on its own, see `DISCONNECT_LINGER`
+        self.reader.abort();
+    }
+}
+
+/// Forwards framed requests to the display.
+async fn read_loop(
+    id: ClientId,
+    mut reader: ReadHalf,
+    events: mpsc::Sender<(ClientId, ClientEvent)>,
+) {
+    loop {
+        let event = match reader.read_raw().await {
+            Ok(message) => ClientEvent::Message(message, reader.fds_mut().drain(..).collect()),
+            Err(_) => {
+                let _ = events.send((id, ClientEvent::Disconnected)).await;
+                return;
+            }
+        };
+
+        if events.send((id, event)).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Writes batches as they come, until the client is gone and its last
+/// batches are written or [`DISCONNECT_LINGER`] expires.
+async fn write_loop(
+    mut writer: WriteHalf,
+    mut outgoing: mpsc::UnboundedReceiver<Batch>,
+    backlog: Arc<AtomicUsize>,
+) {
+    let mut deadline = None;
+
+    loop {
+        let pending = writer.pending();
+        let mut queued = 0;
+
+        tokio::select! {
+            batch = outgoing.recv(), if deadline.is_none() => match batch {
+                Some(batch) => {
+                    queued = batch.data.len();
+                    writer.queue_encoded(&batch.data, batch.fds);
+                }
+                None => deadline = Some(Instant::now() + DISCONNECT_LINGER),
+            },
+            result = writer.flush(), if pending > 0 => {
+                if result.is_err() {
+                    return;
+                }
+            }
+            _ = time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => return,
+        }
+
+        backlog.fetch_sub(pending + queued - writer.pending(), Ordering::Relaxed);
+
+        if deadline.is_some() && writer.pending() == 0 {
+            return;
+        }
+    }
+}
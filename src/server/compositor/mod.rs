@@ -0,0 +1,454 @@
+mod region;
+mod subsurface;
+mod surface;
+
+pub use self::{
+    region::Region,
+    subsurface::{SUBSURFACE_ROLE, Subsurface, SurfaceTree},
+    surface::{BufferAssignment, Damage, Surface, SurfaceState},
+};
+
+use self::subsurface::{SubcompositorDispatch, SubsurfaceDispatch};
+use super::{
+    callback::Callback,
+    client::Resource,
+    display::{Dispatch, Display, DisplayHandle},
+    error::ProtocolError,
+};
+use crate::{
+    geometry::{Point, Rectangle},
+    protocol::{
+        MessageGroup, WEnum,
+        wayland::{wl_compositor, wl_region, wl_subcompositor, wl_subsurface, wl_surface},
+    },
+    wire::Message,
+};
+use std::collections::HashMap;
+
+/// Version of the `wl_compositor` global.
+const VERSION: u32 = 6;
+
+/// Version of the `wl_subcompositor` global.
+const SUBCOMPOSITOR_VERSION: u32 = 1;
+
+/// Callbacks of a surface role, run on every commit of surfaces with that role.
+pub struct RoleHooks<D> {
+    /// Validates the pending state before it is applied.
+    pub pre_commit: fn(&mut D, &mut DisplayHandle, Resource) -> Result<(), ProtocolError>,
+    /// Reacts to the newly applied state.
+    pub post_commit: fn(&mut D, &mut DisplayHandle, Resource),
+}
+
+impl<D> Clone for RoleHooks<D> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<D> Copy for RoleHooks<D> {}
+
+/// State of the `wl_compositor` and `wl_subcompositor` globals.
+pub struct CompositorState<D> {
+    surfaces: HashMap<Resource, Surface>,
+    regions: HashMap<Resource, Region>,
+    /// Surface of each `wl_subsurface`.
+    subsurfaces: HashMap<Resource, Resource>,
+    roles: HashMap<&'static str, RoleHooks<D>>,
+    surface_hooks: HashMap<Resource, RoleHooks<D>>,
+}
+
+/// Access to [`CompositorState`] and surface notifications.
+pub trait CompositorHandler: Sized + 'static {
+    /// Returns the compositor state.
+    fn compositor_state(&mut self) -> &mut CompositorState<Self>;
+
+    /// Called when a client created a surface.
+    fn new_surface(&mut self, handle: &mut DisplayHandle, surface: Resource) {
+        let _ = (handle, surface);
+    }
+
+    /// Called after a commit was applied to the current state of `surface`.
+    fn commit(&mut self, handle: &mut DisplayHandle, surface: Resource);
+
+    /// Called when a surface is destroyed.
+    fn surface_destroyed(&mut self, handle: &mut DisplayHandle, surface: Resource) {
+        let _ = (handle, surface);
+    }
+}
+
+impl<D: CompositorHandler> CompositorState<D> {
+    /// Creates the `wl_compositor` and `wl_subcompositor` globals and handles
+    /// surfaces, regions and subsurfaces.
+    pub fn new(display: &mut Display<D>) -> Self {
+        display.register(&wl_compositor::INTERFACE, CompositorDispatch);
+        display.register(&wl_surface::INTERFACE, SurfaceDispatch);
+        display.register(&wl_region::INTERFACE, RegionDispatch);
+        display.register(&wl_subcompositor::INTERFACE, SubcompositorDispatch);
+        display.register(&wl_subsurface::INTERFACE, SubsurfaceDispatch);
+
+        display.create_global(&wl_compositor::INTERFACE, VERSION, |_, _, _| Ok(()));
+        display.create_global(
+            &wl_subcompositor::INTERFACE,
+            SUBCOMPOSITOR_VERSION,
+            |_, _, _| Ok(()),
+        );
+
+        CompositorState {
+            surfaces: HashMap::new(),
+            regions: HashMap::new(),
+            subsurfaces: HashMap::new(),
+            roles: HashMap::new(),
+            surface_hooks: HashMap::new(),
+        }
+    }
+}
+
+impl<D> CompositorState<D> {
+    /// Returns a surface.
+    pub fn surface(&self, surface: Resource) -> Option<&Surface> {
+        self.surfaces.get(&surface)
+    }
+
+    /// Returns a surface, mutably.
+    pub fn surface_mut(&mut self, surface: Resource) -> Option<&mut Surface> {
+        self.surfaces.get_mut(&surface)
+    }
+
+    /// Returns an iterator over all surfaces.
+    pub fn surfaces(&self) -> impl Iterator<Item = &Surface> {
+        self.surfaces.values()
+    }
+
+    /// Returns the current content of a `wl_region`.
+    pub fn region(&self, region: Resource) -> Option<&Region> {
+        self.regions.get(&region)
+    }
+
+    /// Sets the hooks run when surfaces with `role` are committed.
+    pub fn set_role_hooks(&mut self, role: &'static str, hooks: RoleHooks<D>) {
+        self.roles.insert(role, hooks);
+    }
+
+    /// Sets hooks run when a single surface is committed, after those of its role.
+    ///
+    /// Meant for objects that extend a surface before giving it a role, such
+    /// as `xdg_surface`. The hooks are dropped along with the surface.
+    pub fn set_surface_hooks(&mut self, surface: Resource, hooks: RoleHooks<D>) {
+        self.surface_hooks.insert(surface, hooks);
+    }
+
+    /// Removes the hooks set with [`set_surface_hooks`](Self::set_surface_hooks).
+    pub fn remove_surface_hooks(&mut self, surface: Resource) {
+        self.surface_hooks.remove(&surface);
+    }
+}
+
+/// Handles `wl_surface.commit`, caching the pending state of synchronized
+/// subsurfaces and applying it otherwise.
+fn commit<D: CompositorHandler>(
+    state: &mut D,
+    handle: &mut DisplayHandle,
+    surface: Resource,
+) -> Result<(), ProtocolError> {
+    for hooks in commit_hooks(state, surface) {
+        (hooks.pre_commit)(state, handle, surface)?;
+    }
+
+    let compositor = state.compositor_state();
+
+    if compositor.is_synchronized(surface) {
+        let Surface {
+            pending,
+            subsurface,
+            ..
+        } = compositor
+            .surfaces
+            .get_mut(&surface)
+            .expect("live wl_surface has a surface");
+
+        let subsurface = subsurface
+            .as_mut()
+            .expect("synchronized surface is a subsurface");
+        pending.merge_into(subsurface.cached.get_or_insert_default());
+
+        return Ok(());
+    }
+
+    apply(state, handle, surface, false);
+
+    Ok(())
+}
+
+/// Applies the pending, or with `cached` the cached, state of a surface, then
+/// the cached state of its synchronized subsurfaces.
+fn apply<D: CompositorHandler>(
+    state: &mut D,
+    handle: &mut DisplayHandle,
+    surface: Resource,
+    cached: bool,
+) {
+    let compositor = state.compositor_state();
+    let Some(Surface {
+        pending,
+        current,
+        subsurface,
+        ..
+    }) = compositor.surfaces.get_mut(&surface)
+    else {
+        return;
+    };
+
+    if !cached {
+        pending.merge_into(current);
+    } else if let Some(mut cached) = subsurface.as_mut().and_then(|s| s.cached.take()) {
+        cached.merge_into(current);
+    }
+
+    let children = compositor.apply_children(surface);
+
+    for hooks in commit_hooks(state, surface) {
+        (hooks.post_commit)(state, handle, surface);
+    }
+
+    state.commit(handle, surface);
+
+    for child in children {
+        let compositor = state.compositor_state();
+        let has_cached = compositor
+            .surfaces
+            .get(&child)
+            .and_then(Surface::subsurface)
+            .is_some_and(|subsurface| subsurface.cached.is_some());
+
+        if has_cached {
+            apply(state, handle, child, true);
+        }
+    }
+}
+
+/// Returns the hooks of the role of a surface, then its own.
+fn commit_hooks<D: CompositorHandler>(
+    state: &mut D,
+    surface: Resource,
+) -> impl Iterator<Item = RoleHooks<D>> + use<D> {
+    let compositor = state.compositor_state();
+    let role = compositor
+        .surfaces
+        .get(&surface)
+        .and_then(Surface::role)
+        .and_then(|role| compositor.roles.get(role).copied());
+
+    [role, compositor.surface_hooks.get(&surface).copied()]
+        .into_iter()
+        .flatten()
+}
+
+struct CompositorDispatch;
+
+impl<D: CompositorHandler> Dispatch<D> for CompositorDispatch {
+    fn request(
+        &mut self,
+        state: &mut D,
+        handle: &mut DisplayHandle,
+        resource: Resource,
+        message: Message,
+    ) -> Result<(), ProtocolError> {
+        let request = wl_compositor::Request::from_message(message)
+            .map_err(|err| ProtocolError::malformed(resource.id, err))?;
+
+        match request {
+            wl_compositor::Request::CreateSurface { id } => {
+                let surface = Resource::new(resource.client, id);
+
+                state
+                    .compositor_state()
+                    .surfaces
+                    .insert(surface, Surface::new(surface));
+                state.new_surface(handle, surface);
+            }
+            wl_compositor::Request::CreateRegion { id } => {
+                state
+                    .compositor_state()
+                    .regions
+                    .insert(Resource::new(resource.client, id), Region::new());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct SurfaceDispatch;
+
+impl<D: CompositorHandler> Dispatch<D> for SurfaceDispatch {
+    fn request(
+        &mut self,
+        state: &mut D,
+        handle: &mut DisplayHandle,
+        resource: Resource,
+        message: Message,
+    ) -> Result<(), ProtocolError> {
+        let request = wl_surface::Request::from_message(message)
+            .map_err(|err| ProtocolError::malformed(resource.id, err))?;
+
+        if let wl_surface::Request::Commit = request {
+            return commit(state, handle, resource);
+        }
+
+        let version = handle.version(resource);
+        let compositor = state.compositor_state();
+        let regions = &compositor.regions;
+        let pending = &mut compositor
+            .surfaces
+            .get_mut(&resource)
+            .expect("live wl_surface has a surface")
+            .pending;
+
+        let region = |id| {
+            regions
+                .get(&Resource::new(resource.client, id))
+                .cloned()
+                .ok_or(ProtocolError::invalid_object(id.get()))
+        };
+
+        match request {
+            wl_surface::Request::Destroy | wl_surface::Request::Commit => {}
+            wl_surface::Request::Attach { buffer, x, y } => {
+                if version >= 5 && (x, y) != (0, 0) {
+                    return Err(ProtocolError::new(
+                        resource.id,
+                        wl_surface::Error::InvalidOffset,
+                        "attach offset must be zero, use wl_surface.offset",
+                    ));
+                }
+
+                pending.buffer = Some(match buffer {
+                    Some(buffer) => BufferAssignment::New(Resource::new(resource.client, buffer)),
+                    None => BufferAssignment::Removed,
+                });
+
+                if version < 5 {
+                    pending.buffer_offset = Point::new(x, y);
+                }
+            }
+            wl_surface::Request::Damage {
+                x,
+                y,
+                width,
+                height,
+            } => {
+                let rect = Rectangle::new(x, y, width, height);
+                pending.damage.push(Damage::Surface(rect));
+            }
+            wl_surface::Request::Frame { callback } => {
+                let callback = Callback::new(Resource::new(resource.client, callback));
+                pending.frame_callbacks.push(callback);
+            }
+            wl_surface::Request::SetOpaqueRegion { region: id } => {
+                pending.opaque_region = id.map(region).transpose()?;
+            }
+            wl_surface::Request::SetInputRegion { region: id } => {
+                pending.input_region = id.map(region).transpose()?;
+            }
+            wl_surface::Request::SetBufferTransform { transform } => {
+                pending.buffer_transform = match transform {
+                    WEnum::Value(transform) => transform,
+                    WEnum::Unknown(value) => {
+                        return Err(ProtocolError::new(
+                            resource.id,
+                            wl_surface::Error::InvalidTransform,
+                            format!("buffer transform value ({value}) is not valid"),
+                        ));
+                    }
+                };
+            }
+            wl_surface::Request::SetBufferScale { scale } => {
+                if scale < 1 {
+                    return Err(ProtocolError::new(
+                        resource.id,
+                        wl_surface::Error::InvalidScale,
+                        format!("buffer scale value ({scale}) is not positive"),
+                    ));
+                }
+
+                pending.buffer_scale = scale;
+            }
+            wl_surface::Request::DamageBuffer {
+                x,
+                y,
+                width,
+                height,
+            } => {
+                let rect = Rectangle::new(x, y, width, height);
+                pending.damage.push(Damage::Buffer(rect));
+            }
+            wl_surface::Request::Offset { x, y } => {
+                pending.buffer_offset = Point::new(x, y);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn destroyed(&mut self, state: &mut D, handle: &mut DisplayHandle, resource: Resource) {
+        let compositor = state.compositor_state();
+
+        // Frame callbacks of a destroyed surface never complete
+        if let Some(surface) = compositor.surfaces.get_mut(&resource) {
+            for callback in surface.take_all_frame_callbacks() {
+                handle.destroy_object(callback.resource());
+            }
+        }
+
+        compositor.unlink(resource);
+        compositor.surface_hooks.remove(&resource);
+
+        if let Some(surface) = compositor.surfaces.remove(&resource) {
+            compositor.unlink_children(&surface);
+            state.surface_destroyed(handle, resource);
+        }
+    }
+}
+
+struct RegionDispatch;
+
+impl<D: CompositorHandler> Dispatch<D> for RegionDispatch {
+    fn request(
+        &mut self,
+        state: &mut D,
+        _handle: &mut DisplayHandle,
+        resource: Resource,
+        message: Message,
+    ) -> Result<(), ProtocolError> {
+        let request = wl_region::Request::from_message(message)
+            .map_err(|err| ProtocolError::malformed(resource.id, err))?;
+
+        let region = state
+            .compositor_state()
+            .regions
+            .get_mut(&resource)
+            .expect("live wl_region has a region");
+
+        match request {
+            wl_region::Request::Destroy => {}
+            wl_region::Request::Add {
+                x,
+                y,
+                width,
+                height,
+            } => region.add(Rectangle::new(x, y, width, height)),
+            wl_region::Request::Subtract {
+                x,
+                y,
+                width,
+                height,
+            } => region.subtract(Rectangle::new(x, y, width, height)),
+        }
+
+        Ok(())
+    }
+
+    fn destroyed(&mut self, state: &mut D, _handle: &mut DisplayHandle, resource: Resource) {
+        state.compositor_state().regions.remove(&resource);
+    }
+}
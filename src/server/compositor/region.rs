@@ -0,0 +1,257 @@
+use crate::geometry::{Point, Rectangle};
+
+/// Set of pixels, such as the input or opaque area of a surface.
+///
+/// Stored as disjoint rectangles, built by adding and subtracting rectangles
+/// as described by `wl_region`.
+#[derive(Debug, Clone, Default)]
+pub struct Region {
+    rects: Vec<Rectangle>,
+}
+
+impl Region {
+    /// Creates an empty region.
+    pub fn new() -> Self {
+        Region::default()
+    }
+
+    /// Returns `true` if the region contains no pixels.
+    pub fn is_empty(&self) -> bool {
+        self.rects.is_empty()
+    }
+
+    /// Returns disjoint rectangles covering exactly the region, in no particular order.
+    pub fn rects(&self) -> impl Iterator<Item = Rectangle> + '_ {
+        self.rects.iter().copied()
+    }
+
+    /// Returns the smallest rectangle containing the region.
+    pub fn extents(&self) -> Rectangle {
+        let mut rects = self.rects();
+        let Some(first) = rects.next() else {
+            return Rectangle::default();
+        };
+
+        rects.fold(first, |extents, rect| {
+            Rectangle::from_edges(
+                extents.loc.x.min(rect.loc.x),
+                extents.loc.y.min(rect.loc.y),
+                extents.right().max(rect.right()),
+                extents.bottom().max(rect.bottom()),
+            )
+        })
+    }
+
+    /// Returns `true` if `point` lies within the region.
+    pub fn contains(&self, point: Point) -> bool {
+        self.rects.iter().any(|rect| rect.contains(point))
+    }
+
+    /// Adds a rectangle to the region.
+    pub fn add(&mut self, rect: Rectangle) {
+        if rect.is_empty() {
+            return;
+        }
+
+        // Only add the parts not covered yet, keeping the rectangles disjoint
+        let mut parts = vec![rect];
+        for existing in &self.rects {
+            parts = parts
+                .into_iter()
+                .flat_map(|part| difference(part, *existing))
+                .collect();
+        }
+
+        self.rects.append(&mut parts);
+    }
+
+    /// Removes a rectangle from the region.
+    pub fn subtract(&mut self, rect: Rectangle) {
+        if rect.intersection(self.extents()).is_none() {
+            return;
+        }
+
+        self.rects = self
+            .rects
+            .iter()
+            .flat_map(|existing| difference(*existing, rect))
+            .collect();
+    }
+
+    /// Keeps only the part of the region within a rectangle.
+    pub fn intersect(&mut self, rect: Rectangle) {
+        self.rects
+            .retain_mut(|existing| match existing.intersection(rect) {
+                Some(intersection) => {
+                    *existing = intersection;
+                    true
+                }
+                None => false,
+            });
+    }
+
+    /// Adds another region to this one.
+    pub fn add_region(&mut self, other: &Region) {
+        for rect in other.rects() {
+            self.add(rect);
+        }
+    }
+
+    /// Removes another region from this one.
+    pub fn subtract_region(&mut self, other: &Region) {
+        for rect in other.rects() {
+            self.subtract(rect);
+        }
+    }
+
+    /// Keeps only the part of the region also within `other`.
+    pub fn intersect_region(&mut self, other: &Region) {
+        self.rects = self
+            .rects
+            .iter()
+            .flat_map(|rect| other.rects().filter_map(|other| rect.intersection(other)))
+            .collect();
+    }
+
+    /// Moves the region by `offset`.
+    pub fn translate(&mut self, offset: Point) {
+        for rect in &mut self.rects {
+            *rect = rect.translate(offset);
+        }
+    }
+}
+
+impl From<Rectangle> for Region {
+    fn from(rect: Rectangle) -> Self {
+        let mut region = Region::new();
+        region.add(rect);
+        region
+    }
+}
+
+/// Returns the parts of `rect` outside of `hole`, as up to four rectangles.
+fn difference(rect: Rectangle, hole: Rectangle) -> Vec<Rectangle> {
+    let Some(hole) = rect.intersection(hole) else {
+        return vec![rect];
+    };
+
+    let parts = [
+        // Above and below the hole, full width
+        Rectangle::from_edges(rect.loc.x, rect.loc.y, rect.right(), hole.loc.y),
+        Rectangle::from_edges(rect.loc.x, hole.bottom(), rect.right(), rect.bottom()),
+        // Left and right of the hole, within its rows
+        Rectangle::from_edges(rect.loc.x, hole.loc.y, hole.loc.x, hole.bottom()),
+        Rectangle::from_edges(hole.right(), hole.loc.y, rect.right(), hole.bottom()),
+    ];
+
+    parts.into_iter().filter(|part| !part.is_empty()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(region: &Region) -> i64 {
+        region
+            .rects()
+            .map(|rect| i64::from(rect.size.width) * i64::from(rect.size.height))
+            .sum()
+    }
+
+    fn disjoint(region: &Region) -> bool {
+        let rects: Vec<_> = region.rects().collect();
+        rects
+            .iter()
+            .enumerate()
+            .all(|(i, a)| rects[i + 1..].iter().all(|b| a.intersection(*b).is_none()))
+    }
+
+    #[test]
+    fn add_overlapping() {
+        let mut region = Region::from(Rectangle::new(0, 0, 10, 10));
+        region.add(Rectangle::new(5, 5, 10, 10));
+
+        assert!(disjoint(&region));
+        assert_eq!(area(&region), 175);
+        assert_eq!(region.extents(), Rectangle::new(0, 0, 15, 15));
+        assert!(region.contains(Point::new(14, 14)));
+        assert!(!region.contains(Point::new(14, 0)));
+    }
+
+    #[test]
+    fn add_covered() {
+        let mut region = Region::from(Rectangle::new(0, 0, 10, 10));
+        region.add(Rectangle::new(2, 2, 3, 3));
+        region.add(Rectangle::new(0, 0, 0, 5));
+
+        assert_eq!(region.rects().count(), 1);
+    }
+
+    #[test]
+    fn subtract_hole() {
+        let mut region = Region::from(Rectangle::new(0, 0, 10, 10));
+        region.subtract(Rectangle::new(3, 3, 4, 4));
+
+        assert!(disjoint(&region));
+        assert_eq!(area(&region), 84);
+        assert!(!region.contains(Point::new(5, 5)));
+        assert!(region.contains(Point::new(2, 5)));
+        assert!(region.contains(Point::new(7, 5)));
+        assert_eq!(region.extents(), Rectangle::new(0, 0, 10, 10));
+
+        region.subtract(Rectangle::new(-5, -5, 20, 20));
+        assert!(region.is_empty());
+    }
+
+    #[test]
+    fn intersect() {
+        let mut region = Region::from(Rectangle::new(0, 0, 10, 10));
+        region.add(Rectangle::new(20, 0, 10, 10));
+        region.intersect(Rectangle::new(5, 5, 20, 20));
+
+        assert_eq!(area(&region), 50);
+
+        let mut other = Region::from(Rectangle::new(0, 0, 100, 100));
+        other.subtract(Rectangle::new(0, 0, 8, 8));
+        region.intersect_region(&other);
+
+        assert!(disjoint(&region));
+        assert_eq!(area(&region), 41);
+        assert!(!region.contains(Point::new(6, 6)));
+    }
+
+    #[test]
+    fn regions() {
+        let mut region = Region::from(Rectangle::new(0, 0, 10, 10));
+        let mut other = Region::from(Rectangle::new(10, 0, 10, 10));
+        other.add(Rectangle::new(0, 10, 20, 10));
+
+        region.add_region(&other);
+        assert_eq!(region.extents(), Rectangle::new(0, 0, 20, 20));
+        assert_eq!(area(&region), 400);
+
+        region.subtract_region(&other);
+        assert_eq!(area(&region), 100);
+        assert_eq!(region.extents(), Rectangle::new(0, 0, 10, 10));
+    }
+
+    #[test]
+    fn translate() {
+        let mut region = Region::from(Rectangle::new(0, 0, 10, 10));
+        region.translate(Point::new(-5, 5));
+
+        assert_eq!(region.extents(), Rectangle::new(-5, 5, 10, 10));
+        assert!(region.contains(Point::new(-5, 14)));
+    }
+
+    #[test]
+    fn extreme_coordinates() {
+        let mut region = Region::from(Rectangle::new(i32::MIN, i32::MIN, i32::MAX, i32::MAX));
+        region.add(Rectangle::new(i32::MAX - 1, 0, i32::MAX, 1));
+        region.subtract(Rectangle::new(-10, -10, 5, 5));
+
+        assert!(disjoint(&region));
+        assert!(region.contains(Point::new(i32::MAX - 1, 0)));
+        assert!(!region.contains(Point::new(-8, -8)));
+    }
+}
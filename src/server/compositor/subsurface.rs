@@ -0,0 +1,680 @@
+use super::{CompositorHandler, CompositorState, Surface, SurfaceState, apply};
+use crate::{
+    geometry::Point,
+    protocol::{
+        MessageGroup,
+        wayland::{wl_subcompositor, wl_subsurface},
+    },
+    server::{
+        client::Resource,
+        display::{Dispatch, DisplayHandle},
+        error::ProtocolError,
+    },
+    wire::{Message, ObjectId},
+};
+use std::collections::HashMap;
+
+/// Role of surfaces created by `wl_subcompositor.get_subsurface`.
+pub const SUBSURFACE_ROLE: &str = "wl_subsurface";
+
+/// Attributes of a surface with the `wl_subsurface` role.
+#[derive(Debug)]
+pub struct Subsurface {
+    resource: Resource,
+    parent: Resource,
+    position: Point,
+    pending_position: Option<Point>,
+    sync: bool,
+    pub(crate) cached: Option<SurfaceState>,
+}
+
+impl Subsurface {
+    /// Returns the `wl_subsurface` object.
+    pub fn resource(&self) -> Resource {
+        self.resource
+    }
+
+    /// Returns the parent surface.
+    pub fn parent(&self) -> Resource {
+        self.parent
+    }
+
+    /// Returns the position relative to the parent surface.
+    pub fn position(&self) -> Point {
+        self.position
+    }
+
+    /// Returns `true` in synchronized mode, regardless of the parent's mode.
+    pub fn is_sync(&self) -> bool {
+        self.sync
+    }
+
+    /// Returns the state committed while synchronized, waiting for the parent.
+    pub fn cached(&self) -> Option<&SurfaceState> {
+        self.cached.as_ref()
+    }
+}
+
+impl<D> CompositorState<D> {
+    /// Returns `true` if commits to `surface` are cached until its parent's
+    /// state is applied, because it or one of its ancestors is synchronized.
+    pub fn is_synchronized(&self, surface: Resource) -> bool {
+        let mut surface = surface;
+
+        while let Some(subsurface) = self.subsurface_of(surface) {
+            if subsurface.sync {
+                return true;
+            }
+
+            surface = subsurface.parent;
+        }
+
+        false
+    }
+
+    /// Returns the surface tree rooted at `root`, back to front, for rendering.
+    ///
+    /// Subsurfaces without a buffer are skipped along with their children.
+    pub fn surface_tree(&self, root: Resource) -> SurfaceTree<'_> {
+        SurfaceTree {
+            surfaces: &self.surfaces,
+            walk: vec![(root, Point::default(), 0)],
+        }
+    }
+
+    fn subsurface_of(&self, surface: Resource) -> Option<&Subsurface> {
+        self.surfaces.get(&surface)?.subsurface.as_ref()
+    }
+
+    /// Applies the stacking order and positions of the subsurfaces of
+    /// `parent` along with its state, returning the subsurfaces.
+    pub(super) fn apply_children(&mut self, parent: Resource) -> Vec<Resource> {
+        let Some(surface) = self.surfaces.get_mut(&parent) else {
+            return Vec::new();
+        };
+
+        surface.stack.clone_from(&surface.pending_stack);
+
+        let children: Vec<_> = surface
+            .stack
+            .iter()
+            .copied()
+            .filter(|child| *child != parent)
+            .collect();
+
+        for child in &children {
+            if let Some(subsurface) = self
+                .surfaces
+                .get_mut(child)
+                .and_then(|s| s.subsurface.as_mut())
+                && let Some(position) = subsurface.pending_position.take()
+            {
+                subsurface.position = position;
+            }
+        }
+
+        children
+    }
+
+    /// Detaches a subsurface from its parent, leaving its `wl_subsurface` inert.
+    pub(super) fn unlink(&mut self, surface: Resource) {
+        let Some(subsurface) = self
+            .surfaces
+            .get_mut(&surface)
+            .and_then(|surface| surface.subsurface.take())
+        else {
+            return;
+        };
+
+        if let Some(parent) = self.surfaces.get_mut(&subsurface.parent) {
+            parent.pending_stack.retain(|child| *child != surface);
+            parent.stack.retain(|child| *child != surface);
+        }
+    }
+
+    /// Detaches all subsurfaces of a destroyed surface.
+    pub(super) fn unlink_children(&mut self, parent: &Surface) {
+        for child in parent.pending_stack.iter().chain(&parent.stack) {
+            if let Some(child) = self.surfaces.get_mut(child) {
+                child.subsurface = None;
+            }
+        }
+    }
+}
+
+/// Iterator over a surface tree, yielding each surface and its location
+/// relative to the root.
+pub struct SurfaceTree<'a> {
+    surfaces: &'a HashMap<Resource, Surface>,
+    /// Surfaces being walked, with their location and next index in their stack.
+    walk: Vec<(Resource, Point, usize)>,
+}
+
+impl Iterator for SurfaceTree<'_> {
+    type Item = (Resource, Point);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (resource, location, index) = self.walk.last_mut()?;
+            let Some(&next) = self
+                .surfaces
+                .get(resource)
+                .and_then(|surface| surface.stack.get(*index))
+            else {
+                self.walk.pop();
+                continue;
+            };
+
+            *index += 1;
+
+            if next == *resource {
+                return Some((next, *location));
+            }
+
+            let Some(child) = self.surfaces.get(&next) else {
+                continue;
+            };
+
+            if let Some(subsurface) = &child.subsurface
+                && child.has_buffer()
+            {
+                let location = *location + subsurface.position;
+                self.walk.push((next, location, 0));
+            }
+        }
+    }
+}
+
+pub(super) struct SubcompositorDispatch;
+
+impl<D: CompositorHandler> Dispatch<D> for SubcompositorDispatch {
+    fn request(
+        &mut self,
+        state: &mut D,
+        _handle: &mut DisplayHandle,
+        resource: Resource,
+        message: Message,
+    ) -> Result<(), ProtocolError> {
+        let request = wl_subcompositor::Request::from_message(message)
+            .map_err(|err| ProtocolError::malformed(resource.id, err))?;
+
+        let wl_subcompositor::Request::GetSubsurface {
+            id,
+            surface,
+            parent,
+        } = request
+        else {
+            return Ok(());
+        };
+
+        let compositor = state.compositor_state();
+        let subsurface = Resource::new(resource.client, id);
+        let surface = Resource::new(resource.client, surface);
+        let parent = Resource::new(resource.client, parent);
+
+        let bad_surface = |message: &str| {
+            ProtocolError::new(
+                resource.id,
+                wl_subcompositor::Error::BadSurface,
+                format!("wl_surface@{} {message}", surface.id),
+            )
+        };
+        let bad_parent = |message: &str| {
+            ProtocolError::new(
+                resource.id,
+                wl_subcompositor::Error::BadParent,
+                format!("wl_surface@{} {message}", parent.id),
+            )
+        };
+
+        if surface == parent {
+            return Err(bad_parent("cannot be its own parent"));
+        }
+
+        // The parent must not be a descendant of the surface
+        let mut ancestor = parent;
+        while let Some(next) = compositor.subsurface_of(ancestor) {
+            if next.parent == surface {
+                return Err(bad_parent(&format!(
+                    "is a descendant of wl_surface@{}",
+                    surface.id
+                )));
+            }
+
+            ancestor = next.parent;
+        }
+
+        let child = compositor
+            .surfaces
+            .get_mut(&surface)
+            .expect("live wl_surface has a surface");
+
+        if child.subsurface.is_some() {
+            return Err(bad_surface("is already a sub-surface"));
+        }
+
+        if !child.set_role(SUBSURFACE_ROLE) {
+            return Err(bad_surface("already has another role"));
+        }
+
+        child.subsurface = Some(Subsurface {
+            resource: subsurface,
+            parent,
+            position: Point::default(),
+            pending_position: None,
+            sync: true,
+            cached: None,
+        });
+
+        // New subsurfaces are placed on top of their siblings right away
+        let parent = compositor
+            .surfaces
+            .get_mut(&parent)
+            .expect("live wl_surface has a surface");
+        parent.pending_stack.push(surface);
+        parent.stack.push(surface);
+
+        compositor.subsurfaces.insert(subsurface, surface);
+
+        Ok(())
+    }
+}
+
+pub(super) struct SubsurfaceDispatch;
+
+impl<D: CompositorHandler> Dispatch<D> for SubsurfaceDispatch {
+    fn request(
+        &mut self,
+        state: &mut D,
+        handle: &mut DisplayHandle,
+        resource: Resource,
+        message: Message,
+    ) -> Result<(), ProtocolError> {
+        let request = wl_subsurface::Request::from_message(message)
+            .map_err(|err| ProtocolError::malformed(resource.id, err))?;
+
+        let compositor = state.compositor_state();
+        let Some(&surface) = compositor.subsurfaces.get(&resource) else {
+            return Ok(());
+        };
+
+        // Inert once the surface or its parent is gone
+        let Some(subsurface) = compositor
+            .surfaces
+            .get_mut(&surface)
+            .and_then(|surface| surface.subsurface.as_mut())
+        else {
+            return Ok(());
+        };
+
+        match request {
+            wl_subsurface::Request::Destroy => {}
+            wl_subsurface::Request::SetPosition { x, y } => {
+                subsurface.pending_position = Some(Point::new(x, y));
+            }
+            wl_subsurface::Request::PlaceAbove { sibling } => {
+                restack(compositor, resource, surface, sibling, 1)?;
+            }
+            wl_subsurface::Request::PlaceBelow { sibling } => {
+                restack(compositor, resource, surface, sibling, 0)?;
+            }
+            wl_subsurface::Request::SetSync => subsurface.sync = true,
+            wl_subsurface::Request::SetDesync => {
+                subsurface.sync = false;
+
+                let cached = subsurface.cached.is_some();
+                if cached && !compositor.is_synchronized(surface) {
+                    apply(state, handle, surface, true);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn destroyed(&mut self, state: &mut D, _handle: &mut DisplayHandle, resource: Resource) {
+        let compositor = state.compositor_state();
+
+        let Some(surface) = compositor.subsurfaces.remove(&resource) else {
+            return;
+        };
+
+        if compositor
+            .subsurface_of(surface)
+            .is_some_and(|subsurface| subsurface.resource == resource)
+        {
+            compositor.unlink(surface);
+        }
+    }
+}
+
+/// Moves `surface` next to `sibling` in the pending stack of their parent,
+/// `offset` 1 placing it above and 0 below.
+fn restack<D>(
+    compositor: &mut CompositorState<D>,
+    resource: Resource,
+    surface: Resource,
+    sibling: ObjectId,
+    offset: usize,
+) -> Result<(), ProtocolError> {
+    let sibling = Resource::new(surface.client, sibling);
+    let parent = compositor
+        .subsurface_of(surface)
+        .expect("restacked surface is a subsurface")
+        .parent;
+
+    let stack = &mut compositor
+        .surfaces
+        .get_mut(&parent)
+        .expect("subsurface parent is alive")
+        .pending_stack;
+
+    if sibling == surface || !stack.contains(&sibling) {
+        return Err(ProtocolError::new(
+            resource.id,
+            wl_subsurface::Error::BadSurface,
+            format!(
+                "wl_surface@{} is not a parent or sibling of wl_surface@{}",
+                sibling.id, surface.id
+            ),
+        ));
+    }
+
+    stack.retain(|child| *child != surface);
+    let index = stack
+        .iter()
+        .position(|child| *child == sibling)
+        .expect("sibling is in the stack");
+    stack.insert(index + offset, surface);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        protocol::wayland::{wl_compositor, wl_surface},
+        testing::{Fixture, State, TestClient, protocol_error},
+    };
+
+    struct Setup {
+        fixture: Fixture,
+        client: TestClient,
+        compositor: ObjectId,
+        subcompositor: ObjectId,
+    }
+
+    impl Setup {
+        async fn new() -> Self {
+            let mut fixture = Fixture::new();
+            let mut client = fixture.connect().await;
+            let compositor = client.bind(&wl_compositor::INTERFACE, 6);
+            let subcompositor = client.bind(&wl_subcompositor::INTERFACE, 1);
+
+            Setup {
+                fixture,
+                client,
+                compositor,
+                subcompositor,
+            }
+        }
+
+        fn surface(&mut self) -> ObjectId {
+            let surface = self.client.create(&wl_surface::INTERFACE, 6);
+            self.client.queue(
+                self.compositor,
+                wl_compositor::Request::CreateSurface { id: surface },
+            );
+
+            surface
+        }
+
+        fn subsurface(&mut self, surface: ObjectId, parent: ObjectId) -> ObjectId {
+            let subsurface = self.client.create(&wl_subsurface::INTERFACE, 1);
+            self.client.queue(
+                self.subcompositor,
+                wl_subcompositor::Request::GetSubsurface {
+                    id: subsurface,
+                    surface,
+                    parent,
+                },
+            );
+
+            subsurface
+        }
+
+        /// Creates a surface that is a subsurface of `parent`.
+        fn child(&mut self, parent: ObjectId) -> (ObjectId, ObjectId) {
+            let surface = self.surface();
+            (surface, self.subsurface(surface, parent))
+        }
+
+        fn attach(&mut self, surface: ObjectId) {
+            let buffer = self.client.buffer();
+            self.client.queue(
+                surface,
+                wl_surface::Request::Attach {
+                    buffer: Some(buffer),
+                    x: 0,
+                    y: 0,
+                },
+            );
+        }
+
+        fn commit(&mut self, surface: ObjectId) {
+            self.client.queue(surface, wl_surface::Request::Commit);
+        }
+
+        async fn error(&mut self) -> Option<(ObjectId, u32)> {
+            let mut events = self.fixture.roundtrip(&mut self.client).await;
+            protocol_error(&mut events)
+        }
+
+        fn resource(&self, id: ObjectId) -> Resource {
+            Resource::new(self.client.id, id)
+        }
+
+        fn compositor(&self) -> &CompositorState<State> {
+            &self.fixture.state.compositor
+        }
+
+        fn committed(&self, surface: ObjectId) -> bool {
+            self.fixture.state.commits.contains(&self.resource(surface))
+        }
+
+        fn cached(&self, surface: ObjectId) -> bool {
+            self.compositor()
+                .surface(self.resource(surface))
+                .and_then(Surface::subsurface)
+                .is_some_and(|subsurface| subsurface.cached().is_some())
+        }
+    }
+
+    #[tokio::test]
+    async fn own_parent() {
+        let mut setup = Setup::new().await;
+        let surface = setup.surface();
+        setup.subsurface(surface, surface);
+
+        let subcompositor = setup.subcompositor;
+        assert_eq!(
+            setup.error().await,
+            Some((subcompositor, wl_subcompositor::Error::BadParent.into())),
+        );
+    }
+
+    #[tokio::test]
+    async fn descendant_parent() {
+        let mut setup = Setup::new().await;
+        let root = setup.surface();
+        let (child, _) = setup.child(root);
+        let (grandchild, _) = setup.child(child);
+        setup.subsurface(root, grandchild);
+
+        let subcompositor = setup.subcompositor;
+        assert_eq!(
+            setup.error().await,
+            Some((subcompositor, wl_subcompositor::Error::BadParent.into())),
+        );
+    }
+
+    #[tokio::test]
+    async fn place_sibling() {
+        let mut setup = Setup::new().await;
+        let parent = setup.surface();
+        let (first, first_sub) = setup.child(parent);
+        let (second, _) = setup.child(parent);
+
+        setup.client.queue(
+            first_sub,
+            wl_subsurface::Request::PlaceAbove { sibling: second },
+        );
+        setup.client.queue(
+            first_sub,
+            wl_subsurface::Request::PlaceBelow { sibling: parent },
+        );
+        setup.commit(parent);
+        assert_eq!(setup.error().await, None);
+
+        let stack = setup
+            .compositor()
+            .surface(setup.resource(parent))
+            .unwrap()
+            .stack();
+        assert_eq!(
+            stack,
+            [first, parent, second].map(|surface| setup.resource(surface)),
+        );
+    }
+
+    #[tokio::test]
+    async fn place_non_sibling() {
+        for above in [true, false] {
+            for nested in [true, false] {
+                let mut setup = Setup::new().await;
+                let parent = setup.surface();
+                let (child, child_sub) = setup.child(parent);
+                let sibling = match nested {
+                    true => setup.child(child).0,
+                    false => setup.surface(),
+                };
+
+                let request = match above {
+                    true => wl_subsurface::Request::PlaceAbove { sibling },
+                    false => wl_subsurface::Request::PlaceBelow { sibling },
+                };
+                setup.client.queue(child_sub, request);
+
+                assert_eq!(
+                    setup.error().await,
+                    Some((child_sub, wl_subsurface::Error::BadSurface.into())),
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn sync_commit_cached() {
+        let mut setup = Setup::new().await;
+        let parent = setup.surface();
+        let (child, _) = setup.child(parent);
+
+        setup.attach(child);
+        setup.commit(child);
+        assert_eq!(setup.error().await, None);
+
+        assert!(!setup.committed(child));
+        assert!(setup.cached(child));
+
+        setup.commit(parent);
+        assert_eq!(setup.error().await, None);
+
+        let parent = setup.resource(parent);
+        let child = setup.resource(child);
+        assert_eq!(setup.fixture.state.commits, [parent, child]);
+        assert!(setup.compositor().surface(child).unwrap().has_buffer());
+    }
+
+    #[tokio::test]
+    async fn set_desync() {
+        let mut setup = Setup::new().await;
+        let parent = setup.surface();
+        let (child, child_sub) = setup.child(parent);
+        let (grandchild, grandchild_sub) = setup.child(child);
+
+        setup.commit(child);
+        setup.commit(grandchild);
+
+        // The child is still synchronized
+        setup
+            .client
+            .queue(grandchild_sub, wl_subsurface::Request::SetDesync);
+        assert_eq!(setup.error().await, None);
+        assert!(setup.cached(grandchild));
+        assert!(!setup.committed(grandchild));
+
+        setup
+            .client
+            .queue(child_sub, wl_subsurface::Request::SetDesync);
+        assert_eq!(setup.error().await, None);
+        assert!(!setup.cached(child));
+        assert!(setup.committed(child));
+    }
+
+    #[tokio::test]
+    async fn surface_tree() {
+        let mut setup = Setup::new().await;
+        let root = setup.surface();
+        let (above, above_sub) = setup.child(root);
+        let (below, below_sub) = setup.child(root);
+        let (nested, nested_sub) = setup.child(above);
+        // Skipped along with its children, as it has no buffer
+        let (hidden, _) = setup.child(root);
+        let (hidden_child, _) = setup.child(hidden);
+
+        for (subsurface, x, y) in [(above_sub, 10, 20), (below_sub, 5, 5), (nested_sub, 1, 2)] {
+            setup
+                .client
+                .queue(subsurface, wl_subsurface::Request::SetPosition { x, y });
+        }
+
+        setup.client.queue(
+            below_sub,
+            wl_subsurface::Request::PlaceBelow { sibling: root },
+        );
+
+        let commits = [
+            (nested, true),
+            (above, true),
+            (below, true),
+            (hidden_child, true),
+            (hidden, false),
+            (root, true),
+        ];
+
+        for (surface, buffer) in commits {
+            if buffer {
+                setup.attach(surface);
+            }
+
+            setup.commit(surface);
+        }
+
+        assert_eq!(setup.error().await, None);
+
+        let tree: Vec<_> = setup
+            .compositor()
+            .surface_tree(setup.resource(root))
+            .collect();
+        let expected = [
+            (below, Point::new(5, 5)),
+            (root, Point::new(0, 0)),
+            (above, Point::new(10, 20)),
+            (nested, Point::new(11, 22)),
+        ]
+        .map(|(surface, location)| (setup.resource(surface), location));
+
+        assert_eq!(tree, expected);
+    }
+}
@@ -0,0 +1,190 @@
+use super::{region::Region, subsurface::Subsurface};
+use crate::{
+    geometry::{Point, Rectangle},
+    protocol::wayland::wl_output::Transform,
+    server::{callback::Callback, client::Resource, output::OutputId},
+};
+use std::mem;
+
+/// Buffer change requested by `wl_surface.attach`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferAssignment {
+    /// The surface content is removed.
+    Removed,
+    /// A new buffer is attached.
+    New(Resource),
+}
+
+/// Damaged area, in the coordinate space the client used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Damage {
+    Surface(Rectangle),
+    Buffer(Rectangle),
+}
+
+/// Double-buffered state of a surface.
+#[derive(Debug, Clone)]
+pub struct SurfaceState {
+    /// Buffer change, `None` if the buffer is left as is.
+    pub buffer: Option<BufferAssignment>,
+    /// Position of the new buffer relative to the previous one.
+    pub buffer_offset: Point,
+    pub buffer_transform: Transform,
+    pub buffer_scale: i32,
+    /// Damage accumulated since the compositor last took it.
+    pub damage: Vec<Damage>,
+    /// Area known to be opaque, `None` for nothing.
+    pub opaque_region: Option<Region>,
+    /// Area accepting input, `None` for the whole surface.
+    pub input_region: Option<Region>,
+    /// Callbacks to complete when the compositor next presents the surface.
+    pub frame_callbacks: Vec<Callback>,
+}
+
+impl Default for SurfaceState {
+    fn default() -> Self {
+        SurfaceState {
+            buffer: None,
+            buffer_offset: Point::default(),
+            buffer_transform: Transform::Normal,
+            buffer_scale: 1,
+            damage: Vec::new(),
+            opaque_region: None,
+            input_region: None,
+            frame_callbacks: Vec::new(),
+        }
+    }
+}
+
+impl SurfaceState {
+    /// Moves this state into `target`, as done on commit.
+    ///
+    /// Buffer changes, offsets, damage and frame callbacks are consumed, the
+    /// other attributes keep their values.
+    pub fn merge_into(&mut self, target: &mut SurfaceState) {
+        if let Some(buffer) = self.buffer.take() {
+            target.buffer = Some(buffer);
+        }
+
+        target.buffer_offset = mem::take(&mut self.buffer_offset);
+        target.buffer_transform = self.buffer_transform;
+        target.buffer_scale = self.buffer_scale;
+        target.damage.append(&mut self.damage);
+        target.opaque_region = self.opaque_region.clone();
+        target.input_region = self.input_region.clone();
+        target.frame_callbacks.append(&mut self.frame_callbacks);
+    }
+}
+
+/// A `wl_surface` with its pending and current state.
+#[derive(Debug)]
+pub struct Surface {
+    resource: Resource,
+    role: Option<&'static str>,
+
+    pub(crate) pending: SurfaceState,
+    pub(crate) current: SurfaceState,
+    pub(crate) subsurface: Option<Subsurface>,
+    /// Stacking order of this surface and its subsurfaces, bottom to top.
+    pub(crate) pending_stack: Vec<Resource>,
+    pub(crate) stack: Vec<Resource>,
+    /// Outputs the surface was told it is shown on.
+    pub(crate) outputs: Vec<OutputId>,
+}
+
+impl Surface {
+    pub(crate) fn new(resource: Resource) -> Self {
+        Surface {
+            resource,
+            role: None,
+            pending: SurfaceState::default(),
+            current: SurfaceState::default(),
+            subsurface: None,
+            pending_stack: vec![resource],
+            stack: vec![resource],
+            outputs: Vec::new(),
+        }
+    }
+
+    /// Returns the `wl_surface` object.
+    pub fn resource(&self) -> Resource {
+        self.resource
+    }
+
+    /// Returns the role of the surface, once assigned.
+    pub fn role(&self) -> Option<&'static str> {
+        self.role
+    }
+
+    /// Assigns a role to the surface.
+    ///
+    /// Returns `false` if the surface already has a different role, a role
+    /// can never be changed.
+    pub fn set_role(&mut self, role: &'static str) -> bool {
+        match self.role {
+            Some(current) => current == role,
+            None => {
+                self.role = Some(role);
+                true
+            }
+        }
+    }
+
+    /// Returns the state that the next commit applies.
+    pub fn pending(&self) -> &SurfaceState {
+        &self.pending
+    }
+
+    /// Returns the committed state.
+    pub fn current(&self) -> &SurfaceState {
+        &self.current
+    }
+
+    /// Returns the committed state, mutably, for instance to take the damage.
+    pub fn current_mut(&mut self) -> &mut SurfaceState {
+        &mut self.current
+    }
+
+    /// Returns the subsurface attributes, while the surface is a subsurface.
+    pub fn subsurface(&self) -> Option<&Subsurface> {
+        self.subsurface.as_ref()
+    }
+
+    /// Returns the committed stacking order of this surface and its
+    /// subsurfaces, bottom to top.
+    pub fn stack(&self) -> &[Resource] {
+        &self.stack
+    }
+
+    /// Returns the outputs the surface entered.
+    pub fn outputs(&self) -> &[OutputId] {
+        &self.outputs
+    }
+
+    /// Returns `true` if a buffer is attached in the committed state.
+    pub fn has_buffer(&self) -> bool {
+        matches!(self.current.buffer, Some(BufferAssignment::New(_)))
+    }
+
+    /// Takes the committed frame callbacks, to complete once the surface was presented.
+    pub fn take_frame_callbacks(&mut self) -> Vec<Callback> {
+        mem::take(&mut self.current.frame_callbacks)
+    }
+
+    /// Takes the frame callbacks of the pending, cached and committed states.
+    pub(super) fn take_all_frame_callbacks(&mut self) -> Vec<Callback> {
+        let cached = self
+            .subsurface
+            .as_mut()
+            .and_then(|subsurface| subsurface.cached.as_mut());
+
+        let mut callbacks = mem::take(&mut self.current.frame_callbacks);
+
+        if let Some(cached) = cached {
+            callbacks.append(&mut cached.frame_callbacks);
+        }
+
+        callbacks.append(&mut self.pending.frame_callbacks);
+        callbacks
+    }
+}
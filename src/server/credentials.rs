@@ -0,0 +1,78 @@
+use rustix::net::sockopt;
+use std::{
+    io, mem,
+    os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd},
+};
+
+/// Credentials of the process on the other end of a connection.
+///
+/// They are captured by the kernel when the peer connected, not when queried.
+#[derive(Debug)]
+pub struct Credentials {
+    pid: i32,
+    uid: u32,
+    gid: u32,
+    pidfd: Option<OwnedFd>,
+}
+
+impl Credentials {
+    /// Queries the peer credentials of a connected Unix socket.
+    pub fn from_socket(socket: impl AsFd) -> io::Result<Self> {
+        let socket = socket.as_fd();
+        let cred = sockopt::socket_peercred(socket)?;
+
+        Ok(Credentials {
+            pid: cred.pid.as_raw_nonzero().get(),
+            uid: cred.uid.as_raw(),
+            gid: cred.gid.as_raw(),
+            pidfd: peer_pidfd(socket),
+        })
+    }
+
+    /// Returns the process id of the peer.
+    ///
+    /// The process may have exited and the id been reused since, use
+    /// [`Credentials::pidfd`] to refer to the process reliably.
+    pub fn pid(&self) -> i32 {
+        self.pid
+    }
+
+    /// Returns the effective user id of the peer.
+    pub fn uid(&self) -> u32 {
+        self.uid
+    }
+
+    /// Returns the effective group id of the peer.
+    pub fn gid(&self) -> u32 {
+        self.gid
+    }
+
+    /// Returns a pidfd of the peer process, if the kernel supports `SO_PEERPIDFD`.
+    pub fn pidfd(&self) -> Option<BorrowedFd<'_>> {
+        self.pidfd.as_ref().map(|fd| fd.as_fd())
+    }
+}
+
+/// Returns the pidfd of the peer, or `None` before Linux 6.5.
+fn peer_pidfd(socket: BorrowedFd<'_>) -> Option<OwnedFd> {
+    let mut fd: libc::c_int = -1;
+    let mut len = mem::size_of::<libc::c_int>() as libc::socklen_t;
+
+    // SAFETY: `fd` and `len` describe a valid buffer of the size the option expects
+    let ret = unsafe {
+        libc::getsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERPIDFD,
+            (&raw mut fd).cast(),
+            &mut len,
+        )
+    };
+
+    if ret != 0 || fd < 0 {
+        return None;
+    }
+
+    // SAFETY: the kernel returned a new file descriptor that we now own
+    Some(unsafe { OwnedFd::from_raw_fd(fd) })
+}
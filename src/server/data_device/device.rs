@@ -0,0 +1,163 @@
+use super::{
+    DataDeviceHandler, Selection,
+    dnd::{DragGrab, ICON_ROLE},
+    keyboard_focus,
+};
+use crate::{
+    protocol::{
+        MessageGroup,
+        wayland::{wl_data_device, wl_data_source},
+    },
+    server::{
+        client::Resource,
+        display::{Dispatch, DisplayHandle},
+        error::ProtocolError,
+        seat::SeatState,
+    },
+    wire::Message,
+};
+
+pub(super) struct DataDeviceDispatch;
+
+impl<D: DataDeviceHandler> Dispatch<D> for DataDeviceDispatch {
+    fn request(
+        &mut self,
+        state: &mut D,
+        handle: &mut DisplayHandle,
+        resource: Resource,
+        message: Message,
+    ) -> Result<(), ProtocolError> {
+        let request = wl_data_device::Request::from_message(message)
+            .map_err(|err| ProtocolError::malformed(resource.id, err))?;
+
+        let Some(&seat) = state.data_device_state().devices.get(&resource) else {
+            return Ok(());
+        };
+
+        match request {
+            wl_data_device::Request::SetSelection { source, serial: _ } => {
+                // Only the client with keyboard focus may set the selection
+                let focus = keyboard_focus(state, seat);
+
+                if focus != Some(resource.client) {
+                    return Ok(());
+                }
+
+                let data = state.data_device_state();
+                let source = source.map(|id| Resource::new(resource.client, id));
+
+                if let Some(source) = source {
+                    let Some(source_data) = data.sources.get_mut(&source) else {
+                        return Err(ProtocolError::invalid_object(source.id.get()));
+                    };
+
+                    if source_data.actions.is_some() {
+                        return Err(ProtocolError::new(
+                            source.id,
+                            wl_data_source::Error::InvalidSource,
+                            "drag-and-drop source cannot be the selection",
+                        ));
+                    }
+
+                    if source_data.used {
+                        return Err(ProtocolError::new(
+                            resource.id,
+                            wl_data_device::Error::UsedSource,
+                            format!("wl_data_source@{} was already used", source.id),
+                        ));
+                    }
+
+                    source_data.used = true;
+                }
+
+                data.replace_selection(handle, seat, source.map(Selection::Client), focus);
+                state.selection_changed(handle, seat);
+            }
+            wl_data_device::Request::StartDrag {
+                source,
+                origin,
+                icon,
+                serial,
+            } => {
+                let source = source.map(|id| Resource::new(resource.client, id));
+                let origin = Resource::new(resource.client, origin);
+                let icon = icon.map(|id| Resource::new(resource.client, id));
+
+                if let Some(source) = source {
+                    let Some(source_data) = state.data_device_state().sources.get_mut(&source)
+                    else {
+                        return Err(ProtocolError::invalid_object(source.id.get()));
+                    };
+
+                    if source_data.used {
+                        return Err(ProtocolError::new(
+                            resource.id,
+                            wl_data_device::Error::UsedSource,
+                            format!("wl_data_source@{} was already used", source.id),
+                        ));
+                    }
+
+                    source_data.used = true;
+                }
+
+                if let Some(icon) = icon {
+                    let Some(surface) = state.compositor_state().surface(icon) else {
+                        return Err(ProtocolError::invalid_object(icon.id.get()));
+                    };
+
+                    if surface.role().is_some_and(|role| role != ICON_ROLE) {
+                        return Err(ProtocolError::new(
+                            resource.id,
+                            wl_data_device::Error::Role,
+                            format!("wl_surface@{} already has another role", icon.id),
+                        ));
+                    }
+                }
+
+                // Only a button held down since a press on the origin starts a drag
+                let dragging = state.data_device_state().drags.contains_key(&seat);
+                let Some(data) = state.seat_state().seat_mut(seat) else {
+                    return Ok(());
+                };
+
+                let pointer = data.pointer();
+                let grabbed = pointer.button_serial() == Some(serial)
+                    && !pointer.pressed_buttons().is_empty()
+                    && pointer.focus() == Some(origin)
+                    && !pointer.is_grabbed()
+                    && !dragging;
+
+                if !grabbed {
+                    if let Some(source) = source {
+                        handle.send_event(source, wl_data_source::Event::Cancelled);
+                    }
+
+                    return Ok(());
+                }
+
+                let location = pointer.location();
+                data.clear_pointer_focus(handle);
+
+                // The icon only takes its role once the drag actually starts
+                if let Some(icon) = icon
+                    && let Some(surface) = state.compositor_state().surface_mut(icon)
+                {
+                    surface.set_role(ICON_ROLE);
+                }
+
+                state
+                    .data_device_state()
+                    .start_drag(handle, seat, source, origin, icon, location);
+                SeatState::set_pointer_grab(state, handle, seat, DragGrab);
+                state.drag_started(handle, seat);
+            }
+            wl_data_device::Request::Release => {}
+        }
+
+        Ok(())
+    }
+
+    fn destroyed(&mut self, state: &mut D, _handle: &mut DisplayHandle, resource: Resource) {
+        state.data_device_state().devices.remove(&resource);
+    }
+}
@@ -0,0 +1,482 @@
+use super::{DataDeviceHandler, DataDeviceState, DataOffer, OfferSource};
+use crate::{
+    protocol::wayland::{
+        wl_data_device, wl_data_device_manager::DndAction, wl_data_offer, wl_data_source,
+        wl_pointer::ButtonState,
+    },
+    server::{
+        client::Resource,
+        display::DisplayHandle,
+        seat::{GrabStatus, PointerGrab, SeatId, SeatState},
+    },
+    wire::Fixed,
+};
+
+/// Role of surfaces used as drag-and-drop icons.
+pub const ICON_ROLE: &str = "dnd_icon";
+
+/// First version of `wl_data_offer` negotiating actions, older ones copy.
+const ACTIONS_VERSION: u32 = 3;
+
+/// A drag-and-drop operation started with `wl_data_device.start_drag`.
+#[derive(Debug)]
+pub struct Drag {
+    source: Option<Resource>,
+    origin: Resource,
+    icon: Option<Resource>,
+    /// Surface under the pointer accepting the drag.
+    target: Option<Resource>,
+    /// Offers made to the client of the target.
+    offers: Vec<Resource>,
+}
+
+impl Drag {
+    /// Returns the `wl_data_source`, `None` for drags within a client.
+    pub fn source(&self) -> Option<Resource> {
+        self.source
+    }
+
+    /// Returns the surface the drag started from.
+    pub fn origin(&self) -> Resource {
+        self.origin
+    }
+
+    /// Returns the surface to draw under the pointer during the drag.
+    pub fn icon(&self) -> Option<Resource> {
+        self.icon
+    }
+
+    /// Returns the surface the drag is over.
+    pub fn target(&self) -> Option<Resource> {
+        self.target
+    }
+}
+
+/// Drag-and-drop state of a `wl_data_offer`.
+#[derive(Debug)]
+pub(super) struct DndOffer {
+    pub(super) source_actions: DndAction,
+    /// Actions supported by the destination.
+    pub(super) actions: DndAction,
+    pub(super) preferred: DndAction,
+    /// Action chosen among those of both sides.
+    pub(super) action: DndAction,
+    pub(super) accepted: Option<String>,
+    pub(super) dropped: bool,
+    /// The offer predates actions and `finish`, it always copies.
+    pub(super) legacy: bool,
+}
+
+impl DndOffer {
+    /// Returns the action the source and destination agree on, preferably
+    /// the one preferred by the destination.
+    pub(super) fn choose_action(&self) -> DndAction {
+        let available = self.source_actions & self.actions;
+
+        if available.contains(self.preferred) && !self.preferred.is_empty() {
+            return self.preferred;
+        }
+
+        // Otherwise the first action in the order copy, move, ask
+        DndAction::from_bits_retain(available.bits() & available.bits().wrapping_neg())
+    }
+
+    /// Returns `true` if the destination accepted the data with an action.
+    fn can_drop(&self) -> bool {
+        self.accepted.is_some() && !self.action.is_empty()
+    }
+}
+
+/// Routes the pointer to the drag-and-drop operation of a seat.
+pub(super) struct DragGrab;
+
+impl<D: DataDeviceHandler> PointerGrab<D> for DragGrab {
+    fn motion(
+        &mut self,
+        state: &mut D,
+        handle: &mut DisplayHandle,
+        seat: SeatId,
+        time: u32,
+        focus: Option<Resource>,
+        location: (f64, f64),
+    ) {
+        state
+            .data_device_state()
+            .drag_motion(handle, seat, time, focus, location);
+    }
+
+    fn button(
+        &mut self,
+        state: &mut D,
+        handle: &mut DisplayHandle,
+        seat: SeatId,
+        _time: u32,
+        _button: u32,
+        _button_state: ButtonState,
+    ) -> GrabStatus {
+        // The drop happens once all buttons are released
+        let released = state
+            .seat_state()
+            .seat(seat)
+            .is_none_or(|data| data.pointer().pressed_buttons().is_empty());
+
+        if !released {
+            return GrabStatus::Continue;
+        }
+
+        state.data_device_state().drag_drop(handle, seat);
+        state.drag_ended(handle, seat);
+
+        GrabStatus::End
+    }
+
+    fn ended(&mut self, state: &mut D, handle: &mut DisplayHandle, seat: SeatId) {
+        // Still going when the grab was unset or replaced
+        if state.data_device_state().drags.contains_key(&seat) {
+            state.data_device_state().drag_cancel(handle, seat);
+            state.drag_ended(handle, seat);
+        }
+    }
+}
+
+impl DataDeviceState {
+    /// Returns the drag-and-drop operation of a seat.
+    pub fn drag(&self, seat: SeatId) -> Option<&Drag> {
+        self.drags.get(&seat)
+    }
+
+    /// Cancels the drag-and-drop operation of a seat, for instance when
+    /// Escape is pressed.
+    pub fn cancel_drag<D: DataDeviceHandler>(
+        state: &mut D,
+        handle: &mut DisplayHandle,
+        seat: SeatId,
+    ) {
+        if state.data_device_state().drags.contains_key(&seat) {
+            SeatState::unset_pointer_grab(state, handle, seat);
+        }
+    }
+
+    /// Starts a drag-and-drop operation, entering `origin` at a
+    /// surface-local `location`.
+    pub(super) fn start_drag(
+        &mut self,
+        handle: &mut DisplayHandle,
+        seat: SeatId,
+        source: Option<Resource>,
+        origin: Resource,
+        icon: Option<Resource>,
+        location: (f64, f64),
+    ) {
+        let drag = Drag {
+            source,
+            origin,
+            icon,
+            target: None,
+            offers: Vec::new(),
+        };
+
+        self.drags.insert(seat, drag);
+        self.drag_enter(handle, seat, origin, location);
+    }
+
+    fn drag_motion(
+        &mut self,
+        handle: &mut DisplayHandle,
+        seat: SeatId,
+        time: u32,
+        focus: Option<Resource>,
+        (x, y): (f64, f64),
+    ) {
+        let Some(drag) = self.drags.get(&seat) else {
+            return;
+        };
+
+        // Without a source, only the client that started the drag sees it
+        let focus =
+            focus.filter(|focus| drag.source.is_some() || focus.client == drag.origin.client);
+
+        if drag.target != focus {
+            self.drag_leave(handle, seat);
+
+            if let Some(focus) = focus {
+                self.drag_enter(handle, seat, focus, (x, y));
+            }
+
+            return;
+        }
+
+        if let Some(target) = focus {
+            for device in self.client_devices(seat, target.client) {
+                handle.send_event(
+                    device,
+                    wl_data_device::Event::Motion {
+                        time,
+                        x: Fixed::from_f64(x),
+                        y: Fixed::from_f64(y),
+                    },
+                );
+            }
+        }
+    }
+
+    /// Sends `enter` to the data devices of the client of `target`, each
+    /// with a new offer of the source.
+    fn drag_enter(
+        &mut self,
+        handle: &mut DisplayHandle,
+        seat: SeatId,
+        target: Resource,
+        (x, y): (f64, f64),
+    ) {
+        let Some(source) = self.drags.get(&seat).map(|drag| drag.source) else {
+            return;
+        };
+
+        let source = source.and_then(|source| {
+            let data = self.sources.get(&source)?;
+            let actions = data.actions.unwrap_or(DndAction::COPY);
+            Some((source, data.mime_types.clone(), actions))
+        });
+
+        let serial = handle.next_serial();
+        let mut offers = Vec::new();
+
+        for device in self.client_devices(seat, target.client) {
+            let offer = source.clone().and_then(|(source, mime_types, actions)| {
+                let legacy = handle.version(device) < ACTIONS_VERSION;
+                let offer = DataOffer::new_dnd(seat, source, mime_types, actions, legacy);
+                let id = self.create_offer(handle, device, offer)?;
+
+                Some(Resource::new(device.client, id))
+            });
+
+            if let Some(offer) = offer {
+                let source_actions = source.as_ref().map(|(_, _, actions)| *actions);
+
+                if let Some(source_actions) = source_actions {
+                    handle.send_event(
+                        offer,
+                        wl_data_offer::Event::SourceActions { source_actions },
+                    );
+                }
+
+                offers.push(offer);
+            }
+
+            handle.send_event(
+                device,
+                wl_data_device::Event::Enter {
+                    serial,
+                    surface: target.id,
+                    x: Fixed::from_f64(x),
+                    y: Fixed::from_f64(y),
+                    id: offer.map(|offer| offer.id),
+                },
+            );
+        }
+
+        if let Some(drag) = self.drags.get_mut(&seat) {
+            drag.target = Some(target);
+            drag.offers = offers;
+        }
+    }
+
+    /// Sends `leave` to the data devices of the target, whose offers become
+    /// invalid.
+    fn drag_leave(&mut self, handle: &mut DisplayHandle, seat: SeatId) {
+        let Some(drag) = self.drags.get_mut(&seat) else {
+            return;
+        };
+
+        let Some(target) = drag.target.take() else {
+            return;
+        };
+
+        let offers = std::mem::take(&mut drag.offers);
+        let source = drag.source;
+
+        for device in self.client_devices(seat, target.client) {
+            handle.send_event(device, wl_data_device::Event::Leave);
+        }
+
+        for offer in offers {
+            if let Some(offer) = self.offers.get_mut(&offer) {
+                offer.source = None;
+            }
+        }
+
+        if let Some(source) = source {
+            handle.send_event(source, wl_data_source::Event::Target { mime_type: None });
+        }
+    }
+
+    /// Drops the data on the target if it accepted it, cancels the operation
+    /// otherwise.
+    fn drag_drop(&mut self, handle: &mut DisplayHandle, seat: SeatId) {
+        let Some(drag) = self.drags.get(&seat) else {
+            return;
+        };
+
+        let accepted = drag.offers.iter().copied().find(|offer| {
+            self.offers
+                .get(offer)
+                .and_then(|offer| offer.dnd.as_ref())
+                .is_some_and(DndOffer::can_drop)
+        });
+
+        // Drags within a client need no agreement from the compositor
+        let target = drag
+            .target
+            .filter(|_| accepted.is_some() || drag.source.is_none());
+
+        let Some(target) = target else {
+            self.drag_cancel(handle, seat);
+            return;
+        };
+
+        let drag = self.drags.remove(&seat).expect("drag exists");
+
+        for device in self.client_devices(seat, target.client) {
+            handle.send_event(device, wl_data_device::Event::Drop);
+        }
+
+        for offer in drag.offers {
+            let Some(data) = self.offers.get_mut(&offer) else {
+                continue;
+            };
+
+            match Some(offer) == accepted {
+                true => data.dnd.as_mut().expect("offer is from a drag").dropped = true,
+                false => data.source = None,
+            }
+        }
+
+        if let Some(source) = drag.source {
+            handle.send_event(source, wl_data_source::Event::DndDropPerformed);
+        }
+    }
+
+    /// Ends the drag-and-drop operation of a seat without dropping.
+    fn drag_cancel(&mut self, handle: &mut DisplayHandle, seat: SeatId) {
+        self.drag_leave(handle, seat);
+
+        if let Some(drag) = self.drags.remove(&seat)
+            && let Some(source) = drag.source
+        {
+            handle.send_event(source, wl_data_source::Event::Cancelled);
+        }
+    }
+}
+
+impl DataOffer {
+    fn new_dnd(
+        seat: SeatId,
+        source: Resource,
+        mime_types: Vec<String>,
+        source_actions: DndAction,
+        legacy: bool,
+    ) -> Self {
+        let mut offer = DataOffer::new(seat, OfferSource::Client(source), mime_types);
+
+        offer.dnd = Some(DndOffer {
+            source_actions,
+            actions: DndAction::empty(),
+            preferred: DndAction::empty(),
+            action: match legacy {
+                true => DndAction::COPY,
+                false => DndAction::empty(),
+            },
+            accepted: None,
+            dropped: false,
+            legacy,
+        });
+
+        offer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        server::data_device::{VERSION, tests::Setup},
+        testing::events_of,
+    };
+
+    fn choose_action(source: DndAction, destination: DndAction, preferred: DndAction) -> DndAction {
+        let offer = DndOffer {
+            source_actions: source,
+            actions: destination,
+            preferred,
+            action: DndAction::empty(),
+            accepted: None,
+            dropped: false,
+            legacy: false,
+        };
+
+        offer.choose_action()
+    }
+
+    #[test]
+    fn preferred_action() {
+        let all = DndAction::all();
+
+        assert_eq!(choose_action(all, all, DndAction::MOVE), DndAction::MOVE);
+        assert_eq!(choose_action(all, all, DndAction::ASK), DndAction::ASK);
+    }
+
+    #[test]
+    fn fallback_action() {
+        let all = DndAction::all();
+        let copy_move = DndAction::COPY | DndAction::MOVE;
+        let move_ask = DndAction::MOVE | DndAction::ASK;
+
+        // The preferred action is not supported by the source
+        assert_eq!(
+            choose_action(copy_move, all, DndAction::ASK),
+            DndAction::COPY
+        );
+        assert_eq!(
+            choose_action(move_ask, all, DndAction::empty()),
+            DndAction::MOVE
+        );
+        assert_eq!(
+            choose_action(all, DndAction::ASK, DndAction::COPY),
+            DndAction::ASK
+        );
+        assert_eq!(
+            choose_action(DndAction::COPY, DndAction::MOVE, DndAction::MOVE),
+            DndAction::empty(),
+        );
+    }
+
+    #[tokio::test]
+    async fn source_destroyed() {
+        let mut setup = Setup::new([VERSION; 2]).await;
+        let (source, _) = setup.drag(None).await;
+
+        setup.peers[0]
+            .client
+            .queue(source, wl_data_source::Request::Destroy);
+        setup.events(0).await;
+        let mut events = setup.events(1).await;
+
+        let device = setup.peers[1].device;
+        let leave = events_of(&mut events, device);
+        assert!(matches!(leave[..], [wl_data_device::Event::Leave]));
+
+        let seat = setup.fixture.seat;
+        assert!(setup.fixture.state.data_device.drag(seat).is_none());
+        assert!(
+            !setup
+                .fixture
+                .state
+                .seat
+                .seat(seat)
+                .unwrap()
+                .pointer()
+                .is_grabbed()
+        );
+    }
+}
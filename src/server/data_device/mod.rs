@@ -0,0 +1,657 @@
+mod device;
+mod dnd;
+mod offer;
+mod source;
+
+pub use self::{
+    dnd::{Drag, ICON_ROLE},
+    offer::DataOffer,
+    source::DataSource,
+};
+
+use self::{device::DataDeviceDispatch, offer::DataOfferDispatch, source::DataSourceDispatch};
+use super::{
+    client::{ClientId, Resource},
+    display::{Dispatch, Display, DisplayHandle},
+    error::ProtocolError,
+    seat::{SeatHandler, SeatId},
+};
+use crate::{
+    protocol::{
+        MessageGroup,
+        wayland::{wl_data_device, wl_data_device_manager, wl_data_offer, wl_data_source},
+    },
+    wire::Message,
+};
+use std::{collections::HashMap, os::fd::OwnedFd};
+
+/// Version of the `wl_data_device_manager` global.
+const VERSION: u32 = 3;
+
+/// Owner of the selection of a seat.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Selection {
+    /// A `wl_data_source` set by a client.
+    Client(Resource),
+    /// The compositor, which provides the data through
+    /// [`DataDeviceHandler::send_selection`].
+    Compositor { mime_types: Vec<String> },
+}
+
+/// State of the `wl_data_device_manager` global.
+pub struct DataDeviceState {
+    sources: HashMap<Resource, DataSource>,
+    offers: HashMap<Resource, DataOffer>,
+    /// Seat of each `wl_data_device`.
+    devices: HashMap<Resource, SeatId>,
+    selections: HashMap<SeatId, Selection>,
+    drags: HashMap<SeatId, Drag>,
+}
+
+/// Access to [`DataDeviceState`] and data transfers.
+pub trait DataDeviceHandler: SeatHandler {
+    /// Returns the data device state.
+    fn data_device_state(&mut self) -> &mut DataDeviceState;
+
+    /// Called when a client set or cleared the selection of a seat, or the
+    /// source of the selection was destroyed.
+    fn selection_changed(&mut self, handle: &mut DisplayHandle, seat: SeatId) {
+        let _ = (handle, seat);
+    }
+
+    /// Called when a client reads a selection owned by the compositor.
+    ///
+    /// The data is written to `fd`, which is closed once done. It should be
+    /// written without blocking the display, for instance from a task. The
+    /// default implementation closes it right away.
+    fn send_selection(
+        &mut self,
+        handle: &mut DisplayHandle,
+        seat: SeatId,
+        mime_type: String,
+        fd: OwnedFd,
+    ) {
+        let _ = (handle, seat, mime_type, fd);
+    }
+
+    /// Called when a client started a drag-and-drop operation, see
+    /// [`DataDeviceState::drag`] for its icon.
+    fn drag_started(&mut self, handle: &mut DisplayHandle, seat: SeatId) {
+        let _ = (handle, seat);
+    }
+
+    /// Called when a drag-and-drop operation was dropped or cancelled.
+    fn drag_ended(&mut self, handle: &mut DisplayHandle, seat: SeatId) {
+        let _ = (handle, seat);
+    }
+}
+
+impl DataDeviceState {
+    /// Creates the `wl_data_device_manager` global.
+    pub fn new<D: DataDeviceHandler>(display: &mut Display<D>) -> Self {
+        display.register(&wl_data_device_manager::INTERFACE, ManagerDispatch);
+        display.register(&wl_data_device::INTERFACE, DataDeviceDispatch);
+        display.register(&wl_data_source::INTERFACE, DataSourceDispatch);
+        display.register(&wl_data_offer::INTERFACE, DataOfferDispatch);
+
+        display.create_global(
+            &wl_data_device_manager::INTERFACE,
+            VERSION,
+            |_, _, _| Ok(()),
+        );
+
+        DataDeviceState {
+            sources: HashMap::new(),
+            offers: HashMap::new(),
+            devices: HashMap::new(),
+            selections: HashMap::new(),
+            drags: HashMap::new(),
+        }
+    }
+
+    /// Returns the selection of a seat.
+    pub fn selection(&self, seat: SeatId) -> Option<&Selection> {
+        self.selections.get(&seat)
+    }
+
+    /// Returns a `wl_data_source`.
+    pub fn source(&self, source: Resource) -> Option<&DataSource> {
+        self.sources.get(&source)
+    }
+
+    /// Returns a `wl_data_offer`.
+    pub fn offer(&self, offer: Resource) -> Option<&DataOffer> {
+        self.offers.get(&offer)
+    }
+
+    /// Takes the selection of a seat on behalf of the compositor, offering
+    /// `mime_types` to the client with keyboard focus.
+    ///
+    /// Clients read it through [`DataDeviceHandler::send_selection`].
+    pub fn set_selection<D: DataDeviceHandler>(
+        state: &mut D,
+        handle: &mut DisplayHandle,
+        seat: SeatId,
+        mime_types: Vec<String>,
+    ) {
+        let focus = keyboard_focus(state, seat);
+
+        state.data_device_state().replace_selection(
+            handle,
+            seat,
+            Some(Selection::Compositor { mime_types }),
+            focus,
+        );
+    }
+
+    /// Clears the selection of a seat, cancelling its client source if any.
+    pub fn clear_selection<D: DataDeviceHandler>(
+        state: &mut D,
+        handle: &mut DisplayHandle,
+        seat: SeatId,
+    ) {
+        let focus = keyboard_focus(state, seat);
+
+        state
+            .data_device_state()
+            .replace_selection(handle, seat, None, focus);
+    }
+
+    /// Moves the keyboard focus of a seat like [`Seat::set_keyboard_focus`],
+    /// sending the selection to the client gaining focus first.
+    ///
+    /// [`Seat::set_keyboard_focus`]: super::seat::Seat::set_keyboard_focus
+    pub fn set_keyboard_focus<D: DataDeviceHandler>(
+        state: &mut D,
+        handle: &mut DisplayHandle,
+        seat: SeatId,
+        focus: Option<Resource>,
+    ) {
+        let Some(data) = state.seat_state().seat(seat) else {
+            return;
+        };
+
+        let previous = data.keyboard().focus().map(|surface| surface.client);
+        let client = focus.map(|surface| surface.client);
+
+        if let Some(client) = client
+            && previous != Some(client)
+        {
+            state
+                .data_device_state()
+                .send_selection(handle, seat, client);
+        }
+
+        if let Some(data) = state.seat_state().seat_mut(seat) {
+            data.set_keyboard_focus(handle, focus);
+        }
+    }
+
+    /// Sets the selection of a seat, sending it to the client with keyboard focus.
+    fn replace_selection(
+        &mut self,
+        handle: &mut DisplayHandle,
+        seat: SeatId,
+        selection: Option<Selection>,
+        focus: Option<ClientId>,
+    ) {
+        let previous = match selection {
+            Some(selection) => self.selections.insert(seat, selection),
+            None => self.selections.remove(&seat),
+        };
+
+        if let Some(Selection::Client(source)) = previous
+            && self.selection(seat) != Some(&Selection::Client(source))
+            && self.sources.contains_key(&source)
+        {
+            handle.send_event(source, wl_data_source::Event::Cancelled);
+        }
+
+        // Offers of the previous selection no longer provide data
+        for offer in self.offers.values_mut() {
+            if offer.seat == seat && offer.dnd.is_none() {
+                offer.source = None;
+            }
+        }
+
+        if let Some(client) = focus {
+            self.send_selection(handle, seat, client);
+        }
+    }
+
+    /// Returns the data devices a client got for a seat.
+    fn client_devices(&self, seat: SeatId, client: ClientId) -> Vec<Resource> {
+        self.devices
+            .iter()
+            .filter(|(device, device_seat)| device.client == client && **device_seat == seat)
+            .map(|(device, _)| *device)
+            .collect()
+    }
+
+    /// Sends the selection of a seat to the data devices of a client.
+    fn send_selection(&mut self, handle: &mut DisplayHandle, seat: SeatId, client: ClientId) {
+        for device in self.client_devices(seat, client) {
+            self.send_device_selection(handle, seat, device);
+        }
+    }
+
+    /// Sends the selection of a seat to a data device, with a new offer.
+    fn send_device_selection(
+        &mut self,
+        handle: &mut DisplayHandle,
+        seat: SeatId,
+        device: Resource,
+    ) {
+        let offer = match self.selections.get(&seat) {
+            Some(Selection::Client(source)) => self
+                .sources
+                .get(source)
+                .map(|data| (OfferSource::Client(*source), data.mime_types.clone())),
+            Some(Selection::Compositor { mime_types }) => {
+                Some((OfferSource::Compositor, mime_types.clone()))
+            }
+            None => None,
+        };
+
+        let id = offer.and_then(|(source, mime_types)| {
+            self.create_offer(handle, device, DataOffer::new(seat, source, mime_types))
+        });
+
+        handle.send_event(device, wl_data_device::Event::Selection { id });
+    }
+}
+
+/// Where the data of an offer comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OfferSource {
+    Client(Resource),
+    Compositor,
+}
+
+/// Returns the client with keyboard focus on a seat.
+fn keyboard_focus<D: SeatHandler>(state: &mut D, seat: SeatId) -> Option<ClientId> {
+    let focus = state.seat_state().seat(seat)?.keyboard().focus()?;
+    Some(focus.client)
+}
+
+struct ManagerDispatch;
+
+impl<D: DataDeviceHandler> Dispatch<D> for ManagerDispatch {
+    fn request(
+        &mut self,
+        state: &mut D,
+        handle: &mut DisplayHandle,
+        resource: Resource,
+        message: Message,
+    ) -> Result<(), ProtocolError> {
+        let request = wl_data_device_manager::Request::from_message(message)
+            .map_err(|err| ProtocolError::malformed(resource.id, err))?;
+
+        match request {
+            wl_data_device_manager::Request::CreateDataSource { id } => {
+                let source = Resource::new(resource.client, id);
+                state
+                    .data_device_state()
+                    .sources
+                    .insert(source, DataSource::default());
+            }
+            wl_data_device_manager::Request::GetDataDevice { id, seat } => {
+                let device = Resource::new(resource.client, id);
+                let seat = Resource::new(resource.client, seat);
+
+                let Some(seat) = state.seat_state().seat_of(seat) else {
+                    return Err(ProtocolError::invalid_object(seat.id.get()));
+                };
+
+                let focused = keyboard_focus(state, seat) == Some(resource.client);
+                let data = state.data_device_state();
+                data.devices.insert(device, seat);
+
+                if focused {
+                    data.send_device_selection(handle, seat, device);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        protocol::wayland::{
+            wl_compositor, wl_data_device_manager::DndAction, wl_pointer::ButtonState, wl_seat,
+            wl_surface,
+        },
+        server::seat::SeatState,
+        testing::{Fixture, TestClient, events_of, protocol_error},
+        wire::ObjectId,
+    };
+    use std::io::{Read, Write};
+
+    /// A client with a data device and a surface.
+    pub(super) struct Peer {
+        pub(super) client: TestClient,
+        version: u32,
+        pub(super) device: ObjectId,
+        manager: ObjectId,
+        pub(super) surface: ObjectId,
+    }
+
+    pub(super) struct Setup {
+        pub(super) fixture: Fixture,
+        pub(super) peers: [Peer; 2],
+    }
+
+    impl Setup {
+        /// Connects two clients, with data devices of the given versions.
+        pub(super) async fn new(versions: [u32; 2]) -> Self {
+            let mut fixture = Fixture::new();
+            let mut peers = Vec::new();
+
+            for version in versions {
+                let mut client = fixture.connect().await;
+                let compositor = client.bind(&wl_compositor::INTERFACE, 6);
+                let seat = client.bind(&wl_seat::INTERFACE, 1);
+                let manager = client.bind(&wl_data_device_manager::INTERFACE, version);
+                let surface = client.create(&wl_surface::INTERFACE, 6);
+                let device = client.create(&wl_data_device::INTERFACE, version);
+
+                client.queue(
+                    compositor,
+                    wl_compositor::Request::CreateSurface { id: surface },
+                );
+                client.queue(
+                    manager,
+                    wl_data_device_manager::Request::GetDataDevice { id: device, seat },
+                );
+                fixture.roundtrip(&mut client).await;
+
+                peers.push(Peer {
+                    client,
+                    version,
+                    device,
+                    manager,
+                    surface,
+                });
+            }
+
+            Setup {
+                fixture,
+                peers: peers.try_into().ok().unwrap(),
+            }
+        }
+
+        pub(super) fn resource(&self, peer: usize, id: ObjectId) -> Resource {
+            Resource::new(self.peers[peer].client.id, id)
+        }
+
+        /// Gives the keyboard focus to the surface of a peer.
+        fn focus(&mut self, peer: usize) {
+            let surface = self.resource(peer, self.peers[peer].surface);
+
+            DataDeviceState::set_keyboard_focus(
+                &mut self.fixture.state,
+                self.fixture.display.handle_mut(),
+                self.fixture.seat,
+                Some(surface),
+            );
+        }
+
+        /// Creates a `wl_data_source` offering `mime_types`.
+        pub(super) fn source(&mut self, peer: usize, mime_types: &[&str]) -> ObjectId {
+            let peer = &mut self.peers[peer];
+            let source = peer.client.create(&wl_data_source::INTERFACE, peer.version);
+
+            peer.client.queue(
+                peer.manager,
+                wl_data_device_manager::Request::CreateDataSource { id: source },
+            );
+
+            for mime_type in mime_types {
+                peer.client.queue(
+                    source,
+                    wl_data_source::Request::Offer {
+                        mime_type: mime_type.to_string(),
+                    },
+                );
+            }
+
+            source
+        }
+
+        fn set_selection(&mut self, peer: usize, source: Option<ObjectId>) {
+            let peer = &mut self.peers[peer];
+            let request = wl_data_device::Request::SetSelection { source, serial: 0 };
+            peer.client.queue(peer.device, request);
+        }
+
+        /// Asks to receive an offer through a pipe, returning its read end.
+        fn receive(
+            &mut self,
+            peer: usize,
+            offer: ObjectId,
+            mime_type: &str,
+        ) -> std::io::PipeReader {
+            let (reader, writer) = std::io::pipe().unwrap();
+            let request = wl_data_offer::Request::Receive {
+                mime_type: mime_type.into(),
+                fd: writer.into(),
+            };
+            self.peers[peer].client.queue(offer, request);
+
+            reader
+        }
+
+        pub(super) async fn events(&mut self, peer: usize) -> Vec<Message> {
+            self.fixture.roundtrip(&mut self.peers[peer].client).await
+        }
+
+        /// Moves the pointer over the surface of a peer.
+        pub(super) fn motion(&mut self, peer: usize) {
+            let surface = self.resource(peer, self.peers[peer].surface);
+
+            SeatState::pointer_motion(
+                &mut self.fixture.state,
+                self.fixture.display.handle_mut(),
+                self.fixture.seat,
+                0,
+                Some(surface),
+                (1.0, 1.0),
+            );
+        }
+
+        /// Presses or releases the left button, returning the serial.
+        pub(super) fn button(&mut self, button_state: ButtonState) -> u32 {
+            SeatState::pointer_button(
+                &mut self.fixture.state,
+                self.fixture.display.handle_mut(),
+                self.fixture.seat,
+                0,
+                0x110,
+                button_state,
+            )
+            .unwrap()
+        }
+
+        /// Drags a source offering "text/plain" with `actions` from the first
+        /// peer over the second, returning the source and the offer the
+        /// second peer entered with.
+        pub(super) async fn drag(&mut self, actions: Option<DndAction>) -> (ObjectId, ObjectId) {
+            let source = self.source(0, &["text/plain"]);
+
+            if let Some(dnd_actions) = actions {
+                let request = wl_data_source::Request::SetActions { dnd_actions };
+                self.peers[0].client.queue(source, request);
+            }
+
+            self.motion(0);
+            let serial = self.button(ButtonState::Pressed);
+
+            let peer = &mut self.peers[0];
+            let request = wl_data_device::Request::StartDrag {
+                source: Some(source),
+                origin: peer.surface,
+                icon: None,
+                serial,
+            };
+            peer.client.queue(peer.device, request);
+            self.events(0).await;
+
+            self.motion(1);
+            let mut events = self.events(1).await;
+            let offer = events_of(&mut events, self.peers[1].device)
+                .into_iter()
+                .find_map(|event| match event {
+                    wl_data_device::Event::Enter { id, .. } => id,
+                    _ => None,
+                })
+                .expect("second peer entered");
+
+            (source, offer)
+        }
+
+        /// Returns the offer of the last selection sent to the data device of
+        /// a peer.
+        fn selection(&self, events: &mut Vec<Message>, peer: usize) -> Option<ObjectId> {
+            events_of(events, self.peers[peer].device)
+                .into_iter()
+                .filter_map(|event| match event {
+                    wl_data_device::Event::Selection { id } => Some(id),
+                    _ => None,
+                })
+                .next_back()
+                .flatten()
+        }
+    }
+
+    #[tokio::test]
+    async fn unfocused_selection() {
+        let mut setup = Setup::new([VERSION; 2]).await;
+        setup.focus(0);
+
+        let source = setup.source(1, &["text/plain"]);
+        setup.set_selection(1, Some(source));
+        setup.events(1).await;
+
+        let seat = setup.fixture.seat;
+        assert_eq!(setup.fixture.state.data_device.selection(seat), None);
+    }
+
+    #[tokio::test]
+    async fn replaced_selection() {
+        let mut setup = Setup::new([VERSION; 2]).await;
+        setup.focus(0);
+
+        let first = setup.source(0, &["text/plain"]);
+        setup.set_selection(0, Some(first));
+        let mut events = setup.events(0).await;
+        let offer = setup.selection(&mut events, 0).unwrap();
+        assert!(
+            setup
+                .fixture
+                .state
+                .data_device
+                .offer(setup.resource(0, offer))
+                .unwrap()
+                .is_valid()
+        );
+
+        let second = setup.source(0, &["text/plain"]);
+        setup.set_selection(0, Some(second));
+        let mut events = setup.events(0).await;
+
+        let cancelled = events_of(&mut events, first);
+        assert!(matches!(cancelled[..], [wl_data_source::Event::Cancelled]));
+
+        // The offer of the first source no longer provides data
+        let data = &setup.fixture.state.data_device;
+        assert!(!data.offer(setup.resource(0, offer)).unwrap().is_valid());
+
+        let seat = setup.fixture.seat;
+        let selection = Selection::Client(setup.resource(0, second));
+        assert_eq!(data.selection(seat), Some(&selection));
+
+        let _reader = setup.receive(0, offer, "text/plain");
+        let mut events = setup.events(0).await;
+        assert!(events_of::<wl_data_source::Event>(&mut events, second).is_empty());
+    }
+
+    #[tokio::test]
+    async fn used_source() {
+        let mut setup = Setup::new([VERSION; 2]).await;
+        setup.focus(0);
+
+        let source = setup.source(0, &["text/plain"]);
+        setup.set_selection(0, Some(source));
+        setup.set_selection(0, Some(source));
+        let mut events = setup.events(0).await;
+
+        assert_eq!(
+            protocol_error(&mut events),
+            Some((
+                setup.peers[0].device,
+                wl_data_device::Error::UsedSource.into()
+            )),
+        );
+    }
+
+    #[tokio::test]
+    async fn receive() {
+        let mut setup = Setup::new([VERSION; 2]).await;
+        setup.focus(0);
+
+        let source = setup.source(0, &["text/plain"]);
+        setup.set_selection(0, Some(source));
+        setup.events(0).await;
+
+        setup.focus(1);
+        let mut events = setup.events(1).await;
+        let offer = setup.selection(&mut events, 1).unwrap();
+
+        let mut reader = setup.receive(1, offer, "text/plain");
+        setup.events(1).await;
+
+        let mut events = setup.events(0).await;
+        let Some(wl_data_source::Event::Send { mime_type, fd }) =
+            events_of(&mut events, source).pop()
+        else {
+            panic!("source was not asked to send");
+        };
+        assert_eq!(mime_type, "text/plain");
+
+        // The source writes to the pipe of the receiving client
+        std::fs::File::from(fd).write_all(b"data").unwrap();
+        let mut data = [0; 4];
+        reader.read_exact(&mut data).unwrap();
+        assert_eq!(&data, b"data");
+    }
+
+    #[tokio::test]
+    async fn compositor_selection() {
+        let mut setup = Setup::new([VERSION; 2]).await;
+        setup.focus(0);
+        setup.events(0).await;
+
+        let seat = setup.fixture.seat;
+        DataDeviceState::set_selection(
+            &mut setup.fixture.state,
+            setup.fixture.display.handle_mut(),
+            seat,
+            vec![String::from("text/plain")],
+        );
+
+        let mut events = setup.events(0).await;
+        let offer = setup.selection(&mut events, 0).unwrap();
+        let _reader = setup.receive(0, offer, "text/plain");
+        setup.events(0).await;
+
+        let reads = &setup.fixture.state.selection_reads;
+        assert!(matches!(
+            &reads[..],
+            [(read_seat, mime_type, _)] if *read_seat == seat && mime_type == "text/plain",
+        ));
+    }
+}
@@ -0,0 +1,373 @@
+use super::{DataDeviceHandler, DataDeviceState, OfferSource, dnd::DndOffer};
+use crate::{
+    protocol::{
+        MessageGroup,
+        wayland::{
+            wl_data_device, wl_data_device_manager::DndAction, wl_data_offer, wl_data_source,
+        },
+    },
+    server::{
+        client::Resource,
+        display::{Dispatch, DisplayHandle},
+        error::ProtocolError,
+        seat::SeatId,
+    },
+    wire::{Message, ObjectId},
+};
+
+/// Data offered to a client through a `wl_data_offer`.
+#[derive(Debug)]
+pub struct DataOffer {
+    pub(super) seat: SeatId,
+    /// Where the data comes from, `None` once the offer is no longer valid.
+    pub(super) source: Option<OfferSource>,
+    mime_types: Vec<String>,
+    /// Drag-and-drop state, `None` for the selection.
+    pub(super) dnd: Option<DndOffer>,
+}
+
+impl DataOffer {
+    pub(super) fn new(seat: SeatId, source: OfferSource, mime_types: Vec<String>) -> Self {
+        DataOffer {
+            seat,
+            source: Some(source),
+            mime_types,
+            dnd: None,
+        }
+    }
+
+    pub fn seat(&self) -> SeatId {
+        self.seat
+    }
+
+    /// Returns the MIME types offered.
+    pub fn mime_types(&self) -> &[String] {
+        &self.mime_types
+    }
+
+    /// Returns `true` while the data can still be received.
+    pub fn is_valid(&self) -> bool {
+        self.source.is_some()
+    }
+
+    /// Returns the action chosen for a drag-and-drop offer.
+    pub fn dnd_action(&self) -> Option<DndAction> {
+        self.dnd.as_ref().map(|dnd| dnd.action)
+    }
+}
+
+impl DataDeviceState {
+    /// Creates a `wl_data_offer` for the client of `device`, introducing it
+    /// with `wl_data_device.data_offer` followed by its MIME types.
+    ///
+    /// Returns `None` if the client ran out of server ids.
+    pub(super) fn create_offer(
+        &mut self,
+        handle: &mut DisplayHandle,
+        device: Resource,
+        offer: DataOffer,
+    ) -> Option<ObjectId> {
+        let resource = handle.create_object(
+            device.client,
+            &wl_data_offer::INTERFACE,
+            handle.version(device),
+        )?;
+
+        handle.send_event(device, wl_data_device::Event::DataOffer { id: resource.id });
+
+        for mime_type in &offer.mime_types {
+            handle.send_event(
+                resource,
+                wl_data_offer::Event::Offer {
+                    mime_type: mime_type.clone(),
+                },
+            );
+        }
+
+        self.offers.insert(resource, offer);
+
+        Some(resource.id)
+    }
+}
+
+pub(super) struct DataOfferDispatch;
+
+impl<D: DataDeviceHandler> Dispatch<D> for DataOfferDispatch {
+    fn request(
+        &mut self,
+        state: &mut D,
+        handle: &mut DisplayHandle,
+        resource: Resource,
+        message: Message,
+    ) -> Result<(), ProtocolError> {
+        let request = wl_data_offer::Request::from_message(message)
+            .map_err(|err| ProtocolError::malformed(resource.id, err))?;
+
+        let Some(offer) = state.data_device_state().offers.get_mut(&resource) else {
+            return Ok(());
+        };
+
+        let not_dnd = |code: wl_data_offer::Error| {
+            ProtocolError::new(
+                resource.id,
+                code,
+                "offer is not from a drag-and-drop operation",
+            )
+        };
+
+        match request {
+            wl_data_offer::Request::Receive { mime_type, fd } => {
+                // Dropping the file descriptor closes it, ending the transfer
+                if !offer.mime_types.contains(&mime_type) {
+                    return Ok(());
+                }
+
+                match offer.source {
+                    Some(OfferSource::Client(source)) => {
+                        handle.send_event(source, wl_data_source::Event::Send { mime_type, fd });
+                    }
+                    Some(OfferSource::Compositor) => {
+                        let seat = offer.seat;
+                        state.send_selection(handle, seat, mime_type, fd);
+                    }
+                    None => {}
+                }
+            }
+            wl_data_offer::Request::Accept {
+                serial: _,
+                mime_type,
+            } => {
+                if let Some(OfferSource::Client(source)) = offer.source
+                    && let Some(dnd) = &mut offer.dnd
+                {
+                    dnd.accepted = mime_type.clone();
+                    handle.send_event(source, wl_data_source::Event::Target { mime_type });
+                }
+            }
+            wl_data_offer::Request::SetActions {
+                dnd_actions,
+                preferred_action,
+            } => {
+                let Some(dnd) = &mut offer.dnd else {
+                    return Err(not_dnd(wl_data_offer::Error::InvalidOffer));
+                };
+
+                if !DndAction::all().contains(dnd_actions) {
+                    return Err(ProtocolError::new(
+                        resource.id,
+                        wl_data_offer::Error::InvalidActionMask,
+                        "invalid drag-and-drop actions",
+                    ));
+                }
+
+                if !DndAction::all().contains(preferred_action)
+                    || preferred_action.bits().count_ones() > 1
+                {
+                    return Err(ProtocolError::new(
+                        resource.id,
+                        wl_data_offer::Error::InvalidAction,
+                        "preferred action must be a single action",
+                    ));
+                }
+
+                // After the drop, only the final action of "ask" may be chosen
+                if dnd.dropped && dnd.action != DndAction::ASK {
+                    return Err(ProtocolError::new(
+                        resource.id,
+                        wl_data_offer::Error::InvalidOffer,
+                        "offer was already dropped",
+                    ));
+                }
+
+                dnd.actions = dnd_actions;
+                dnd.preferred = preferred_action;
+
+                let action = dnd.choose_action();
+
+                if let Some(OfferSource::Client(source)) = offer.source
+                    && action != dnd.action
+                {
+                    dnd.action = action;
+                    handle.send_event(
+                        resource,
+                        wl_data_offer::Event::Action { dnd_action: action },
+                    );
+                    handle.send_event(source, wl_data_source::Event::Action { dnd_action: action });
+                }
+            }
+            wl_data_offer::Request::Finish => {
+                let Some(dnd) = &offer.dnd else {
+                    return Err(not_dnd(wl_data_offer::Error::InvalidFinish));
+                };
+
+                let invalid = |message: &str| {
+                    ProtocolError::new(resource.id, wl_data_offer::Error::InvalidFinish, message)
+                };
+
+                if !dnd.dropped {
+                    return Err(invalid("offer was not dropped"));
+                }
+
+                if dnd.accepted.is_none() {
+                    return Err(invalid("no mime type was accepted"));
+                }
+
+                if dnd.action.is_empty() || dnd.action == DndAction::ASK {
+                    return Err(invalid("no action was chosen"));
+                }
+
+                if let Some(OfferSource::Client(source)) = offer.source.take() {
+                    handle.send_event(source, wl_data_source::Event::DndFinished);
+                }
+            }
+            wl_data_offer::Request::Destroy => {}
+        }
+
+        Ok(())
+    }
+
+    fn destroyed(&mut self, state: &mut D, handle: &mut DisplayHandle, resource: Resource) {
+        let Some(offer) = state.data_device_state().offers.remove(&resource) else {
+            return;
+        };
+
+        // A dropped offer destroyed before `finish` ends the operation, which
+        // is how offers predating `finish` complete
+        if let Some(OfferSource::Client(source)) = offer.source
+            && let Some(dnd) = offer.dnd
+            && dnd.dropped
+        {
+            match dnd.legacy {
+                true => handle.send_event(source, wl_data_source::Event::DndFinished),
+                false => handle.send_event(source, wl_data_source::Event::Cancelled),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        protocol::wayland::wl_pointer::ButtonState,
+        server::data_device::{VERSION, tests::Setup},
+        testing::{events_of, protocol_error},
+    };
+
+    /// Starts a drag supporting copy and ask, accepted by the second peer with
+    /// `preferred_action`, and dropped if `drop` is set.
+    async fn drag(preferred_action: DndAction, drop: bool) -> (Setup, ObjectId, ObjectId) {
+        let mut setup = Setup::new([VERSION; 2]).await;
+        let (source, offer) = setup.drag(Some(DndAction::COPY | DndAction::ASK)).await;
+
+        let client = &mut setup.peers[1].client;
+        client.queue(
+            offer,
+            wl_data_offer::Request::Accept {
+                serial: 0,
+                mime_type: Some(String::from("text/plain")),
+            },
+        );
+        client.queue(
+            offer,
+            wl_data_offer::Request::SetActions {
+                dnd_actions: DndAction::COPY | DndAction::ASK,
+                preferred_action,
+            },
+        );
+        setup.events(1).await;
+
+        if drop {
+            setup.button(ButtonState::Released);
+        }
+
+        (setup, source, offer)
+    }
+
+    async fn finish_error(setup: &mut Setup, offer: ObjectId) -> Option<(ObjectId, u32)> {
+        setup.peers[1]
+            .client
+            .queue(offer, wl_data_offer::Request::Finish);
+        let mut events = setup.events(1).await;
+
+        protocol_error(&mut events)
+    }
+
+    #[tokio::test]
+    async fn finish() {
+        let (mut setup, source, offer) = drag(DndAction::COPY, true).await;
+
+        assert_eq!(finish_error(&mut setup, offer).await, None);
+
+        let mut events = setup.events(0).await;
+        let finished = events_of(&mut events, source);
+        assert!(matches!(
+            finished[..],
+            [
+                ..,
+                wl_data_source::Event::DndDropPerformed,
+                wl_data_source::Event::DndFinished
+            ],
+        ));
+    }
+
+    #[tokio::test]
+    async fn finish_before_drop() {
+        let (mut setup, _, offer) = drag(DndAction::COPY, false).await;
+        let error = (offer, wl_data_offer::Error::InvalidFinish.into());
+
+        assert_eq!(finish_error(&mut setup, offer).await, Some(error));
+    }
+
+    #[tokio::test]
+    async fn finish_without_mime_type() {
+        let (mut setup, _, offer) = drag(DndAction::COPY, true).await;
+        let error = (offer, wl_data_offer::Error::InvalidFinish.into());
+
+        let request = wl_data_offer::Request::Accept {
+            serial: 0,
+            mime_type: None,
+        };
+        setup.peers[1].client.queue(offer, request);
+
+        assert_eq!(finish_error(&mut setup, offer).await, Some(error));
+    }
+
+    #[tokio::test]
+    async fn finish_with_ask() {
+        let (mut setup, _, offer) = drag(DndAction::ASK, true).await;
+        let error = (offer, wl_data_offer::Error::InvalidFinish.into());
+
+        assert_eq!(finish_error(&mut setup, offer).await, Some(error));
+    }
+
+    #[tokio::test]
+    async fn legacy_offer_destroyed() {
+        let mut setup = Setup::new([VERSION, 2]).await;
+        let (source, offer) = setup.drag(None).await;
+
+        let request = wl_data_offer::Request::Accept {
+            serial: 0,
+            mime_type: Some(String::from("text/plain")),
+        };
+        setup.peers[1].client.queue(offer, request);
+        setup.events(1).await;
+
+        setup.button(ButtonState::Released);
+        setup.peers[1]
+            .client
+            .queue(offer, wl_data_offer::Request::Destroy);
+        setup.events(1).await;
+
+        let mut events = setup.events(0).await;
+        let finished = events_of(&mut events, source);
+        assert!(matches!(
+            finished[..],
+            [
+                ..,
+                wl_data_source::Event::DndDropPerformed,
+                wl_data_source::Event::DndFinished
+            ],
+        ));
+    }
+}
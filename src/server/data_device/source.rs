@@ -0,0 +1,126 @@
+use super::{DataDeviceHandler, DataDeviceState, OfferSource, Selection, keyboard_focus};
+use crate::{
+    protocol::{
+        MessageGroup,
+        wayland::{wl_data_device_manager::DndAction, wl_data_source},
+    },
+    server::{
+        client::Resource,
+        display::{Dispatch, DisplayHandle},
+        error::ProtocolError,
+    },
+    wire::Message,
+};
+
+/// Data offered by a client through a `wl_data_source`.
+#[derive(Debug, Default)]
+pub struct DataSource {
+    pub(super) mime_types: Vec<String>,
+    /// Drag-and-drop actions, set at most once.
+    pub(super) actions: Option<DndAction>,
+    /// The source was set as selection or dragged, it cannot be used again.
+    pub(super) used: bool,
+}
+
+impl DataSource {
+    /// Returns the MIME types the data is offered as.
+    pub fn mime_types(&self) -> &[String] {
+        &self.mime_types
+    }
+
+    /// Returns the drag-and-drop actions the source supports, if set.
+    pub fn actions(&self) -> Option<DndAction> {
+        self.actions
+    }
+}
+
+pub(super) struct DataSourceDispatch;
+
+impl<D: DataDeviceHandler> Dispatch<D> for DataSourceDispatch {
+    fn request(
+        &mut self,
+        state: &mut D,
+        _handle: &mut DisplayHandle,
+        resource: Resource,
+        message: Message,
+    ) -> Result<(), ProtocolError> {
+        let request = wl_data_source::Request::from_message(message)
+            .map_err(|err| ProtocolError::malformed(resource.id, err))?;
+
+        let Some(source) = state.data_device_state().sources.get_mut(&resource) else {
+            return Ok(());
+        };
+
+        match request {
+            wl_data_source::Request::Offer { mime_type } => {
+                if !source.mime_types.contains(&mime_type) {
+                    source.mime_types.push(mime_type);
+                }
+            }
+            wl_data_source::Request::Destroy => {}
+            wl_data_source::Request::SetActions { dnd_actions } => {
+                let invalid = |message: &str| {
+                    ProtocolError::new(
+                        resource.id,
+                        wl_data_source::Error::InvalidActionMask,
+                        message,
+                    )
+                };
+
+                if !DndAction::all().contains(dnd_actions) {
+                    return Err(invalid("invalid drag-and-drop actions"));
+                }
+
+                if source.actions.is_some() {
+                    return Err(invalid("actions were already set"));
+                }
+
+                if source.used {
+                    return Err(invalid("source was already used"));
+                }
+
+                source.actions = Some(dnd_actions);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn destroyed(&mut self, state: &mut D, handle: &mut DisplayHandle, resource: Resource) {
+        let data = state.data_device_state();
+        data.sources.remove(&resource);
+
+        let seats: Vec<_> = data
+            .selections
+            .iter()
+            .filter(|(_, selection)| **selection == Selection::Client(resource))
+            .map(|(seat, _)| *seat)
+            .collect();
+
+        for seat in seats {
+            let focus = keyboard_focus(state, seat);
+            state
+                .data_device_state()
+                .replace_selection(handle, seat, None, focus);
+            state.selection_changed(handle, seat);
+        }
+
+        for offer in state.data_device_state().offers.values_mut() {
+            if offer.source == Some(OfferSource::Client(resource)) {
+                offer.source = None;
+            }
+        }
+
+        let drags: Vec<_> = state
+            .data_device_state()
+            .drags
+            .iter()
+            .filter(|(_, drag)| drag.source() == Some(resource))
+            .map(|(seat, _)| *seat)
+            .collect();
+
+        for seat in drags {
+            DataDeviceState::cancel_drag(state, handle, seat);
+        }
+    }
+}
@@ -0,0 +1,1041 @@
+use super::{
+    callback::Callback,
+    client::{Client, ClientEvent, ClientId, Resource},
+    error::ProtocolError,
+    global::{Global, GlobalId},
+    socket::{ClientConnection, SocketError, WaylandSocket},
+};
+use crate::{
+    object_map::Object,
+    protocol::{
+        MessageGroup,
+        wayland::{wl_display, wl_registry},
+    },
+    wire::{
+        Argument, Connection, Interface, MAX_QUEUED_FDS, Message, ObjectId, RawMessage, WireError,
+    },
+};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    os::fd::OwnedFd,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::{mpsc, watch};
+
+/// Capacity of the channel carrying requests from all clients to the display.
+const EVENT_QUEUE_SIZE: usize = 256;
+
+/// Default limit of unwritten bytes per client.
+const DEFAULT_HIGH_WATER_MARK: usize = 4 * 1024 * 1024;
+
+/// How long clients may still bind a global after it was removed.
+const REMOVED_GLOBAL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to stop accepting connections after running out of file
+/// descriptors.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Handler for the requests of one interface.
+///
+/// Implemented for closures taking the same arguments as [`Dispatch::request`].
+pub trait Dispatch<D>: 'static {
+    /// Handles a request sent to `resource`.
+    ///
+    /// Objects created by `new_id` arguments already exist, and destructor
+    /// requests destroy `resource` once this returns. Errors are sent to the
+    /// client, which is then disconnected.
+    fn request(
+        &mut self,
+        state: &mut D,
+        handle: &mut DisplayHandle,
+        resource: Resource,
+        message: Message,
+    ) -> Result<(), ProtocolError>;
+
+    /// Called once `resource` has been destroyed, including on client disconnect.
+    fn destroyed(&mut self, state: &mut D, handle: &mut DisplayHandle, resource: Resource) {
+        let _ = (state, handle, resource);
+    }
+}
+
+impl<D, F> Dispatch<D> for F
+where
+    F: FnMut(&mut D, &mut DisplayHandle, Resource, Message) -> Result<(), ProtocolError> + 'static,
+{
+    fn request(
+        &mut self,
+        state: &mut D,
+        handle: &mut DisplayHandle,
+        resource: Resource,
+        message: Message,
+    ) -> Result<(), ProtocolError> {
+        self(state, handle, resource, message)
+    }
+}
+
+/// Callback creating the state behind a newly bound global.
+pub type BindFn<D> =
+    Box<dyn FnMut(&mut D, &mut DisplayHandle, Resource) -> Result<(), ProtocolError>>;
+
+/// Callback for clients exceeding the high-water mark.
+pub type SlowClientFn<D> = Box<dyn FnMut(&mut D, &mut DisplayHandle, ClientId)>;
+
+/// Client table and object management, available to request handlers.
+#[derive(Debug)]
+pub struct DisplayHandle {
+    clients: HashMap<ClientId, Client>,
+    next_client_id: u64,
+
+    globals: BTreeMap<GlobalId, Global>,
+    /// Globals that were removed, clients may still bind them until they
+    /// process `wl_registry.global_remove`.
+    removed_globals: HashMap<GlobalId, RemovedGlobal>,
+    next_global_id: u32,
+
+    /// Destroyed objects whose handlers have not been notified yet.
+    destroyed: Vec<(Resource, &'static Interface)>,
+
+    serial: u32,
+    high_water_mark: usize,
+    events: mpsc::Sender<(ClientId, ClientEvent)>,
+}
+
+/// A global withdrawn from the registries, kept until the clients that saw
+/// it can no longer bind it.
+#[derive(Debug)]
+struct RemovedGlobal {
+    global: Global,
+    removed_at: Instant,
+    /// Clients that were sent `wl_registry.global_remove`.
+    clients: HashSet<ClientId>,
+}
+
+impl DisplayHandle {
+    /// Returns a connected client.
+    pub fn client(&self, id: ClientId) -> Option<&Client> {
+        self.clients.get(&id)
+    }
+
+    /// Returns an iterator over connected clients.
+    pub fn clients(&self) -> impl Iterator<Item = &Client> {
+        self.clients.values()
+    }
+
+    /// Returns the interface and version of a live object.
+    pub fn object(&self, resource: Resource) -> Option<Object> {
+        self.clients
+            .get(&resource.client)?
+            .objects
+            .get(resource.id)
+            .copied()
+    }
+
+    /// Returns the version of a live object, or 0 if it does not exist.
+    pub fn version(&self, resource: Resource) -> u32 {
+        self.object(resource).map_or(0, |object| object.version)
+    }
+
+    /// Sends an event from `resource`.
+    ///
+    /// Events to objects that no longer exist, or whose version predates the
+    /// event, are dropped.
+    pub fn send_event<E: MessageGroup>(&mut self, resource: Resource, event: E) {
+        let Some(client) = self.clients.get_mut(&resource.client) else {
+            return;
+        };
+
+        let Some(object) = client.objects.get(resource.id) else {
+            return;
+        };
+
+        debug_assert_eq!(object.interface, E::interface());
+
+        if event.since() > object.version {
+            return;
+        }
+
+        client.send(event.into_message(resource.id));
+    }
+
+    /// Creates an object with a server-allocated id.
+    ///
+    /// Returns `None` if the client is gone or ran out of server ids.
+    pub fn create_object(
+        &mut self,
+        client: ClientId,
+        interface: &'static Interface,
+        version: u32,
+    ) -> Option<Resource> {
+        let client = self.clients.get_mut(&client)?;
+        let id = client
+            .objects
+            .insert_new(Object { interface, version })
+            .ok()?;
+
+        Some(Resource::new(client.id(), id))
+    }
+
+    /// Destroys an object, acknowledging client-allocated ids with `wl_display.delete_id`.
+    pub fn destroy_object(&mut self, resource: Resource) {
+        let Some(client) = self.clients.get_mut(&resource.client) else {
+            return;
+        };
+
+        let Some(object) = client.objects.remove(resource.id) else {
+            return;
+        };
+
+        if resource.id.get() < crate::object_map::SERVER_ID_START {
+            let event = wl_display::Event::DeleteId {
+                id: resource.id.get(),
+            };
+
+            client.send(event.into_message(ObjectId::DISPLAY));
+        }
+
+        self.destroyed.push((resource, object.interface));
+    }
+
+    /// Hands the events queued for a client to its writer task.
+    ///
+    /// Events are otherwise written at the end of each [`Display::dispatch`]
+    /// or on [`Display::flush`].
+    pub fn flush(&mut self, client: ClientId) {
+        if let Some(client) = self.clients.get_mut(&client) {
+            client.flush();
+        }
+    }
+
+    /// Returns a new serial, for events that clients may refer back to.
+    pub fn next_serial(&mut self) -> u32 {
+        self.serial = self.serial.wrapping_add(1);
+        self.serial
+    }
+
+    /// Returns the limit of unwritten bytes per client.
+    pub fn high_water_mark(&self) -> usize {
+        self.high_water_mark
+    }
+
+    /// Sets the limit of unwritten bytes per client, 4 MiB by default.
+    ///
+    /// Clients above it are checked on every flush of the display, see
+    /// [`Display::set_slow_client_handler`].
+    pub fn set_high_water_mark(&mut self, bytes: usize) {
+        self.high_water_mark = bytes;
+    }
+
+    /// Sends a protocol error to a client and disconnects it.
+    pub fn post_error(&mut self, client_id: ClientId, error: ProtocolError) {
+        let Some(client) = self.clients.get_mut(&client_id) else {
+            return;
+        };
+
+        let event = wl_display::Event::Error {
+            object_id: error.object,
+            code: error.code,
+            message: error.message,
+        };
+
+        client.send(event.into_message(ObjectId::DISPLAY));
+
+        self.disconnect(client_id);
+    }
+
+    /// Disconnects a client, destroying all of its objects.
+    ///
+    /// Messages already sent to it are still delivered.
+    pub fn disconnect(&mut self, client: ClientId) {
+        let Some(mut client) = self.clients.remove(&client) else {
+            return;
+        };
+
+        for (id, object) in client.objects.clear() {
+            // The display object is not managed by a handler
+            if id != ObjectId::DISPLAY {
+                self.destroyed
+                    .push((Resource::new(client.id(), id), object.interface));
+            }
+        }
+
+        for removed in self.removed_globals.values_mut() {
+            removed.clients.remove(&client.id());
+        }
+
+        self.prune_removed_globals();
+    }
+
+    /// Returns an advertised global.
+    pub fn global(&self, id: GlobalId) -> Option<&Global> {
+        self.globals.get(&id)
+    }
+
+    /// Returns an iterator over advertised globals.
+    pub fn globals(&self) -> impl Iterator<Item = (GlobalId, &Global)> {
+        self.globals.iter().map(|(id, global)| (*id, global))
+    }
+
+    /// Advertises a global to every registry that may see it.
+    fn insert_global(&mut self, global: Global) -> GlobalId {
+        self.next_global_id += 1;
+        let id = GlobalId(self.next_global_id);
+
+        for client in self.clients.values_mut() {
+            if global.is_visible(client) {
+                for registry in client.registries.clone() {
+                    announce(client, registry, id, &global);
+                }
+            }
+        }
+
+        self.globals.insert(id, global);
+
+        id
+    }
+
+    /// Withdraws a global from every registry that could see it.
+    fn remove_global(&mut self, id: GlobalId) -> bool {
+        let Some(global) = self.globals.remove(&id) else {
+            return false;
+        };
+
+        let mut clients = HashSet::new();
+
+        for client in self.clients.values_mut() {
+            if global.is_visible(client) && !client.registries.is_empty() {
+                for registry in client.registries.clone() {
+                    let event = wl_registry::Event::GlobalRemove { name: id.0 };
+                    client.send(event.into_message(registry));
+                }
+
+                clients.insert(client.id());
+            }
+        }
+
+        self.prune_removed_globals();
+
+        if !clients.is_empty() {
+            let removed = RemovedGlobal {
+                global,
+                removed_at: Instant::now(),
+                clients,
+            };
+
+            self.removed_globals.insert(id, removed);
+        }
+
+        true
+    }
+
+    /// Returns a global, or a removed one `client` may still bind.
+    fn bindable_global(&self, id: GlobalId, client: ClientId) -> Option<&Global> {
+        let removed = self
+            .removed_globals
+            .get(&id)
+            .filter(|removed| removed.clients.contains(&client))
+            .map(|removed| &removed.global);
+
+        self.globals.get(&id).or(removed)
+    }
+
+    /// Forgets removed globals once they timed out or all the clients that
+    /// knew about them are gone.
+    fn prune_removed_globals(&mut self) {
+        self.removed_globals.retain(|_, removed| {
+            !removed.clients.is_empty() && removed.removed_at.elapsed() < REMOVED_GLOBAL_TIMEOUT
+        });
+    }
+
+    /// Announces the visible globals to a new registry.
+    fn add_registry(&mut self, client: ClientId, registry: ObjectId) {
+        let Some(client) = self.clients.get_mut(&client) else {
+            return;
+        };
+
+        for (id, global) in &self.globals {
+            if global.is_visible(client) {
+                announce(client, registry, *id, global);
+            }
+        }
+
+        client.registries.push(registry);
+    }
+
+    /// Starts serving a connection, as if it had been accepted on the socket.
+    ///
+    /// Must be called within the Tokio runtime driving the display.
+    pub fn insert_client(&mut self, connection: ClientConnection) -> ClientId {
+        let id = ClientId(self.next_client_id);
+        self.next_client_id += 1;
+
+        let (stream, credentials) = connection.into_parts();
+        let (reader, writer) = Connection::new(stream).into_split();
+        let mut client = Client::spawn(id, credentials, reader, writer, self.events.clone());
+
+        // Every connection starts with the display singleton
+        let display = Object {
+            interface: &wl_display::INTERFACE,
+            version: 1,
+        };
+
+        client
+            .objects
+            .insert_at(ObjectId::DISPLAY, display)
+            .expect("object map of a new client is empty");
+
+        self.clients.insert(id, client);
+
+        id
+    }
+}
+
+/// Handle to stop [`Display::run`], it can be cloned and sent across tasks.
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle(Arc<watch::Sender<bool>>);
+
+impl ShutdownHandle {
+    /// Asks the display to stop.
+    pub fn shutdown(&self) {
+        self.0.send_replace(true);
+    }
+}
+
+/// Wayland display server.
+///
+/// Owns a [`WaylandSocket`], accepts connections and drives one reader and one
+/// writer task per client. Requests are dispatched one at a time to the
+/// handlers registered for their interface, with mutable access to the user
+/// state `D`.
+pub struct Display<D> {
+    socket: WaylandSocket,
+    handle: DisplayHandle,
+    handlers: HashMap<&'static str, Box<dyn Dispatch<D>>>,
+    binds: HashMap<GlobalId, BindFn<D>>,
+    slow_client: Option<SlowClientFn<D>>,
+    /// When accepting connections may resume.
+    accept_backoff: Option<Instant>,
+
+    events: mpsc::Receiver<(ClientId, ClientEvent)>,
+    shutdown: watch::Receiver<bool>,
+    shutdown_tx: Arc<watch::Sender<bool>>,
+}
+
+impl<D: 'static> Display<D> {
+    /// Creates a display serving the given socket.
+    pub fn new(socket: WaylandSocket) -> Self {
+        let (events_tx, events) = mpsc::channel(EVENT_QUEUE_SIZE);
+        let (shutdown_tx, shutdown) = watch::channel(false);
+
+        Display {
+            socket,
+            handle: DisplayHandle {
+                clients: HashMap::new(),
+                next_client_id: 0,
+                globals: BTreeMap::new(),
+                removed_globals: HashMap::new(),
+                next_global_id: 0,
+                destroyed: Vec::new(),
+                serial: 0,
+                high_water_mark: DEFAULT_HIGH_WATER_MARK,
+                events: events_tx,
+            },
+            handlers: HashMap::new(),
+            binds: HashMap::new(),
+            slow_client: None,
+            accept_backoff: None,
+            events,
+            shutdown,
+            shutdown_tx: Arc::new(shutdown_tx),
+        }
+    }
+
+    /// Returns the socket being served.
+    pub fn socket(&self) -> &WaylandSocket {
+        &self.socket
+    }
+
+    /// Returns the client table.
+    pub fn handle(&self) -> &DisplayHandle {
+        &self.handle
+    }
+
+    /// Returns the client table, mutably.
+    pub fn handle_mut(&mut self) -> &mut DisplayHandle {
+        &mut self.handle
+    }
+
+    /// Registers the handler for an interface, replacing any previous one.
+    ///
+    /// Requests to interfaces without a handler are ignored, apart from
+    /// creating and destroying objects. `wl_display` and `wl_registry` are
+    /// implemented by the display and never reach handlers.
+    pub fn register(&mut self, interface: &'static Interface, handler: impl Dispatch<D>) {
+        self.handlers.insert(interface.name, Box::new(handler));
+    }
+
+    /// Advertises a global to all clients.
+    ///
+    /// `bind` is called with the new object whenever a client binds the
+    /// global, at the version the client asked for. Errors are reported like
+    /// for request handlers.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `version` is 0 or above the version of `interface`.
+    pub fn create_global(
+        &mut self,
+        interface: &'static Interface,
+        version: u32,
+        bind: impl FnMut(&mut D, &mut DisplayHandle, Resource) -> Result<(), ProtocolError> + 'static,
+    ) -> GlobalId {
+        let id = self
+            .handle
+            .insert_global(Global::new(interface, version, None));
+        self.binds.insert(id, Box::new(bind));
+
+        id
+    }
+
+    /// Advertises a global to the clients accepted by `filter`.
+    ///
+    /// Clients rejected by the filter neither see nor can bind the global.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `version` is 0 or above the version of `interface`.
+    pub fn create_global_filtered(
+        &mut self,
+        interface: &'static Interface,
+        version: u32,
+        filter: impl Fn(&Client) -> bool + 'static,
+        bind: impl FnMut(&mut D, &mut DisplayHandle, Resource) -> Result<(), ProtocolError> + 'static,
+    ) -> GlobalId {
+        let global = Global::new(interface, version, Some(Box::new(filter)));
+        let id = self.handle.insert_global(global);
+        self.binds.insert(id, Box::new(bind));
+
+        id
+    }
+
+    /// Withdraws a global, objects already bound to it stay alive.
+    ///
+    /// Clients that have not processed `wl_registry.global_remove` yet may
+    /// still bind it for a few seconds. Such objects are created without
+    /// calling the bind callback, and must be handled as inert by the
+    /// interface handler.
+    ///
+    /// Returns `false` if the global did not exist.
+    pub fn remove_global(&mut self, id: GlobalId) -> bool {
+        self.binds.remove(&id);
+        self.handle.remove_global(id)
+    }
+
+    /// Sets the callback for clients that stopped reading their events.
+    ///
+    /// It is called once whenever a client goes above the high-water mark, and
+    /// is responsible for dealing with it. Without a callback, such clients are
+    /// disconnected. Clients the callback lets stay are still disconnected
+    /// once they reach twice the high-water mark.
+    pub fn set_slow_client_handler(
+        &mut self,
+        handler: impl FnMut(&mut D, &mut DisplayHandle, ClientId) + 'static,
+    ) {
+        self.slow_client = Some(Box::new(handler));
+    }
+
+    /// Returns a handle that makes [`Display::run`] return.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle(self.shutdown_tx.clone())
+    }
+
+    /// Runs until shutdown is requested, then disconnects all clients.
+    ///
+    /// Dropping the display afterwards removes the socket and lock files.
+    pub async fn run(&mut self, state: &mut D) {
+        let mut shutdown = self.shutdown.clone();
+
+        loop {
+            tokio::select! {
+                _ = shutdown.wait_for(|stop| *stop) => break,
+                _ = self.dispatch(state) => {}
+            }
+        }
+
+        let clients: Vec<_> = self.handle.clients.keys().copied().collect();
+
+        for client in clients {
+            self.handle.disconnect(client);
+        }
+
+        self.notify_destroyed(state);
+    }
+
+    /// Waits for the next connection or request and processes it.
+    ///
+    /// Failing to accept a connection is logged and does not stop the
+    /// display. This is cancel safe, so it can be raced against other event
+    /// sources.
+    pub async fn dispatch(&mut self, state: &mut D) {
+        let backoff = self.accept_backoff;
+
+        tokio::select! {
+            connection = self.socket.accept(), if backoff.is_none() => self.accepted(connection),
+            _ = tokio::time::sleep_until(backoff.unwrap_or_else(Instant::now).into()), if backoff.is_some() => {
+                self.accept_backoff = None;
+            }
+            Some((client, event)) = self.events.recv() => match event {
+                ClientEvent::Message(message, fds) => self.dispatch_request(state, client, message, fds),
+                ClientEvent::Disconnected => self.handle.disconnect(client),
+            },
+        }
+
+        self.notify_destroyed(state);
+        self.flush(state);
+    }
+
+    /// Adds an accepted client, or reports why accepting failed.
+    fn accepted(&mut self, connection: Result<ClientConnection, SocketError>) {
+        match connection {
+            Ok(connection) => {
+                self.handle.insert_client(connection);
+            }
+            // The peer went away before it could be identified
+            Err(SocketError::Credentials(_)) => {}
+            Err(err) => {
+                eprintln!("alow: {err}");
+
+                // The listener stays readable while the pending connection
+                // cannot be accepted, so retrying right away would spin
+                if let SocketError::Accept(err) = &err
+                    && matches!(err.raw_os_error(), Some(libc::EMFILE | libc::ENFILE))
+                {
+                    self.accept_backoff = Some(Instant::now() + ACCEPT_BACKOFF);
+                }
+            }
+        }
+    }
+
+    /// Hands the queued events of all clients to their writer tasks, and
+    /// checks them against the high-water mark.
+    pub fn flush(&mut self, state: &mut D) {
+        let mut slow = Vec::new();
+        let mut overflowed = Vec::new();
+
+        for client in self.handle.clients.values_mut() {
+            client.flush();
+
+            let pending = client.pending_bytes();
+            let congested = pending > self.handle.high_water_mark;
+
+            if congested && !client.congested {
+                slow.push(client.id());
+            } else if client.congested && pending > self.handle.high_water_mark.saturating_mul(2) {
+                overflowed.push(client.id());
+            }
+
+            client.congested = congested;
+        }
+
+        for client in slow {
+            match &mut self.slow_client {
+                Some(handler) => handler(state, &mut self.handle, client),
+                None => self.handle.disconnect(client),
+            }
+        }
+
+        // Already reported, and the handler did not get the queue down
+        for client in overflowed {
+            self.handle.disconnect(client);
+        }
+
+        self.notify_destroyed(state);
+    }
+
+    fn dispatch_request(
+        &mut self,
+        state: &mut D,
+        client_id: ClientId,
+        raw: RawMessage,
+        fds: Vec<OwnedFd>,
+    ) {
+        // Requests still in flight from a client that was disconnected
+        let Some(client) = self.handle.clients.get_mut(&client_id) else {
+            return;
+        };
+
+        client.fds.extend(fds);
+
+        // Descriptors attached to requests without fd arguments pile up
+        if client.fds.len() > MAX_QUEUED_FDS {
+            let err = ProtocolError::malformed(raw.header.object, WireError::TooManyFds);
+            self.handle.post_error(client_id, err);
+            return;
+        }
+
+        let result = decode(client, &raw)
+            .and_then(|(object, message)| self.dispatch_message(state, client_id, object, message));
+
+        if let Err(err) = result {
+            self.handle.post_error(client_id, err);
+        }
+    }
+
+    fn dispatch_message(
+        &mut self,
+        state: &mut D,
+        client_id: ClientId,
+        object: Object,
+        message: Message,
+    ) -> Result<(), ProtocolError> {
+        let resource = Resource::new(client_id, message.object);
+        let desc = &object.interface.requests[message.opcode as usize];
+
+        // The display and registry are implemented by the display itself
+        let message = if object.interface == &wl_display::INTERFACE {
+            let request = wl_display::Request::from_message(message)
+                .map_err(|err| ProtocolError::malformed(resource.id, err))?;
+
+            match request {
+                wl_display::Request::Sync { callback } => {
+                    // Requests are handled in order, so all previous ones are done
+                    let serial = self.handle.next_serial();
+                    Callback::new(Resource::new(client_id, callback))
+                        .done(&mut self.handle, serial);
+                }
+                wl_display::Request::GetRegistry { registry } => {
+                    self.handle.add_registry(client_id, registry);
+                }
+            }
+
+            return Ok(());
+        } else if object.interface == &wl_registry::INTERFACE {
+            let request = wl_registry::Request::from_message(message)
+                .map_err(|err| ProtocolError::malformed(resource.id, err))?;
+
+            return self.bind(state, resource, request);
+        } else {
+            message
+        };
+
+        if let Some(handler) = self.handlers.get_mut(object.interface.name) {
+            handler.request(state, &mut self.handle, resource, message)?;
+        }
+
+        if desc.is_destructor {
+            self.handle.destroy_object(resource);
+        }
+
+        Ok(())
+    }
+
+    /// Binds a global through `registry`.
+    fn bind(
+        &mut self,
+        state: &mut D,
+        registry: Resource,
+        request: wl_registry::Request,
+    ) -> Result<(), ProtocolError> {
+        let wl_registry::Request::Bind {
+            name,
+            interface,
+            version,
+            id,
+        } = request;
+
+        let invalid = |message: String| {
+            ProtocolError::new(registry.id, wl_display::Error::InvalidObject, message)
+        };
+
+        self.handle.prune_removed_globals();
+
+        let client = &self.handle.clients[&registry.client];
+
+        // Binding a removed global creates an inert object, see `Display::remove_global`
+        let global = self
+            .handle
+            .bindable_global(GlobalId(name), registry.client)
+            .filter(|global| global.is_visible(client))
+            .ok_or_else(|| invalid(format!("invalid global {interface} ({name})")))?;
+
+        if global.interface().name != interface {
+            return Err(invalid(format!(
+                "invalid interface for global {name}: have {interface}, wanted {}",
+                global.interface().name
+            )));
+        }
+
+        if version == 0 || version > global.version() {
+            return Err(invalid(format!(
+                "invalid version for global {interface} ({name}): have {}, wanted {version}",
+                global.version()
+            )));
+        }
+
+        let object = Object {
+            interface: global.interface(),
+            version,
+        };
+
+        self.handle
+            .clients
+            .get_mut(&registry.client)
+            .expect("dispatching client is connected")
+            .objects
+            .insert_at(id, object)
+            .map_err(|_| invalid_new_id(id))?;
+
+        match self.binds.get_mut(&GlobalId(name)) {
+            Some(bind) => bind(state, &mut self.handle, Resource::new(registry.client, id)),
+            None => Ok(()),
+        }
+    }
+
+    /// Notifies handlers of destroyed objects.
+    fn notify_destroyed(&mut self, state: &mut D) {
+        while !self.handle.destroyed.is_empty() {
+            for (resource, interface) in std::mem::take(&mut self.handle.destroyed) {
+                if let Some(handler) = self.handlers.get_mut(interface.name) {
+                    handler.destroyed(state, &mut self.handle, resource);
+                }
+            }
+        }
+    }
+}
+
+/// Sends `wl_registry.global` for one global.
+fn announce(client: &mut Client, registry: ObjectId, id: GlobalId, global: &Global) {
+    let event = wl_registry::Event::Global {
+        name: id.0,
+        interface: global.interface().name.to_owned(),
+        version: global.version(),
+    };
+
+    client.send(event.into_message(registry));
+}
+
+/// Decodes a request and creates the objects it introduces.
+///
+/// Returns the message with the object it was sent to.
+fn decode(client: &mut Client, raw: &RawMessage) -> Result<(Object, Message), ProtocolError> {
+    let id = raw.header.object;
+    let object = *client
+        .objects
+        .get(id)
+        .ok_or(ProtocolError::invalid_object(id.get()))?;
+
+    let opcode = raw.header.opcode;
+    let name = object.interface.name;
+
+    let desc = match object.interface.requests.get(opcode as usize) {
+        Some(desc) if desc.since <= object.version => desc,
+        Some(desc) => {
+            return Err(ProtocolError::invalid_method(
+                id,
+                format!(
+                    "invalid method {opcode} (since {} > {}), object {name}@{id}",
+                    desc.since, object.version
+                ),
+            ));
+        }
+        None => {
+            return Err(ProtocolError::invalid_method(
+                id,
+                format!("invalid method {opcode}, object {name}@{id}"),
+            ));
+        }
+    };
+
+    let mut message = raw
+        .decode(desc.signature, &mut client.fds)
+        .map_err(|err| ProtocolError::malformed(id, err))?;
+
+    for (arg, interface) in message.args.iter_mut().zip(desc.interfaces) {
+        let Argument::Object(Some(other)) = *arg else {
+            continue;
+        };
+
+        match client.objects.get(other) {
+            Some(object) => {
+                if interface.is_some_and(|interface| object.interface != interface) {
+                    return Err(ProtocolError::invalid_object(other.get()));
+                }
+            }
+            // Server objects may have been destroyed before the client noticed
+            None if other.get() >= crate::object_map::SERVER_ID_START => {
+                *arg = Argument::Object(None);
+            }
+            None => return Err(ProtocolError::invalid_object(other.get())),
+        }
+    }
+
+    // Untyped new_id arguments are created by whoever handles the request
+    if let Some(child) = desc.child_interface {
+        for arg in &message.args {
+            if let Argument::NewId(new) = arg {
+                let object = Object {
+                    interface: child,
+                    version: object.version,
+                };
+
+                client
+                    .objects
+                    .insert_at(*new, object)
+                    .map_err(|_| invalid_new_id(*new))?;
+            }
+        }
+    }
+
+    Ok((object, message))
+}
+
+/// Returns the error for a `new_id` that is in use or out of sequence.
+fn invalid_new_id(id: ObjectId) -> ProtocolError {
+    ProtocolError::new(
+        ObjectId::DISPLAY,
+        wl_display::Error::InvalidObject,
+        format!("invalid new id {id}"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        protocol::wayland::{
+            wl_compositor, wl_data_device, wl_data_device_manager, wl_pointer, wl_region, wl_seat,
+            wl_surface,
+        },
+        server::socket::socket_pair,
+        testing::{Fixture, protocol_error},
+    };
+    use std::io;
+    use tokio::{net::UnixStream, time::timeout};
+
+    #[tokio::test]
+    async fn failed_accept() {
+        let dir = std::env::temp_dir();
+        let name = format!("alow-test-{}-failed-accept", std::process::id());
+        let socket = WaylandSocket::with_name_in_dir(&dir, name.as_str().into()).unwrap();
+        let mut display = Display::<()>::new(socket);
+        let state = &mut ();
+
+        display.accepted(Err(SocketError::Accept(io::Error::from_raw_os_error(
+            libc::ECONNABORTED,
+        ))));
+        assert!(display.accept_backoff.is_none());
+
+        display.accepted(Err(SocketError::Accept(io::Error::from_raw_os_error(
+            libc::EMFILE,
+        ))));
+        assert!(display.accept_backoff.is_some());
+
+        let _client = UnixStream::connect(dir.join(&name)).await.unwrap();
+
+        // The pending connection is left alone until the backoff expired
+        timeout(Duration::from_secs(1), display.dispatch(state))
+            .await
+            .unwrap();
+        assert!(display.accept_backoff.is_none());
+        assert_eq!(display.handle().clients().count(), 0);
+
+        timeout(Duration::from_secs(1), display.dispatch(state))
+            .await
+            .unwrap();
+        assert_eq!(display.handle().clients().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn object_interface_mismatch() {
+        let mut fixture = Fixture::new();
+        let mut client = fixture.connect().await;
+
+        let compositor = client.bind(&wl_compositor::INTERFACE, 1);
+        let surface = client.create(&wl_surface::INTERFACE, 1);
+        let region = client.create(&wl_region::INTERFACE, 1);
+
+        client.queue(
+            compositor,
+            wl_compositor::Request::CreateSurface { id: surface },
+        );
+        client.queue(
+            compositor,
+            wl_compositor::Request::CreateRegion { id: region },
+        );
+        client.queue(
+            surface,
+            wl_surface::Request::Attach {
+                buffer: Some(region),
+                x: 0,
+                y: 0,
+            },
+        );
+
+        let mut events = fixture.roundtrip(&mut client).await;
+        assert_eq!(
+            protocol_error(&mut events),
+            Some((ObjectId::DISPLAY, wl_display::Error::InvalidObject.into())),
+        );
+    }
+
+    #[tokio::test]
+    async fn seat_device_as_seat() {
+        let mut fixture = Fixture::new();
+        let mut client = fixture.connect().await;
+
+        let seat = client.bind(&wl_seat::INTERFACE, 1);
+        let manager = client.bind(&wl_data_device_manager::INTERFACE, 1);
+        let pointer = client.create(&wl_pointer::INTERFACE, 1);
+        let device = client.create(&wl_data_device::INTERFACE, 1);
+
+        client.queue(seat, wl_seat::Request::GetPointer { id: pointer });
+        client.queue(
+            manager,
+            wl_data_device_manager::Request::GetDataDevice {
+                id: device,
+                seat: pointer,
+            },
+        );
+
+        let mut events = fixture.roundtrip(&mut client).await;
+        assert_eq!(
+            protocol_error(&mut events),
+            Some((ObjectId::DISPLAY, wl_display::Error::InvalidObject.into())),
+        );
+    }
+
+    #[tokio::test]
+    async fn slow_client() {
+        let name = format!("alow-test-{}-slow-client", std::process::id());
+        let socket = WaylandSocket::with_name_in_dir(&std::env::temp_dir(), name.into()).unwrap();
+        let mut display = Display::<Vec<ClientId>>::new(socket);
+        let mut reported = Vec::new();
+
+        display.set_slow_client_handler(|reported, _, client| reported.push(client));
+        display.handle_mut().set_high_water_mark(4096);
+
+        // The client never reads
+        let (server, _client) = socket_pair().unwrap();
+        let client = display.handle_mut().insert_client(server);
+        let target = Resource::new(client, ObjectId::DISPLAY);
+
+        let fill = |display: &mut Display<_>, bytes| {
+            let handle = display.handle_mut();
+
+            while handle.client(client).unwrap().pending_bytes() < bytes {
+                handle.send_event(target, wl_display::Event::DeleteId { id: 100 });
+            }
+        };
+
+        fill(&mut display, 4097);
+        display.flush(&mut reported);
+        assert_eq!(reported, [client]);
+        assert!(display.handle().client(client).is_some());
+
+        // Reported once per crossing, but the limit is still enforced
+        fill(&mut display, 8193);
+        display.flush(&mut reported);
+        assert_eq!(reported, [client]);
+        assert!(display.handle().client(client).is_none());
+    }
+}
@@ -0,0 +1,58 @@
+use crate::{
+    protocol::wayland::wl_display,
+    wire::{ObjectId, WireError},
+};
+use thiserror::Error;
+
+/// Fatal protocol error, sent to the client with `wl_display.error` before it
+/// is disconnected.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("error {code} on object {object}: {message}")]
+pub struct ProtocolError {
+    /// Object the error is reported on.
+    pub object: ObjectId,
+    /// Error code, from the error enum of the object's interface.
+    pub code: u32,
+    /// Human-readable description.
+    pub message: String,
+}
+
+impl ProtocolError {
+    /// Creates an error with an interface-specific code.
+    pub fn new(object: ObjectId, code: impl Into<u32>, message: impl Into<String>) -> Self {
+        ProtocolError {
+            object,
+            code: code.into(),
+            message: message.into(),
+        }
+    }
+
+    /// A request referenced an object that does not exist.
+    pub fn invalid_object(id: u32) -> Self {
+        Self::new(
+            ObjectId::DISPLAY,
+            wl_display::Error::InvalidObject,
+            format!("invalid object {id}"),
+        )
+    }
+
+    /// A request does not exist on the object or was malformed.
+    pub fn invalid_method(object: ObjectId, message: impl Into<String>) -> Self {
+        Self::new(object, wl_display::Error::InvalidMethod, message)
+    }
+
+    /// The arguments of a request could not be decoded.
+    pub fn malformed(object: ObjectId, err: WireError) -> Self {
+        Self::invalid_method(object, format!("invalid arguments: {err}"))
+    }
+
+    /// The server ran out of memory or ids.
+    pub fn no_memory() -> Self {
+        Self::new(ObjectId::DISPLAY, wl_display::Error::NoMemory, "no memory")
+    }
+
+    /// The compositor failed in a way unrelated to the request.
+    pub fn implementation(object: ObjectId, message: impl Into<String>) -> Self {
+        Self::new(object, wl_display::Error::Implementation, message)
+    }
+}
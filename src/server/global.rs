@@ -0,0 +1,75 @@
+use super::client::Client;
+use crate::wire::Interface;
+use std::fmt;
+
+/// Numeric name of a global, as advertised by `wl_registry.global`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GlobalId(pub(crate) u32);
+
+impl GlobalId {
+    /// Returns the numeric name.
+    pub fn get(self) -> u32 {
+        self.0
+    }
+}
+
+impl fmt::Display for GlobalId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Decides whether a client may see and bind a global.
+pub type GlobalFilter = Box<dyn Fn(&Client) -> bool>;
+
+/// An advertised global.
+pub struct Global {
+    interface: &'static Interface,
+    version: u32,
+    filter: Option<GlobalFilter>,
+}
+
+impl Global {
+    pub(crate) fn new(
+        interface: &'static Interface,
+        version: u32,
+        filter: Option<GlobalFilter>,
+    ) -> Self {
+        assert!(
+            version > 0 && version <= interface.version,
+            "invalid version {version} for global {}",
+            interface.name,
+        );
+
+        Global {
+            interface,
+            version,
+            filter,
+        }
+    }
+
+    /// Returns the interface of the global.
+    pub fn interface(&self) -> &'static Interface {
+        self.interface
+    }
+
+    /// Returns the highest version clients may bind.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Returns `true` if the global is visible to `client`.
+    pub fn is_visible(&self, client: &Client) -> bool {
+        self.filter.as_ref().is_none_or(|filter| filter(client))
+    }
+}
+
+impl fmt::Debug for Global {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Global")
+            .field("interface", &self.interface.name)
+            .field("version", &self.version)
+            .field("filtered", &self.filter.is_some())
+            .finish()
+    }
+}
@@ -1 +1,13 @@
+pub mod callback;
+pub mod client;
+pub mod compositor;
+pub mod credentials;
+pub mod data_device;
+pub mod display;
+pub mod error;
+pub mod global;
+pub mod output;
+pub mod seat;
+pub mod shm;
 pub mod socket;
+pub mod xdg_shell;
@@ -0,0 +1,681 @@
+mod xdg;
+
+use super::{
+    client::{ClientId, Resource},
+    compositor::CompositorHandler,
+    display::{Dispatch, Display, DisplayHandle},
+    error::ProtocolError,
+    global::GlobalId,
+};
+use crate::{
+    geometry::{Point, Rectangle, Size},
+    protocol::{
+        MessageGroup,
+        wayland::{
+            wl_output::{self, Subpixel, Transform},
+            wl_surface,
+        },
+    },
+    wire::Message,
+};
+use std::{collections::HashMap, fmt};
+
+/// Version of the `wl_output` global.
+const VERSION: u32 = 4;
+
+/// Identifies an output created with [`OutputState::add_output`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OutputId(u32);
+
+impl fmt::Display for OutputId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// A video mode of an output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mode {
+    /// Size in physical pixels.
+    pub size: Size,
+    /// Refresh rate in mHz, 0 if unknown.
+    pub refresh: i32,
+}
+
+/// Properties of an output advertised to clients.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputProperties {
+    /// Position in the global compositor space.
+    pub location: Point,
+    /// Physical size in millimeters, zero if unknown.
+    pub physical_size: Size,
+    pub subpixel: Subpixel,
+    pub make: String,
+    pub model: String,
+    pub transform: Transform,
+    /// Modes the output supports, all advertised when clients bind it.
+    pub modes: Vec<Mode>,
+    /// Index of the current mode in `modes`.
+    pub current_mode: Option<usize>,
+    /// Index of the preferred mode in `modes`.
+    pub preferred_mode: Option<usize>,
+    /// Scale clients should render their buffers at.
+    pub scale: i32,
+    /// Human-readable description, such as the make and model.
+    pub description: String,
+}
+
+impl OutputProperties {
+    /// Returns the current mode.
+    pub fn mode(&self) -> Option<Mode> {
+        self.modes.get(self.current_mode?).copied()
+    }
+
+    /// Returns the flags of the mode at `index`.
+    fn mode_flags(&self, index: usize) -> wl_output::Mode {
+        let mut flags = wl_output::Mode::empty();
+
+        if self.current_mode == Some(index) {
+            flags |= wl_output::Mode::CURRENT;
+        }
+
+        if self.preferred_mode == Some(index) {
+            flags |= wl_output::Mode::PREFERRED;
+        }
+
+        flags
+    }
+
+    /// Returns the area of the global compositor space the output shows.
+    ///
+    /// The size is that of the current mode, rotated by the transform and
+    /// divided by the scale.
+    pub fn logical_geometry(&self) -> Rectangle {
+        let mut size = self.mode().map(|mode| mode.size).unwrap_or_default();
+
+        if matches!(
+            self.transform,
+            Transform::_90 | Transform::_270 | Transform::Flipped90 | Transform::Flipped270
+        ) {
+            size = Size::new(size.height, size.width);
+        }
+
+        let scale = self.scale.max(1);
+        Rectangle {
+            loc: self.location,
+            size: Size::new(size.width / scale, size.height / scale),
+        }
+    }
+}
+
+impl Default for OutputProperties {
+    fn default() -> Self {
+        OutputProperties {
+            location: Point::default(),
+            physical_size: Size::default(),
+            subpixel: Subpixel::Unknown,
+            make: String::from("unknown"),
+            model: String::from("unknown"),
+            transform: Transform::Normal,
+            modes: Vec::new(),
+            current_mode: None,
+            preferred_mode: None,
+            scale: 1,
+            description: String::new(),
+        }
+    }
+}
+
+/// State of the `wl_output` globals.
+pub struct OutputState {
+    outputs: HashMap<OutputId, Output>,
+    /// Output of each `wl_output`.
+    resources: HashMap<Resource, OutputId>,
+    /// Output of each `zxdg_output_v1`.
+    xdg_outputs: HashMap<Resource, OutputId>,
+    next_id: u32,
+}
+
+/// Access to [`OutputState`].
+pub trait OutputHandler: CompositorHandler {
+    /// Returns the output state.
+    fn output_state(&mut self) -> &mut OutputState;
+}
+
+/// A display device, advertised as a `wl_output` global.
+#[derive(Debug)]
+pub struct Output {
+    id: OutputId,
+    global: GlobalId,
+    name: String,
+    resources: Vec<Resource>,
+    /// Each `zxdg_output_v1` with the `wl_output` it was created from.
+    xdg_outputs: Vec<(Resource, Resource)>,
+    pending: OutputProperties,
+    current: OutputProperties,
+}
+
+impl Output {
+    pub fn id(&self) -> OutputId {
+        self.id
+    }
+
+    /// Returns the `wl_output` global.
+    pub fn global(&self) -> GlobalId {
+        self.global
+    }
+
+    /// Returns the name of the output, such as its connector.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the `wl_output` objects of a client bound to this output.
+    pub fn client_resources(&self, client: ClientId) -> impl Iterator<Item = Resource> + '_ {
+        self.resources
+            .iter()
+            .copied()
+            .filter(move |resource| resource.client == client)
+    }
+
+    /// Returns the properties sent by the next [`done`](Self::done).
+    pub fn pending(&self) -> &OutputProperties {
+        &self.pending
+    }
+
+    /// Returns the properties sent by the next [`done`](Self::done), mutably.
+    pub fn pending_mut(&mut self) -> &mut OutputProperties {
+        &mut self.pending
+    }
+
+    /// Returns the properties advertised to clients.
+    pub fn current(&self) -> &OutputProperties {
+        &self.current
+    }
+
+    /// Advertises the pending properties, sending the events that changed
+    /// followed by `wl_output.done` to bound clients.
+    ///
+    /// Changes to the logical geometry are sent to `zxdg_output_v1` objects
+    /// in the same batch.
+    pub fn done(&mut self, handle: &mut DisplayHandle) {
+        if self.pending == self.current {
+            return;
+        }
+
+        for resource in &self.resources {
+            send_properties(handle, *resource, Some(&self.current), &self.pending);
+
+            for (xdg_output, _) in self.xdg_outputs.iter().filter(|(_, wl)| wl == resource) {
+                xdg::send_properties(handle, *xdg_output, Some(&self.current), &self.pending);
+            }
+
+            handle.send_event(*resource, wl_output::Event::Done);
+        }
+
+        self.current = self.pending.clone();
+    }
+}
+
+/// Sends the properties differing from `old`, or all of them without `old`.
+fn send_properties(
+    handle: &mut DisplayHandle,
+    resource: Resource,
+    old: Option<&OutputProperties>,
+    new: &OutputProperties,
+) {
+    let geometry_changed = old.is_none_or(|old| {
+        old.location != new.location
+            || old.physical_size != new.physical_size
+            || old.subpixel != new.subpixel
+            || old.make != new.make
+            || old.model != new.model
+            || old.transform != new.transform
+    });
+
+    if geometry_changed {
+        handle.send_event(
+            resource,
+            wl_output::Event::Geometry {
+                x: new.location.x,
+                y: new.location.y,
+                physical_width: new.physical_size.width,
+                physical_height: new.physical_size.height,
+                subpixel: new.subpixel.into(),
+                make: new.make.clone(),
+                model: new.model.clone(),
+                transform: new.transform.into(),
+            },
+        );
+    }
+
+    for (index, mode) in new.modes.iter().enumerate() {
+        let flags = new.mode_flags(index);
+
+        // Updates announce new modes and those that became current or preferred
+        let previous = old.map(|old| {
+            let index = old.modes.iter().position(|old| old == mode);
+            index.map(|index| old.mode_flags(index))
+        });
+
+        if let Some(Some(previous)) = previous
+            && previous.contains(flags)
+        {
+            continue;
+        }
+
+        handle.send_event(
+            resource,
+            wl_output::Event::Mode {
+                flags,
+                width: mode.size.width,
+                height: mode.size.height,
+                refresh: mode.refresh,
+            },
+        );
+    }
+
+    if old.is_none_or(|old| old.scale != new.scale) {
+        handle.send_event(resource, wl_output::Event::Scale { factor: new.scale });
+    }
+
+    if old.is_none_or(|old| old.description != new.description) {
+        handle.send_event(
+            resource,
+            wl_output::Event::Description {
+                description: new.description.clone(),
+            },
+        );
+    }
+}
+
+impl OutputState {
+    /// Handles `wl_output`, outputs are added with [`add_output`](Self::add_output).
+    pub fn new<D: OutputHandler>(display: &mut Display<D>) -> Self {
+        display.register(&wl_output::INTERFACE, OutputDispatch);
+
+        OutputState {
+            outputs: HashMap::new(),
+            resources: HashMap::new(),
+            xdg_outputs: HashMap::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Creates a `wl_output` global.
+    ///
+    /// The name identifies the output to clients and never changes.
+    pub fn add_output<D: OutputHandler>(
+        &mut self,
+        display: &mut Display<D>,
+        name: impl Into<String>,
+        properties: OutputProperties,
+    ) -> OutputId {
+        self.next_id += 1;
+        let id = OutputId(self.next_id);
+
+        let global = display.create_global(
+            &wl_output::INTERFACE,
+            VERSION,
+            move |state, handle, resource| {
+                let outputs = state.output_state();
+                let Some(output) = outputs.outputs.get_mut(&id) else {
+                    return Ok(());
+                };
+
+                send_properties(handle, resource, None, &output.current);
+                handle.send_event(
+                    resource,
+                    wl_output::Event::Name {
+                        name: output.name.clone(),
+                    },
+                );
+                handle.send_event(resource, wl_output::Event::Done);
+
+                output.resources.push(resource);
+                outputs.resources.insert(resource, id);
+
+                // Surfaces already shown on the output learn about the new object
+                let surfaces: Vec<_> = state
+                    .compositor_state()
+                    .surfaces()
+                    .filter(|surface| {
+                        surface.resource().client == resource.client
+                            && surface.outputs.contains(&id)
+                    })
+                    .map(|surface| surface.resource())
+                    .collect();
+
+                for surface in surfaces {
+                    handle.send_event(
+                        surface,
+                        wl_surface::Event::Enter {
+                            output: resource.id,
+                        },
+                    );
+                }
+
+                Ok(())
+            },
+        );
+
+        self.outputs.insert(
+            id,
+            Output {
+                id,
+                global,
+                name: name.into(),
+                resources: Vec::new(),
+                xdg_outputs: Vec::new(),
+                pending: properties.clone(),
+                current: properties,
+            },
+        );
+
+        id
+    }
+
+    /// Withdraws an output, sending `wl_surface.leave` to the surfaces shown on it.
+    ///
+    /// Objects bound to it stay alive without receiving events.
+    pub fn remove_output<D: OutputHandler>(
+        state: &mut D,
+        display: &mut Display<D>,
+        output: OutputId,
+    ) {
+        let Some(data) = state.output_state().outputs.remove(&output) else {
+            return;
+        };
+
+        display.remove_global(data.global);
+
+        let compositor = state.compositor_state();
+        let surfaces: Vec<_> = compositor
+            .surfaces()
+            .filter(|surface| surface.outputs.contains(&output))
+            .map(|surface| surface.resource())
+            .collect();
+
+        for surface in surfaces {
+            if let Some(surface) = compositor.surface_mut(surface) {
+                surface.outputs.retain(|other| *other != output);
+            }
+
+            send_surface_event(display.handle_mut(), &data, surface, false);
+        }
+    }
+
+    /// Returns an output.
+    pub fn output(&self, output: OutputId) -> Option<&Output> {
+        self.outputs.get(&output)
+    }
+
+    /// Returns an output, mutably, to change its properties.
+    pub fn output_mut(&mut self, output: OutputId) -> Option<&mut Output> {
+        self.outputs.get_mut(&output)
+    }
+
+    /// Returns an iterator over all outputs.
+    pub fn outputs(&self) -> impl Iterator<Item = &Output> {
+        self.outputs.values()
+    }
+
+    /// Returns the output of a `wl_output`.
+    ///
+    /// Useful for requests naming a `wl_output`, such as
+    /// `xdg_toplevel.set_fullscreen`.
+    pub fn output_of(&self, resource: Resource) -> Option<OutputId> {
+        self.resources.get(&resource).copied()
+    }
+
+    /// Tells a surface it is shown on an output, sending `wl_surface.enter`
+    /// unless it already entered it.
+    pub fn surface_enter<D: OutputHandler>(
+        state: &mut D,
+        handle: &mut DisplayHandle,
+        surface: Resource,
+        output: OutputId,
+    ) {
+        let Some(data) = state.compositor_state().surface_mut(surface) else {
+            return;
+        };
+
+        if data.outputs.contains(&output) {
+            return;
+        }
+
+        data.outputs.push(output);
+
+        if let Some(output) = state.output_state().outputs.get(&output) {
+            send_surface_event(handle, output, surface, true);
+        }
+    }
+
+    /// Tells a surface it is no longer shown on an output, sending
+    /// `wl_surface.leave` if it entered it.
+    pub fn surface_leave<D: OutputHandler>(
+        state: &mut D,
+        handle: &mut DisplayHandle,
+        surface: Resource,
+        output: OutputId,
+    ) {
+        let Some(data) = state.compositor_state().surface_mut(surface) else {
+            return;
+        };
+
+        if !data.outputs.contains(&output) {
+            return;
+        }
+
+        data.outputs.retain(|other| *other != output);
+
+        if let Some(output) = state.output_state().outputs.get(&output) {
+            send_surface_event(handle, output, surface, false);
+        }
+    }
+}
+
+/// Sends `wl_surface.enter` or `leave` for each `wl_output` the client bound.
+fn send_surface_event(handle: &mut DisplayHandle, output: &Output, surface: Resource, enter: bool) {
+    for resource in output.client_resources(surface.client) {
+        let output = resource.id;
+
+        match enter {
+            true => handle.send_event(surface, wl_surface::Event::Enter { output }),
+            false => handle.send_event(surface, wl_surface::Event::Leave { output }),
+        }
+    }
+}
+
+struct OutputDispatch;
+
+impl<D: OutputHandler> Dispatch<D> for OutputDispatch {
+    fn request(
+        &mut self,
+        _state: &mut D,
+        _handle: &mut DisplayHandle,
+        resource: Resource,
+        message: Message,
+    ) -> Result<(), ProtocolError> {
+        let request = wl_output::Request::from_message(message)
+            .map_err(|err| ProtocolError::malformed(resource.id, err))?;
+
+        match request {
+            wl_output::Request::Release => Ok(()),
+        }
+    }
+
+    fn destroyed(&mut self, state: &mut D, _handle: &mut DisplayHandle, resource: Resource) {
+        let outputs = state.output_state();
+
+        if let Some(id) = outputs.resources.remove(&resource)
+            && let Some(output) = outputs.outputs.get_mut(&id)
+        {
+            output.resources.retain(|other| *other != resource);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        testing::{Fixture, TestClient, events_of},
+        wire::ObjectId,
+    };
+
+    const LARGE: Mode = Mode {
+        size: Size::new(1920, 1080),
+        refresh: 60000,
+    };
+
+    const SMALL: Mode = Mode {
+        size: Size::new(1280, 720),
+        refresh: 60000,
+    };
+
+    struct Setup {
+        fixture: Fixture,
+        client: TestClient,
+        output: OutputId,
+        wl_output: ObjectId,
+    }
+
+    impl Setup {
+        /// Adds an output and binds it, returning the events sent on bind.
+        async fn new(version: u32, current_mode: usize) -> (Self, Vec<wl_output::Event>) {
+            let mut fixture = Fixture::new();
+            let properties = OutputProperties {
+                modes: vec![LARGE, SMALL],
+                current_mode: Some(current_mode),
+                preferred_mode: Some(0),
+                description: String::from("a monitor"),
+                ..OutputProperties::default()
+            };
+            let output = fixture
+                .state
+                .output
+                .add_output(&mut fixture.display, "DP-1", properties);
+
+            let mut client = fixture.connect().await;
+            let wl_output = client.bind(&wl_output::INTERFACE, version);
+
+            let mut setup = Setup {
+                fixture,
+                client,
+                output,
+                wl_output,
+            };
+            let events = setup.events().await;
+
+            (setup, events)
+        }
+
+        async fn events(&mut self) -> Vec<wl_output::Event> {
+            let mut events = self.fixture.roundtrip(&mut self.client).await;
+            events_of(&mut events, self.wl_output)
+        }
+
+        /// Changes the pending properties and advertises them.
+        async fn change(&mut self, f: impl FnOnce(&mut OutputProperties)) -> Vec<wl_output::Event> {
+            let output = self.fixture.state.output.output_mut(self.output).unwrap();
+            f(output.pending_mut());
+            output.done(self.fixture.display.handle_mut());
+
+            self.events().await
+        }
+    }
+
+    #[tokio::test]
+    async fn bind() {
+        let (_, events) = Setup::new(VERSION, 1).await;
+
+        assert!(matches!(
+            events[..],
+            [
+                wl_output::Event::Geometry { .. },
+                wl_output::Event::Mode {
+                    flags: wl_output::Mode::PREFERRED,
+                    width: 1920,
+                    ..
+                },
+                wl_output::Event::Mode {
+                    flags: wl_output::Mode::CURRENT,
+                    width: 1280,
+                    ..
+                },
+                wl_output::Event::Scale { factor: 1 },
+                wl_output::Event::Description { .. },
+                wl_output::Event::Name { .. },
+                wl_output::Event::Done,
+            ],
+        ));
+    }
+
+    #[tokio::test]
+    async fn scale() {
+        let (mut setup, _) = Setup::new(VERSION, 0).await;
+        let events = setup.change(|properties| properties.scale = 2).await;
+
+        assert!(matches!(
+            events[..],
+            [
+                wl_output::Event::Scale { factor: 2 },
+                wl_output::Event::Done
+            ],
+        ));
+    }
+
+    #[tokio::test]
+    async fn current_mode() {
+        let (mut setup, _) = Setup::new(VERSION, 0).await;
+        let events = setup
+            .change(|properties| properties.current_mode = Some(1))
+            .await;
+
+        // The previous mode only lost a flag, and is not sent again
+        assert!(matches!(
+            events[..],
+            [
+                wl_output::Event::Mode {
+                    flags: wl_output::Mode::CURRENT,
+                    width: 1280,
+                    height: 720,
+                    refresh: 60000,
+                },
+                wl_output::Event::Done,
+            ],
+        ));
+    }
+
+    #[tokio::test]
+    async fn unchanged() {
+        let (mut setup, _) = Setup::new(VERSION, 0).await;
+        let events = setup.change(|_| {}).await;
+
+        assert!(events.is_empty());
+    }
+
+    #[tokio::test]
+    async fn version_1() {
+        let (mut setup, events) = Setup::new(1, 0).await;
+
+        assert!(matches!(
+            events[..],
+            [
+                wl_output::Event::Geometry { .. },
+                wl_output::Event::Mode { .. },
+                wl_output::Event::Mode { .. },
+            ],
+        ));
+
+        let events = setup
+            .change(|properties| {
+                properties.scale = 2;
+                properties.description = String::from("another monitor");
+            })
+            .await;
+
+        assert!(events.is_empty());
+    }
+}
@@ -0,0 +1,174 @@
+use super::{OutputHandler, OutputProperties, OutputState};
+use crate::{
+    protocol::{
+        MessageGroup,
+        wayland::wl_output,
+        xdg_output_unstable_v1::{zxdg_output_manager_v1, zxdg_output_v1},
+    },
+    server::{
+        client::Resource,
+        display::{Dispatch, Display, DisplayHandle},
+        error::ProtocolError,
+        global::GlobalId,
+    },
+    wire::Message,
+};
+
+/// Version of the `zxdg_output_manager_v1` global.
+const MANAGER_VERSION: u32 = 3;
+
+/// First version of `zxdg_output_v1` relying on `wl_output.done` instead of its own.
+const WL_OUTPUT_DONE_VERSION: u32 = 3;
+
+impl OutputState {
+    /// Creates the `zxdg_output_manager_v1` global, describing outputs by
+    /// their [logical geometry](OutputProperties::logical_geometry).
+    pub fn create_xdg_output_manager<D: OutputHandler>(display: &mut Display<D>) -> GlobalId {
+        display.register(&zxdg_output_manager_v1::INTERFACE, ManagerDispatch);
+        display.register(&zxdg_output_v1::INTERFACE, XdgOutputDispatch);
+
+        display.create_global(
+            &zxdg_output_manager_v1::INTERFACE,
+            MANAGER_VERSION,
+            |_, _, _| Ok(()),
+        )
+    }
+}
+
+/// Sends the logical properties differing from `old`, or all of them
+/// without `old`.
+///
+/// Before version 3, the changes are followed by `zxdg_output_v1.done`.
+pub(super) fn send_properties(
+    handle: &mut DisplayHandle,
+    xdg_output: Resource,
+    old: Option<&OutputProperties>,
+    new: &OutputProperties,
+) {
+    let version = handle.version(xdg_output);
+    let geometry = new.logical_geometry();
+    let old_geometry = old.map(OutputProperties::logical_geometry);
+    let mut changed = false;
+
+    if old_geometry.is_none_or(|old| old.loc != geometry.loc) {
+        handle.send_event(
+            xdg_output,
+            zxdg_output_v1::Event::LogicalPosition {
+                x: geometry.loc.x,
+                y: geometry.loc.y,
+            },
+        );
+        changed = true;
+    }
+
+    if old_geometry.is_none_or(|old| old.size != geometry.size) {
+        handle.send_event(
+            xdg_output,
+            zxdg_output_v1::Event::LogicalSize {
+                width: geometry.size.width,
+                height: geometry.size.height,
+            },
+        );
+        changed = true;
+    }
+
+    // The description only changes from version 3
+    let description_changed = match old {
+        Some(old) => old.description != new.description && version >= WL_OUTPUT_DONE_VERSION,
+        None => true,
+    };
+
+    if description_changed {
+        handle.send_event(
+            xdg_output,
+            zxdg_output_v1::Event::Description {
+                description: new.description.clone(),
+            },
+        );
+        changed = true;
+    }
+
+    if changed && version < WL_OUTPUT_DONE_VERSION {
+        handle.send_event(xdg_output, zxdg_output_v1::Event::Done);
+    }
+}
+
+struct ManagerDispatch;
+
+impl<D: OutputHandler> Dispatch<D> for ManagerDispatch {
+    fn request(
+        &mut self,
+        state: &mut D,
+        handle: &mut DisplayHandle,
+        resource: Resource,
+        message: Message,
+    ) -> Result<(), ProtocolError> {
+        let request = zxdg_output_manager_v1::Request::from_message(message)
+            .map_err(|err| ProtocolError::malformed(resource.id, err))?;
+
+        let zxdg_output_manager_v1::Request::GetXdgOutput { id, output } = request else {
+            return Ok(());
+        };
+
+        let xdg_output = Resource::new(resource.client, id);
+        let wl_output = Resource::new(resource.client, output);
+
+        let outputs = state.output_state();
+        let Some((id, data)) = outputs
+            .resources
+            .get(&wl_output)
+            .and_then(|id| Some((*id, outputs.outputs.get_mut(id)?)))
+        else {
+            // Outputs that were removed leave inert objects behind
+            return Ok(());
+        };
+
+        handle.send_event(
+            xdg_output,
+            zxdg_output_v1::Event::Name {
+                name: data.name.clone(),
+            },
+        );
+        send_properties(handle, xdg_output, None, &data.current);
+
+        if handle.version(xdg_output) >= WL_OUTPUT_DONE_VERSION {
+            handle.send_event(wl_output, wl_output::Event::Done);
+        }
+
+        data.xdg_outputs.push((xdg_output, wl_output));
+        outputs.xdg_outputs.insert(xdg_output, id);
+
+        Ok(())
+    }
+}
+
+struct XdgOutputDispatch;
+
+impl<D: OutputHandler> Dispatch<D> for XdgOutputDispatch {
+    fn request(
+        &mut self,
+        _state: &mut D,
+        _handle: &mut DisplayHandle,
+        resource: Resource,
+        message: Message,
+    ) -> Result<(), ProtocolError> {
+        let request = zxdg_output_v1::Request::from_message(message)
+            .map_err(|err| ProtocolError::malformed(resource.id, err))?;
+
+        match request {
+            zxdg_output_v1::Request::Destroy => Ok(()),
+        }
+    }
+
+    fn destroyed(&mut self, state: &mut D, _handle: &mut DisplayHandle, resource: Resource) {
+        let outputs = state.output_state();
+
+        if let Some(id) = outputs.xdg_outputs.remove(&resource)
+            && let Some(output) = outputs.outputs.get_mut(&id)
+        {
+            output
+                .xdg_outputs
+                .retain(|(xdg_output, _)| *xdg_output != resource);
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use super::{AxisFrame, SeatHandler, SeatId, SeatState};
+use crate::{
+    protocol::wayland::wl_pointer::ButtonState,
+    server::{client::Resource, display::DisplayHandle},
+};
+
+/// Whether a grab goes on after handling an event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GrabStatus {
+    Continue,
+    End,
+}
+
+/// Receives the pointer events of a seat in place of the focus, for instance
+/// during drag-and-drop.
+///
+/// Grabs only see the events delivered through [`SeatState`], such as
+/// [`SeatState::pointer_motion`], the methods of [`Seat`](super::Seat)
+/// bypass them.
+pub trait PointerGrab<D>: 'static {
+    /// Handles motion to a surface-local `location` on `focus`.
+    fn motion(
+        &mut self,
+        state: &mut D,
+        handle: &mut DisplayHandle,
+        seat: SeatId,
+        time: u32,
+        focus: Option<Resource>,
+        location: (f64, f64),
+    );
+
+    /// Handles a button press or release, already tracked by the pointer.
+    fn button(
+        &mut self,
+        state: &mut D,
+        handle: &mut DisplayHandle,
+        seat: SeatId,
+        time: u32,
+        button: u32,
+        button_state: ButtonState,
+    ) -> GrabStatus;
+
+    /// Handles scrolling, ignored by default.
+    fn axis(&mut self, state: &mut D, handle: &mut DisplayHandle, seat: SeatId, frame: AxisFrame) {
+        let _ = (state, handle, seat, frame);
+    }
+
+    /// Ends a group of events, ignored by default.
+    fn frame(&mut self, state: &mut D, handle: &mut DisplayHandle, seat: SeatId) {
+        let _ = (state, handle, seat);
+    }
+
+    /// Called once the grab ended, was unset or replaced by another.
+    fn ended(&mut self, state: &mut D, handle: &mut DisplayHandle, seat: SeatId) {
+        let _ = (state, handle, seat);
+    }
+}
+
+type BoxedGrab<D> = Box<dyn PointerGrab<D>>;
+
+impl SeatState {
+    /// Sets the grab of a seat's pointer, ending the previous one.
+    pub fn set_pointer_grab<D: SeatHandler>(
+        state: &mut D,
+        handle: &mut DisplayHandle,
+        seat: SeatId,
+        grab: impl PointerGrab<D>,
+    ) {
+        Self::unset_pointer_grab(state, handle, seat);
+
+        if let Some(data) = state.seat_state().seats.get_mut(&seat) {
+            let grab: BoxedGrab<D> = Box::new(grab);
+            data.pointer.grab = Some(Box::new(grab));
+            data.pointer.grab_generation += 1;
+        }
+    }
+
+    /// Ends the grab of a seat's pointer, if any.
+    pub fn unset_pointer_grab<D: SeatHandler>(
+        state: &mut D,
+        handle: &mut DisplayHandle,
+        seat: SeatId,
+    ) {
+        let grab = take_grab(state, seat);
+
+        // Also ends a grab taken out to handle an event
+        if let Some(data) = state.seat_state().seats.get_mut(&seat) {
+            data.pointer.grab_generation += 1;
+        }
+
+        if let Some((mut grab, _)) = grab {
+            grab.ended(state, handle, seat);
+        }
+    }
+
+    /// Moves the pointer like [`Seat::pointer_motion`], unless a grab takes
+    /// the event.
+    ///
+    /// [`Seat::pointer_motion`]: super::Seat::pointer_motion
+    pub fn pointer_motion<D: SeatHandler>(
+        state: &mut D,
+        handle: &mut DisplayHandle,
+        seat: SeatId,
+        time: u32,
+        focus: Option<Resource>,
+        location: (f64, f64),
+    ) {
+        match take_grab(state, seat) {
+            Some((mut grab, generation)) => {
+                grab.motion(state, handle, seat, time, focus, location);
+                restore_grab(state, handle, seat, grab, generation);
+            }
+            None => {
+                if let Some(data) = state.seat_state().seats.get_mut(&seat) {
+                    data.pointer_motion(handle, time, focus, location);
+                }
+            }
+        }
+    }
+
+    /// Sends a button event like [`Seat::pointer_button`], unless a grab
+    /// takes it, returning its serial.
+    ///
+    /// Returns `None` if the seat does not exist.
+    ///
+    /// [`Seat::pointer_button`]: super::Seat::pointer_button
+    pub fn pointer_button<D: SeatHandler>(
+        state: &mut D,
+        handle: &mut DisplayHandle,
+        seat: SeatId,
+        time: u32,
+        button: u32,
+        button_state: ButtonState,
+    ) -> Option<u32> {
+        let Some((mut grab, generation)) = take_grab(state, seat) else {
+            let data = state.seat_state().seats.get_mut(&seat)?;
+            return Some(data.pointer_button(handle, time, button, button_state));
+        };
+
+        let data = state.seat_state().seats.get_mut(&seat)?;
+        let serial = data.pointer.track_button(handle, button, button_state);
+
+        match grab.button(state, handle, seat, time, button, button_state) {
+            GrabStatus::Continue => restore_grab(state, handle, seat, grab, generation),
+            GrabStatus::End => grab.ended(state, handle, seat),
+        }
+
+        Some(serial)
+    }
+
+    /// Sends scrolling like [`Seat::pointer_axis`], unless a grab takes it.
+    ///
+    /// [`Seat::pointer_axis`]: super::Seat::pointer_axis
+    pub fn pointer_axis<D: SeatHandler>(
+        state: &mut D,
+        handle: &mut DisplayHandle,
+        seat: SeatId,
+        frame: AxisFrame,
+    ) {
+        match take_grab(state, seat) {
+            Some((mut grab, generation)) => {
+                grab.axis(state, handle, seat, frame);
+                restore_grab(state, handle, seat, grab, generation);
+            }
+            None => {
+                if let Some(data) = state.seat_state().seats.get_mut(&seat) {
+                    data.pointer_axis(handle, frame);
+                }
+            }
+        }
+    }
+
+    /// Ends a group of pointer events like [`Seat::pointer_frame`], unless a
+    /// grab takes it.
+    ///
+    /// [`Seat::pointer_frame`]: super::Seat::pointer_frame
+    pub fn pointer_frame<D: SeatHandler>(state: &mut D, handle: &mut DisplayHandle, seat: SeatId) {
+        match take_grab(state, seat) {
+            Some((mut grab, generation)) => {
+                grab.frame(state, handle, seat);
+                restore_grab(state, handle, seat, grab, generation);
+            }
+            None => {
+                if let Some(data) = state.seat_state().seats.get_mut(&seat) {
+                    data.pointer_frame(handle);
+                }
+            }
+        }
+    }
+}
+
+/// Takes the grab out of a seat while it handles an event, along with the
+/// generation to put it back at.
+fn take_grab<D: SeatHandler>(state: &mut D, seat: SeatId) -> Option<(BoxedGrab<D>, u64)> {
+    let pointer = &mut state.seat_state().seats.get_mut(&seat)?.pointer;
+    let grab = pointer.grab.take()?;
+    pointer.grab_generation += 1;
+
+    let grab = grab
+        .downcast::<BoxedGrab<D>>()
+        .expect("pointer grabs are set for the state type");
+
+    Some((*grab, pointer.grab_generation))
+}
+
+/// Puts a grab back after it handled an event, or ends it if another grab
+/// was set or it was unset meanwhile.
+fn restore_grab<D: SeatHandler>(
+    state: &mut D,
+    handle: &mut DisplayHandle,
+    seat: SeatId,
+    mut grab: BoxedGrab<D>,
+    generation: u64,
+) {
+    if let Some(data) = state.seat_state().seats.get_mut(&seat)
+        && data.pointer.grab_generation == generation
+    {
+        data.pointer.grab = Some(Box::new(grab));
+        return;
+    }
+
+    grab.ended(state, handle, seat);
+}
@@ -0,0 +1,328 @@
+use super::{Keymap, Seat, SeatHandler, is_alive};
+use crate::{
+    protocol::{
+        MessageGroup,
+        wayland::wl_keyboard::{self, KeyState, KeymapFormat},
+    },
+    server::{
+        client::Resource,
+        display::{Dispatch, DisplayHandle},
+        error::ProtocolError,
+    },
+    wire::Message,
+};
+use rustix::fs::{Mode, OFlags};
+
+/// XKB modifier and layout state.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub depressed: u32,
+    pub latched: u32,
+    pub locked: u32,
+    /// Active layout.
+    pub group: u32,
+}
+
+/// Key repeat settings sent in `wl_keyboard.repeat_info`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RepeatInfo {
+    /// Characters per second, 0 disables repeating.
+    pub rate: i32,
+    /// Delay before repeating starts, in milliseconds.
+    pub delay: i32,
+}
+
+impl Default for RepeatInfo {
+    fn default() -> Self {
+        RepeatInfo {
+            rate: 25,
+            delay: 600,
+        }
+    }
+}
+
+/// State of a seat's keyboard.
+#[derive(Debug, Default)]
+pub struct Keyboard {
+    pub(super) resources: Vec<Resource>,
+    focus: Option<Resource>,
+    pressed: Vec<u32>,
+    modifiers: Modifiers,
+    keymap: Option<Keymap>,
+    repeat_info: RepeatInfo,
+}
+
+impl Keyboard {
+    /// Returns the surface with keyboard focus.
+    pub fn focus(&self) -> Option<Resource> {
+        self.focus
+    }
+
+    /// Returns the keys held down, as evdev key codes.
+    pub fn pressed_keys(&self) -> &[u32] {
+        &self.pressed
+    }
+
+    pub fn modifiers(&self) -> Modifiers {
+        self.modifiers
+    }
+
+    pub fn keymap(&self) -> Option<&Keymap> {
+        self.keymap.as_ref()
+    }
+
+    pub fn repeat_info(&self) -> RepeatInfo {
+        self.repeat_info
+    }
+
+    /// Returns the resources of the client owning the focus.
+    fn focused(&self) -> impl Iterator<Item = Resource> + '_ {
+        self.resources.iter().copied().filter(|resource| {
+            self.focus
+                .is_some_and(|focus| focus.client == resource.client)
+        })
+    }
+
+    /// Adds a `wl_keyboard`, sending the keymap and repeat settings and
+    /// entering the focus if its client owns it.
+    pub(super) fn add_resource(&mut self, handle: &mut DisplayHandle, keyboard: Resource) {
+        self.resources.push(keyboard);
+        self.send_keymap(handle, keyboard);
+        send_repeat_info(handle, keyboard, self.repeat_info);
+
+        if let Some(focus) = self.focus
+            && focus.client == keyboard.client
+            && is_alive(handle, focus)
+        {
+            let serial = handle.next_serial();
+            self.send_enter(handle, keyboard, serial, focus);
+        }
+    }
+
+    fn send_keymap(&self, handle: &mut DisplayHandle, keyboard: Resource) {
+        if let Some(keymap) = &self.keymap
+            && keymap.send(handle, keyboard).is_ok()
+        {
+            return;
+        }
+
+        // Without a keymap, clients interpret the raw key codes themselves
+        if let Ok(fd) =
+            rustix::fs::open("/dev/null", OFlags::RDONLY | OFlags::CLOEXEC, Mode::empty())
+        {
+            handle.send_event(
+                keyboard,
+                wl_keyboard::Event::Keymap {
+                    format: KeymapFormat::NoKeymap.into(),
+                    fd,
+                    size: 0,
+                },
+            );
+        }
+    }
+
+    fn send_enter(
+        &self,
+        handle: &mut DisplayHandle,
+        keyboard: Resource,
+        serial: u32,
+        surface: Resource,
+    ) {
+        let keys = self
+            .pressed
+            .iter()
+            .flat_map(|key| key.to_ne_bytes())
+            .collect();
+
+        handle.send_event(
+            keyboard,
+            wl_keyboard::Event::Enter {
+                serial,
+                surface: surface.id,
+                keys,
+            },
+        );
+        send_modifiers(handle, keyboard, serial, self.modifiers);
+    }
+}
+
+fn send_repeat_info(handle: &mut DisplayHandle, keyboard: Resource, info: RepeatInfo) {
+    handle.send_event(
+        keyboard,
+        wl_keyboard::Event::RepeatInfo {
+            rate: info.rate,
+            delay: info.delay,
+        },
+    );
+}
+
+fn send_modifiers(
+    handle: &mut DisplayHandle,
+    keyboard: Resource,
+    serial: u32,
+    modifiers: Modifiers,
+) {
+    handle.send_event(
+        keyboard,
+        wl_keyboard::Event::Modifiers {
+            serial,
+            mods_depressed: modifiers.depressed,
+            mods_latched: modifiers.latched,
+            mods_locked: modifiers.locked,
+            group: modifiers.group,
+        },
+    );
+}
+
+impl Seat {
+    /// Sets the keymap, sending it to every keyboard of the seat.
+    ///
+    /// The same keymap may be shared between seats.
+    pub fn set_keymap(&mut self, handle: &mut DisplayHandle, keymap: Keymap) {
+        let keyboard = &mut self.keyboard;
+        keyboard.keymap = Some(keymap);
+
+        for resource in &keyboard.resources {
+            keyboard.send_keymap(handle, *resource);
+        }
+    }
+
+    /// Sets the key repeat settings, sending them to every keyboard of the seat.
+    pub fn set_repeat_info(&mut self, handle: &mut DisplayHandle, info: RepeatInfo) {
+        let keyboard = &mut self.keyboard;
+
+        if keyboard.repeat_info == info {
+            return;
+        }
+
+        keyboard.repeat_info = info;
+
+        for resource in &keyboard.resources {
+            send_repeat_info(handle, *resource, info);
+        }
+    }
+
+    /// Moves the keyboard focus, sending `leave` to the previous surface and
+    /// `enter` with the pressed keys and modifiers to the new one.
+    ///
+    /// With a data device manager, [`DataDeviceState::set_keyboard_focus`]
+    /// also sends the selection to the client gaining focus.
+    ///
+    /// [`DataDeviceState::set_keyboard_focus`]: crate::server::data_device::DataDeviceState::set_keyboard_focus
+    pub fn set_keyboard_focus(&mut self, handle: &mut DisplayHandle, focus: Option<Resource>) {
+        let keyboard = &mut self.keyboard;
+
+        if keyboard.focus == focus {
+            return;
+        }
+
+        if let Some(previous) = keyboard.focus
+            && is_alive(handle, previous)
+        {
+            let serial = handle.next_serial();
+
+            for resource in keyboard.focused() {
+                handle.send_event(
+                    resource,
+                    wl_keyboard::Event::Leave {
+                        serial,
+                        surface: previous.id,
+                    },
+                );
+            }
+        }
+
+        keyboard.focus = focus;
+
+        if let Some(focus) = focus {
+            let serial = handle.next_serial();
+
+            for resource in keyboard.focused() {
+                keyboard.send_enter(handle, resource, serial, focus);
+            }
+        }
+    }
+
+    /// Sends a key press or release to the focus, returning its serial.
+    ///
+    /// Keys are evdev key codes, pressed keys are tracked even without focus
+    /// so that the next `enter` lists them.
+    pub fn keyboard_key(
+        &mut self,
+        handle: &mut DisplayHandle,
+        time: u32,
+        key: u32,
+        state: KeyState,
+    ) -> u32 {
+        let keyboard = &mut self.keyboard;
+        let serial = handle.next_serial();
+
+        match state {
+            KeyState::Pressed if !keyboard.pressed.contains(&key) => keyboard.pressed.push(key),
+            KeyState::Pressed => {}
+            KeyState::Released => keyboard.pressed.retain(|other| *other != key),
+        }
+
+        for resource in keyboard.focused() {
+            handle.send_event(
+                resource,
+                wl_keyboard::Event::Key {
+                    serial,
+                    time,
+                    key,
+                    state: state.into(),
+                },
+            );
+        }
+
+        serial
+    }
+
+    /// Updates the modifiers, sending them to the focus if they changed.
+    pub fn keyboard_modifiers(&mut self, handle: &mut DisplayHandle, modifiers: Modifiers) {
+        let keyboard = &mut self.keyboard;
+
+        if keyboard.modifiers == modifiers {
+            return;
+        }
+
+        keyboard.modifiers = modifiers;
+
+        if keyboard.focus.is_some() {
+            let serial = handle.next_serial();
+
+            for resource in keyboard.focused() {
+                send_modifiers(handle, resource, serial, modifiers);
+            }
+        }
+    }
+}
+
+pub(super) struct KeyboardDispatch;
+
+impl<D: SeatHandler> Dispatch<D> for KeyboardDispatch {
+    fn request(
+        &mut self,
+        _state: &mut D,
+        _handle: &mut DisplayHandle,
+        resource: Resource,
+        message: Message,
+    ) -> Result<(), ProtocolError> {
+        let request = wl_keyboard::Request::from_message(message)
+            .map_err(|err| ProtocolError::malformed(resource.id, err))?;
+
+        match request {
+            wl_keyboard::Request::Release => Ok(()),
+        }
+    }
+
+    fn destroyed(&mut self, state: &mut D, _handle: &mut DisplayHandle, resource: Resource) {
+        let seats = state.seat_state();
+
+        if let Some(seat) = seats.resource_seat(resource) {
+            seat.keyboard.resources.retain(|other| *other != resource);
+        }
+
+        seats.resources.remove(&resource);
+    }
+}
@@ -0,0 +1,103 @@
+use crate::{
+    protocol::wayland::wl_keyboard::{self, KeymapFormat},
+    server::{client::Resource, display::DisplayHandle},
+};
+use rustix::fs::{self, MemfdFlags, SealFlags};
+use std::{
+    fmt,
+    fs::File,
+    io::{self, Write},
+    os::fd::OwnedFd,
+    sync::Arc,
+};
+
+/// First version of `wl_keyboard` whose clients must map the keymap privately.
+const PRIVATE_MAP_VERSION: u32 = 7;
+
+/// An XKB keymap, as sent in `wl_keyboard.keymap`.
+///
+/// The keymap is written once into a sealed memfd, shared by every client
+/// that maps it privately. Clients older than version 7 of `wl_keyboard` may
+/// map it shared and writable, so they get a copy of their own. Clones share
+/// the same file.
+#[derive(Clone)]
+pub struct Keymap(Arc<Inner>);
+
+struct Inner {
+    /// Keymap text with its terminating NUL byte.
+    contents: Vec<u8>,
+    sealed: OwnedFd,
+}
+
+impl Keymap {
+    /// Writes a keymap in the XKB text format into a sealed memfd.
+    pub fn new(keymap: &str) -> io::Result<Self> {
+        let mut contents = Vec::with_capacity(keymap.len() + 1);
+        contents.extend_from_slice(keymap.as_bytes());
+        contents.push(0);
+
+        let sealed = write_memfd(&contents)?;
+        fs::fcntl_add_seals(
+            &sealed,
+            SealFlags::SHRINK | SealFlags::GROW | SealFlags::WRITE | SealFlags::SEAL,
+        )?;
+
+        Ok(Keymap(Arc::new(Inner { contents, sealed })))
+    }
+
+    /// Returns the keymap text.
+    pub fn as_str(&self) -> &str {
+        let text = &self.0.contents[..self.0.contents.len() - 1];
+        std::str::from_utf8(text).expect("keymap was created from a string")
+    }
+
+    /// Returns the size of the file, including the terminating NUL byte.
+    pub fn size(&self) -> u32 {
+        self.0.contents.len() as u32
+    }
+
+    /// Returns a file holding the keymap, suitable for a `wl_keyboard` of `version`.
+    pub fn fd(&self, version: u32) -> io::Result<OwnedFd> {
+        match version >= PRIVATE_MAP_VERSION {
+            true => self.0.sealed.try_clone(),
+            false => write_memfd(&self.0.contents),
+        }
+    }
+
+    /// Sends the keymap to a `wl_keyboard`.
+    pub fn send(&self, handle: &mut DisplayHandle, keyboard: Resource) -> io::Result<()> {
+        let fd = self.fd(handle.version(keyboard))?;
+
+        handle.send_event(
+            keyboard,
+            wl_keyboard::Event::Keymap {
+                format: KeymapFormat::XkbV1.into(),
+                fd,
+                size: self.size(),
+            },
+        );
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Keymap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Keymap")
+            .field("size", &self.size())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Creates a memfd holding `contents`, allowing seals to be added.
+fn write_memfd(contents: &[u8]) -> io::Result<OwnedFd> {
+    let fd = fs::memfd_create(
+        "alow-keymap",
+        MemfdFlags::CLOEXEC | MemfdFlags::ALLOW_SEALING,
+    )?;
+
+    let mut file = File::from(fd);
+    file.write_all(contents)?;
+
+    Ok(file.into())
+}
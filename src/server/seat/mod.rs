@@ -0,0 +1,317 @@
+mod grab;
+mod keyboard;
+mod keymap;
+mod pointer;
+mod touch;
+
+pub use self::{
+    grab::{GrabStatus, PointerGrab},
+    keyboard::{Keyboard, Modifiers, RepeatInfo},
+    keymap::Keymap,
+    pointer::{AxisFrame, AxisValue, CURSOR_ROLE, CursorImage, Pointer},
+    touch::Touch,
+};
+
+use self::{keyboard::KeyboardDispatch, pointer::PointerDispatch, touch::TouchDispatch};
+use super::{
+    client::Resource,
+    compositor::CompositorHandler,
+    display::{Dispatch, Display, DisplayHandle},
+    error::ProtocolError,
+    global::GlobalId,
+};
+use crate::{
+    protocol::{
+        MessageGroup,
+        wayland::{
+            wl_keyboard, wl_pointer,
+            wl_seat::{self, Capability},
+            wl_surface, wl_touch,
+        },
+    },
+    wire::Message,
+};
+use std::{collections::HashMap, fmt};
+
+/// Version of the `wl_seat` global.
+const VERSION: u32 = 9;
+
+/// Identifies a seat created with [`SeatState::add_seat`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SeatId(u32);
+
+impl fmt::Display for SeatId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// State of the `wl_seat` globals.
+pub struct SeatState {
+    seats: HashMap<SeatId, Seat>,
+    /// Seat of each `wl_seat`, `wl_pointer`, `wl_keyboard` and `wl_touch`.
+    resources: HashMap<Resource, SeatId>,
+    next_id: u32,
+}
+
+/// Access to [`SeatState`] and input requests.
+pub trait SeatHandler: CompositorHandler {
+    /// Returns the seat state.
+    fn seat_state(&mut self) -> &mut SeatState;
+
+    /// Called when the client with pointer focus sets the cursor image.
+    fn cursor_image(&mut self, handle: &mut DisplayHandle, seat: SeatId, image: CursorImage) {
+        let _ = (handle, seat, image);
+    }
+}
+
+/// A group of input devices, advertised as a `wl_seat` global.
+///
+/// Input events are delivered to the resources that the client owning the
+/// focused surface created from this seat.
+#[derive(Debug)]
+pub struct Seat {
+    id: SeatId,
+    global: GlobalId,
+    name: String,
+    capabilities: Capability,
+    /// Capabilities the seat had at any point, clients may only ask for those.
+    ever_capabilities: Capability,
+    resources: Vec<Resource>,
+    pointer: Pointer,
+    keyboard: Keyboard,
+    touch: Touch,
+}
+
+impl Seat {
+    pub fn id(&self) -> SeatId {
+        self.id
+    }
+
+    /// Returns the `wl_seat` global.
+    pub fn global(&self) -> GlobalId {
+        self.global
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn capabilities(&self) -> Capability {
+        self.capabilities
+    }
+
+    /// Returns `true` if `resource` is a `wl_seat` bound to this seat.
+    pub fn owns(&self, resource: Resource) -> bool {
+        self.resources.contains(&resource)
+    }
+
+    pub fn pointer(&self) -> &Pointer {
+        &self.pointer
+    }
+
+    pub fn keyboard(&self) -> &Keyboard {
+        &self.keyboard
+    }
+
+    pub fn touch(&self) -> &Touch {
+        &self.touch
+    }
+
+    /// Sets the input devices available, announcing them to bound clients.
+    pub fn set_capabilities(&mut self, handle: &mut DisplayHandle, capabilities: Capability) {
+        if self.capabilities == capabilities {
+            return;
+        }
+
+        self.capabilities = capabilities;
+        self.ever_capabilities |= capabilities;
+
+        for resource in &self.resources {
+            handle.send_event(*resource, wl_seat::Event::Capabilities { capabilities });
+        }
+    }
+}
+
+impl SeatState {
+    /// Handles `wl_seat` and its input devices, seats are added with
+    /// [`add_seat`](Self::add_seat).
+    pub fn new<D: SeatHandler>(display: &mut Display<D>) -> Self {
+        display.register(&wl_seat::INTERFACE, SeatDispatch);
+        display.register(&wl_pointer::INTERFACE, PointerDispatch);
+        display.register(&wl_keyboard::INTERFACE, KeyboardDispatch);
+        display.register(&wl_touch::INTERFACE, TouchDispatch);
+
+        SeatState {
+            seats: HashMap::new(),
+            resources: HashMap::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Creates a `wl_seat` global without capabilities.
+    pub fn add_seat<D: SeatHandler>(
+        &mut self,
+        display: &mut Display<D>,
+        name: impl Into<String>,
+    ) -> SeatId {
+        self.next_id += 1;
+        let id = SeatId(self.next_id);
+
+        let global = display.create_global(
+            &wl_seat::INTERFACE,
+            VERSION,
+            move |state, handle, resource| {
+                let seats = state.seat_state();
+                let Some(seat) = seats.seats.get_mut(&id) else {
+                    return Ok(());
+                };
+
+                handle.send_event(
+                    resource,
+                    wl_seat::Event::Capabilities {
+                        capabilities: seat.capabilities,
+                    },
+                );
+                handle.send_event(
+                    resource,
+                    wl_seat::Event::Name {
+                        name: seat.name.clone(),
+                    },
+                );
+
+                seat.resources.push(resource);
+                seats.resources.insert(resource, id);
+
+                Ok(())
+            },
+        );
+
+        self.seats.insert(
+            id,
+            Seat {
+                id,
+                global,
+                name: name.into(),
+                capabilities: Capability::empty(),
+                ever_capabilities: Capability::empty(),
+                resources: Vec::new(),
+                pointer: Pointer::default(),
+                keyboard: Keyboard::default(),
+                touch: Touch::default(),
+            },
+        );
+
+        id
+    }
+
+    /// Returns a seat.
+    pub fn seat(&self, seat: SeatId) -> Option<&Seat> {
+        self.seats.get(&seat)
+    }
+
+    /// Returns a seat, mutably, to deliver input events.
+    pub fn seat_mut(&mut self, seat: SeatId) -> Option<&mut Seat> {
+        self.seats.get_mut(&seat)
+    }
+
+    /// Returns an iterator over all seats.
+    pub fn seats(&self) -> impl Iterator<Item = &Seat> {
+        self.seats.values()
+    }
+
+    /// Returns the seat of a `wl_seat`, `wl_pointer`, `wl_keyboard` or `wl_touch`.
+    ///
+    /// Useful for requests naming a `wl_seat`, such as `xdg_toplevel.move`.
+    pub fn seat_of(&self, resource: Resource) -> Option<SeatId> {
+        self.resources.get(&resource).copied()
+    }
+
+    /// Returns the seat a resource belongs to.
+    fn resource_seat(&mut self, resource: Resource) -> Option<&mut Seat> {
+        let id = self.resources.get(&resource)?;
+        self.seats.get_mut(id)
+    }
+}
+
+/// Returns `true` if `surface` is still a live `wl_surface`.
+///
+/// Focus is kept until the compositor moves it, events about destroyed
+/// surfaces are not sent.
+fn is_alive(handle: &DisplayHandle, surface: Resource) -> bool {
+    handle
+        .object(surface)
+        .is_some_and(|object| *object.interface == wl_surface::INTERFACE)
+}
+
+struct SeatDispatch;
+
+impl<D: SeatHandler> Dispatch<D> for SeatDispatch {
+    fn request(
+        &mut self,
+        state: &mut D,
+        handle: &mut DisplayHandle,
+        resource: Resource,
+        message: Message,
+    ) -> Result<(), ProtocolError> {
+        let request = wl_seat::Request::from_message(message)
+            .map_err(|err| ProtocolError::malformed(resource.id, err))?;
+
+        let seats = state.seat_state();
+        let Some(&id) = seats.resources.get(&resource) else {
+            return Ok(());
+        };
+        let seat = seats.seats.get_mut(&id).expect("bound wl_seat has a seat");
+
+        let missing = |capability: &str| {
+            ProtocolError::new(
+                resource.id,
+                wl_seat::Error::MissingCapability,
+                format!("seat never had the {capability} capability"),
+            )
+        };
+
+        match request {
+            wl_seat::Request::GetPointer { id: object } => {
+                if !seat.ever_capabilities.contains(Capability::POINTER) {
+                    return Err(missing("pointer"));
+                }
+
+                let pointer = Resource::new(resource.client, object);
+                seat.pointer.add_resource(handle, pointer);
+                seats.resources.insert(pointer, id);
+            }
+            wl_seat::Request::GetKeyboard { id: object } => {
+                if !seat.ever_capabilities.contains(Capability::KEYBOARD) {
+                    return Err(missing("keyboard"));
+                }
+
+                let keyboard = Resource::new(resource.client, object);
+                seat.keyboard.add_resource(handle, keyboard);
+                seats.resources.insert(keyboard, id);
+            }
+            wl_seat::Request::GetTouch { id: object } => {
+                if !seat.ever_capabilities.contains(Capability::TOUCH) {
+                    return Err(missing("touch"));
+                }
+
+                let touch = Resource::new(resource.client, object);
+                seat.touch.resources.push(touch);
+                seats.resources.insert(touch, id);
+            }
+            wl_seat::Request::Release => {}
+        }
+
+        Ok(())
+    }
+
+    fn destroyed(&mut self, state: &mut D, _handle: &mut DisplayHandle, resource: Resource) {
+        let seats = state.seat_state();
+
+        if let Some(seat) = seats.resource_seat(resource) {
+            seat.resources.retain(|other| *other != resource);
+        }
+
+        seats.resources.remove(&resource);
+    }
+}
@@ -0,0 +1,435 @@
+use super::{Seat, SeatHandler, is_alive};
+use crate::{
+    geometry::Point,
+    protocol::{
+        MessageGroup,
+        wayland::wl_pointer::{self, Axis, AxisRelativeDirection, AxisSource, ButtonState},
+    },
+    server::{
+        client::Resource,
+        display::{Dispatch, DisplayHandle},
+        error::ProtocolError,
+    },
+    wire::{Fixed, Message},
+};
+use std::any::Any;
+
+/// Role of surfaces used as cursor images.
+pub const CURSOR_ROLE: &str = "wl_pointer";
+
+/// Cursor image requested with `wl_pointer.set_cursor`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorImage {
+    Hidden,
+    Surface {
+        surface: Resource,
+        /// Position of the pointer relative to the surface.
+        hotspot: Point,
+    },
+}
+
+/// Scrolling on one axis within an [`AxisFrame`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AxisValue {
+    /// Distance in surface-local coordinates.
+    pub value: f64,
+    /// Wheel detents, sent to clients before version 8.
+    pub discrete: i32,
+    /// Wheel movement in fractions of 120 per detent, sent from version 8.
+    pub value120: i32,
+    /// Scrolling stopped, for instance when lifting fingers from a touchpad.
+    pub stop: bool,
+    /// The physical motion is opposite to the scroll direction, as with
+    /// natural scrolling.
+    pub inverted: bool,
+}
+
+impl AxisValue {
+    fn is_empty(&self) -> bool {
+        self.value == 0.0 && self.discrete == 0 && self.value120 == 0
+    }
+}
+
+/// Scroll events grouped in one `wl_pointer.frame`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AxisFrame {
+    pub source: Option<AxisSource>,
+    pub time: u32,
+    pub horizontal: AxisValue,
+    pub vertical: AxisValue,
+}
+
+/// State of a seat's pointer.
+#[derive(Debug, Default)]
+pub struct Pointer {
+    pub(super) resources: Vec<Resource>,
+    focus: Option<Resource>,
+    /// Surface-local position within the focus.
+    location: (f64, f64),
+    enter_serial: u32,
+    pressed: Vec<u32>,
+    button_serial: Option<u32>,
+    /// Active grab, a `Box<dyn PointerGrab<D>>`.
+    pub(super) grab: Option<Box<dyn Any>>,
+    /// Incremented whenever the grab is set or unset.
+    pub(super) grab_generation: u64,
+}
+
+impl Pointer {
+    /// Returns the surface with pointer focus.
+    pub fn focus(&self) -> Option<Resource> {
+        self.focus
+    }
+
+    /// Returns the last position sent, relative to the focus.
+    pub fn location(&self) -> (f64, f64) {
+        self.location
+    }
+
+    /// Returns the buttons held down.
+    pub fn pressed_buttons(&self) -> &[u32] {
+        &self.pressed
+    }
+
+    /// Returns the serial of the latest button press.
+    pub fn button_serial(&self) -> Option<u32> {
+        self.button_serial
+    }
+
+    /// Returns `true` if a [grab](super::PointerGrab) receives the pointer events.
+    pub fn is_grabbed(&self) -> bool {
+        self.grab.is_some()
+    }
+
+    /// Tracks a button press or release, returning the serial of the event.
+    pub(super) fn track_button(
+        &mut self,
+        handle: &mut DisplayHandle,
+        button: u32,
+        state: ButtonState,
+    ) -> u32 {
+        let serial = handle.next_serial();
+
+        match state {
+            ButtonState::Pressed => {
+                if !self.pressed.contains(&button) {
+                    self.pressed.push(button);
+                }
+
+                self.button_serial = Some(serial);
+            }
+            ButtonState::Released => self.pressed.retain(|other| *other != button),
+        }
+
+        serial
+    }
+
+    /// Returns the resources of the client owning the focus.
+    fn focused(&self) -> impl Iterator<Item = Resource> + '_ {
+        self.resources.iter().copied().filter(|resource| {
+            self.focus
+                .is_some_and(|focus| focus.client == resource.client)
+        })
+    }
+
+    /// Adds a `wl_pointer`, entering the focus if its client owns it.
+    pub(super) fn add_resource(&mut self, handle: &mut DisplayHandle, pointer: Resource) {
+        self.resources.push(pointer);
+
+        if let Some(focus) = self.focus
+            && focus.client == pointer.client
+            && is_alive(handle, focus)
+        {
+            send_enter(handle, pointer, self.enter_serial, focus, self.location);
+            handle.send_event(pointer, wl_pointer::Event::Frame);
+        }
+    }
+}
+
+fn send_enter(
+    handle: &mut DisplayHandle,
+    pointer: Resource,
+    serial: u32,
+    surface: Resource,
+    (x, y): (f64, f64),
+) {
+    handle.send_event(
+        pointer,
+        wl_pointer::Event::Enter {
+            serial,
+            surface: surface.id,
+            surface_x: Fixed::from_f64(x),
+            surface_y: Fixed::from_f64(y),
+        },
+    );
+}
+
+impl Seat {
+    /// Moves the pointer to a surface-local `location` on `focus`.
+    ///
+    /// Changing the focus sends `leave` to the previous surface and `enter` to
+    /// the new one, otherwise `motion` is sent. Events are grouped until
+    /// [`pointer_frame`](Self::pointer_frame), apart from `leave` which ends
+    /// the frame of the previous client.
+    ///
+    /// This bypasses grabs, see [`SeatState::pointer_motion`].
+    ///
+    /// [`SeatState::pointer_motion`]: super::SeatState::pointer_motion
+    pub fn pointer_motion(
+        &mut self,
+        handle: &mut DisplayHandle,
+        time: u32,
+        focus: Option<Resource>,
+        location: (f64, f64),
+    ) {
+        let pointer = &mut self.pointer;
+        pointer.location = location;
+
+        if pointer.focus == focus {
+            let (x, y) = location;
+
+            for resource in pointer.focused() {
+                handle.send_event(
+                    resource,
+                    wl_pointer::Event::Motion {
+                        time,
+                        surface_x: Fixed::from_f64(x),
+                        surface_y: Fixed::from_f64(y),
+                    },
+                );
+            }
+
+            return;
+        }
+
+        if let Some(previous) = pointer.focus
+            && is_alive(handle, previous)
+        {
+            let serial = handle.next_serial();
+
+            for resource in pointer.focused() {
+                handle.send_event(
+                    resource,
+                    wl_pointer::Event::Leave {
+                        serial,
+                        surface: previous.id,
+                    },
+                );
+                handle.send_event(resource, wl_pointer::Event::Frame);
+            }
+        }
+
+        pointer.focus = focus;
+
+        if let Some(focus) = focus {
+            let serial = handle.next_serial();
+            pointer.enter_serial = serial;
+
+            for resource in pointer.focused() {
+                send_enter(handle, resource, serial, focus, location);
+            }
+        }
+    }
+
+    /// Removes the pointer focus, sending `leave` to the surface.
+    pub fn clear_pointer_focus(&mut self, handle: &mut DisplayHandle) {
+        // The time is only sent with motion, not when leaving
+        let location = self.pointer.location;
+        self.pointer_motion(handle, 0, None, location);
+    }
+
+    /// Sends a button press or release to the focus, returning its serial.
+    ///
+    /// Clients refer to the serial of presses to start grabs, such as
+    /// interactive moves or popups. This bypasses grabs, see
+    /// [`SeatState::pointer_button`].
+    ///
+    /// [`SeatState::pointer_button`]: super::SeatState::pointer_button
+    pub fn pointer_button(
+        &mut self,
+        handle: &mut DisplayHandle,
+        time: u32,
+        button: u32,
+        state: ButtonState,
+    ) -> u32 {
+        let pointer = &mut self.pointer;
+        let serial = pointer.track_button(handle, button, state);
+
+        for resource in pointer.focused() {
+            handle.send_event(
+                resource,
+                wl_pointer::Event::Button {
+                    serial,
+                    time,
+                    button,
+                    state: state.into(),
+                },
+            );
+        }
+
+        serial
+    }
+
+    /// Sends scrolling to the focus.
+    ///
+    /// Wheel steps are sent as `axis_value120` or `axis_discrete` depending
+    /// on the version of each `wl_pointer`.
+    pub fn pointer_axis(&mut self, handle: &mut DisplayHandle, frame: AxisFrame) {
+        let axes = [
+            (Axis::HorizontalScroll, frame.horizontal),
+            (Axis::VerticalScroll, frame.vertical),
+        ];
+
+        for resource in self.pointer.focused() {
+            let version = handle.version(resource);
+
+            if let Some(source) = frame.source {
+                handle.send_event(
+                    resource,
+                    wl_pointer::Event::AxisSource {
+                        axis_source: source.into(),
+                    },
+                );
+            }
+
+            for (axis, value) in axes {
+                if !value.is_empty() {
+                    let direction = match value.inverted {
+                        true => AxisRelativeDirection::Inverted,
+                        false => AxisRelativeDirection::Identical,
+                    };
+
+                    handle.send_event(
+                        resource,
+                        wl_pointer::Event::AxisRelativeDirection {
+                            axis: axis.into(),
+                            direction: direction.into(),
+                        },
+                    );
+
+                    if version >= 8 {
+                        if value.value120 != 0 {
+                            handle.send_event(
+                                resource,
+                                wl_pointer::Event::AxisValue120 {
+                                    axis: axis.into(),
+                                    value120: value.value120,
+                                },
+                            );
+                        }
+                    } else if value.discrete != 0 {
+                        handle.send_event(
+                            resource,
+                            wl_pointer::Event::AxisDiscrete {
+                                axis: axis.into(),
+                                discrete: value.discrete,
+                            },
+                        );
+                    }
+
+                    handle.send_event(
+                        resource,
+                        wl_pointer::Event::Axis {
+                            time: frame.time,
+                            axis: axis.into(),
+                            value: Fixed::from_f64(value.value),
+                        },
+                    );
+                }
+
+                if value.stop {
+                    handle.send_event(
+                        resource,
+                        wl_pointer::Event::AxisStop {
+                            time: frame.time,
+                            axis: axis.into(),
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    /// Ends a group of pointer events sent to the focus.
+    pub fn pointer_frame(&mut self, handle: &mut DisplayHandle) {
+        for resource in self.pointer.focused() {
+            handle.send_event(resource, wl_pointer::Event::Frame);
+        }
+    }
+}
+
+pub(super) struct PointerDispatch;
+
+impl<D: SeatHandler> Dispatch<D> for PointerDispatch {
+    fn request(
+        &mut self,
+        state: &mut D,
+        handle: &mut DisplayHandle,
+        resource: Resource,
+        message: Message,
+    ) -> Result<(), ProtocolError> {
+        let request = wl_pointer::Request::from_message(message)
+            .map_err(|err| ProtocolError::malformed(resource.id, err))?;
+
+        let wl_pointer::Request::SetCursor {
+            serial,
+            surface,
+            hotspot_x,
+            hotspot_y,
+        } = request
+        else {
+            return Ok(());
+        };
+
+        let Some(seat) = state.seat_state().resource_seat(resource) else {
+            return Ok(());
+        };
+        let (id, pointer) = (seat.id, &seat.pointer);
+
+        // Only the client with pointer focus may set the cursor
+        let focused = pointer
+            .focus
+            .is_some_and(|focus| focus.client == resource.client);
+
+        if !focused || serial != pointer.enter_serial {
+            return Ok(());
+        }
+
+        let image = match surface {
+            Some(surface) => {
+                let surface = Resource::new(resource.client, surface);
+                let Some(data) = state.compositor_state().surface_mut(surface) else {
+                    return Err(ProtocolError::invalid_object(surface.id.get()));
+                };
+
+                if !data.set_role(CURSOR_ROLE) {
+                    return Err(ProtocolError::new(
+                        resource.id,
+                        wl_pointer::Error::Role,
+                        format!("wl_surface@{} already has another role", surface.id),
+                    ));
+                }
+
+                CursorImage::Surface {
+                    surface,
+                    hotspot: Point::new(hotspot_x, hotspot_y),
+                }
+            }
+            None => CursorImage::Hidden,
+        };
+
+        state.cursor_image(handle, id, image);
+
+        Ok(())
+    }
+
+    fn destroyed(&mut self, state: &mut D, _handle: &mut DisplayHandle, resource: Resource) {
+        let seats = state.seat_state();
+
+        if let Some(seat) = seats.resource_seat(resource) {
+            seat.pointer.resources.retain(|other| *other != resource);
+        }
+
+        seats.resources.remove(&resource);
+    }
+}
@@ -0,0 +1,182 @@
+use super::{Seat, SeatHandler};
+use crate::{
+    protocol::{MessageGroup, wayland::wl_touch},
+    server::{
+        client::{ClientId, Resource},
+        display::{Dispatch, DisplayHandle},
+        error::ProtocolError,
+    },
+    wire::{Fixed, Message},
+};
+use std::collections::HashMap;
+
+/// State of a seat's touch device.
+#[derive(Debug, Default)]
+pub struct Touch {
+    pub(super) resources: Vec<Resource>,
+    /// Surface each touch point went down on.
+    points: HashMap<i32, Resource>,
+    /// Clients that received events since the last frame.
+    unframed: Vec<ClientId>,
+}
+
+impl Touch {
+    /// Returns the surface a touch point went down on.
+    pub fn point_surface(&self, id: i32) -> Option<Resource> {
+        self.points.get(&id).copied()
+    }
+
+    /// Returns an iterator over the touch points and their surface.
+    pub fn points(&self) -> impl Iterator<Item = (i32, Resource)> + '_ {
+        self.points.iter().map(|(id, surface)| (*id, *surface))
+    }
+
+    /// Returns the resources of `client`, marking it for the next frame.
+    fn client_resources(&mut self, client: ClientId) -> impl Iterator<Item = Resource> + '_ {
+        if !self.unframed.contains(&client) {
+            self.unframed.push(client);
+        }
+
+        self.resources
+            .iter()
+            .copied()
+            .filter(move |resource| resource.client == client)
+    }
+}
+
+impl Seat {
+    /// Starts a touch point on `surface` at a surface-local `location`,
+    /// returning the serial of the event.
+    ///
+    /// Later events of the point go to the same surface until it is lifted.
+    pub fn touch_down(
+        &mut self,
+        handle: &mut DisplayHandle,
+        time: u32,
+        id: i32,
+        surface: Resource,
+        (x, y): (f64, f64),
+    ) -> u32 {
+        let serial = handle.next_serial();
+        self.touch.points.insert(id, surface);
+
+        for resource in self.touch.client_resources(surface.client) {
+            handle.send_event(
+                resource,
+                wl_touch::Event::Down {
+                    serial,
+                    time,
+                    surface: surface.id,
+                    id,
+                    x: Fixed::from_f64(x),
+                    y: Fixed::from_f64(y),
+                },
+            );
+        }
+
+        serial
+    }
+
+    /// Moves a touch point, `location` being relative to its surface.
+    pub fn touch_motion(
+        &mut self,
+        handle: &mut DisplayHandle,
+        time: u32,
+        id: i32,
+        (x, y): (f64, f64),
+    ) {
+        let Some(surface) = self.touch.point_surface(id) else {
+            return;
+        };
+
+        for resource in self.touch.client_resources(surface.client) {
+            handle.send_event(
+                resource,
+                wl_touch::Event::Motion {
+                    time,
+                    id,
+                    x: Fixed::from_f64(x),
+                    y: Fixed::from_f64(y),
+                },
+            );
+        }
+    }
+
+    /// Ends a touch point, returning the serial of the event.
+    ///
+    /// Returns `None` if the point does not exist.
+    pub fn touch_up(&mut self, handle: &mut DisplayHandle, time: u32, id: i32) -> Option<u32> {
+        let surface = self.touch.points.remove(&id)?;
+        let serial = handle.next_serial();
+
+        for resource in self.touch.client_resources(surface.client) {
+            handle.send_event(resource, wl_touch::Event::Up { serial, time, id });
+        }
+
+        Some(serial)
+    }
+
+    /// Ends a group of touch events, to every client that received one.
+    pub fn touch_frame(&mut self, handle: &mut DisplayHandle) {
+        let touch = &mut self.touch;
+
+        for client in touch.unframed.drain(..) {
+            for resource in &touch.resources {
+                if resource.client == client {
+                    handle.send_event(*resource, wl_touch::Event::Frame);
+                }
+            }
+        }
+    }
+
+    /// Cancels all touch points, for instance when the compositor recognized
+    /// a gesture.
+    pub fn touch_cancel(&mut self, handle: &mut DisplayHandle) {
+        let touch = &mut self.touch;
+        let mut clients: Vec<ClientId> = touch
+            .points
+            .values()
+            .map(|surface| surface.client)
+            .collect();
+        clients.append(&mut touch.unframed);
+        clients.sort_unstable();
+        clients.dedup();
+
+        touch.points.clear();
+
+        for resource in &touch.resources {
+            if clients.contains(&resource.client) {
+                handle.send_event(*resource, wl_touch::Event::Cancel);
+            }
+        }
+    }
+}
+
+pub(super) struct TouchDispatch;
+
+impl<D: SeatHandler> Dispatch<D> for TouchDispatch {
+    fn request(
+        &mut self,
+        _state: &mut D,
+        _handle: &mut DisplayHandle,
+        resource: Resource,
+        message: Message,
+    ) -> Result<(), ProtocolError> {
+        let request = wl_touch::Request::from_message(message)
+            .map_err(|err| ProtocolError::malformed(resource.id, err))?;
+
+        match request {
+            wl_touch::Request::Release => Ok(()),
+        }
+    }
+
+    fn destroyed(&mut self, state: &mut D, _handle: &mut DisplayHandle, resource: Resource) {
+        let seats = state.seat_state();
+
+        if let Some(seat) = seats.resource_seat(resource) {
+            seat.touch.resources.retain(|other| *other != resource);
+        }
+
+        seats.resources.remove(&resource);
+    }
+}
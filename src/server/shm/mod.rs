@@ -0,0 +1,457 @@
+mod pool;
+
+use self::pool::Pool;
+use super::{
+    client::Resource,
+    display::{Dispatch, Display, DisplayHandle},
+    error::ProtocolError,
+};
+use crate::{
+    protocol::{
+        MessageGroup, WEnum,
+        wayland::{wl_buffer, wl_shm, wl_shm_pool},
+    },
+    wire::Message,
+};
+use std::{collections::HashMap, sync::Arc};
+use thiserror::Error;
+
+/// Version of the `wl_shm` global.
+const VERSION: u32 = 2;
+
+/// Errors returned when accessing the contents of a buffer.
+#[derive(Debug, Error)]
+pub enum ShmAccessError {
+    #[error("buffer is not a wl_shm buffer")]
+    NotShm,
+    #[error("client truncated the pool backing the buffer")]
+    Truncated,
+}
+
+/// Layout of a `wl_shm` buffer within its pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferData {
+    /// Offset of the first pixel from the start of the pool, in bytes.
+    pub offset: i32,
+    pub width: i32,
+    pub height: i32,
+    /// Length of a row, in bytes.
+    pub stride: i32,
+    pub format: wl_shm::Format,
+}
+
+#[derive(Debug)]
+struct ShmBuffer {
+    pool: Arc<Pool>,
+    data: BufferData,
+}
+
+/// State of the `wl_shm` global.
+#[derive(Debug)]
+pub struct ShmState {
+    formats: Vec<wl_shm::Format>,
+    pools: HashMap<Resource, Arc<Pool>>,
+    buffers: HashMap<Resource, ShmBuffer>,
+}
+
+/// Access to [`ShmState`] from the display state.
+pub trait ShmHandler: Sized + 'static {
+    /// Returns the shm state.
+    fn shm_state(&mut self) -> &mut ShmState;
+
+    /// Called when a shm buffer is destroyed.
+    fn buffer_destroyed(&mut self, handle: &mut DisplayHandle, buffer: Resource) {
+        let _ = (handle, buffer);
+    }
+}
+
+impl ShmState {
+    /// Creates the `wl_shm` global and handles its objects.
+    ///
+    /// `argb8888` and `xrgb8888` are always advertised, `formats` are added to them.
+    pub fn new<D: ShmHandler>(
+        display: &mut Display<D>,
+        formats: impl IntoIterator<Item = wl_shm::Format>,
+    ) -> Self {
+        let mut all = vec![wl_shm::Format::Argb8888, wl_shm::Format::Xrgb8888];
+
+        for format in formats {
+            if !all.contains(&format) {
+                all.push(format);
+            }
+        }
+
+        display.register(&wl_shm::INTERFACE, ShmDispatch);
+        display.register(&wl_shm_pool::INTERFACE, PoolDispatch);
+        display.register(&wl_buffer::INTERFACE, BufferDispatch);
+
+        display.create_global(
+            &wl_shm::INTERFACE,
+            VERSION,
+            |state: &mut D, handle: &mut DisplayHandle, shm| {
+                for format in &state.shm_state().formats {
+                    handle.send_event(
+                        shm,
+                        wl_shm::Event::Format {
+                            format: WEnum::Value(*format),
+                        },
+                    );
+                }
+
+                Ok(())
+            },
+        );
+
+        ShmState {
+            formats: all,
+            pools: HashMap::new(),
+            buffers: HashMap::new(),
+        }
+    }
+
+    /// Returns the advertised formats.
+    pub fn formats(&self) -> &[wl_shm::Format] {
+        &self.formats
+    }
+
+    /// Returns the layout of a shm buffer.
+    pub fn buffer_data(&self, buffer: Resource) -> Option<BufferData> {
+        self.buffers.get(&buffer).map(|buffer| buffer.data)
+    }
+
+    /// Calls `f` with the start and length of the pool backing a buffer, and
+    /// the layout of the buffer within it.
+    ///
+    /// The memory is shared with the client, which may write to it at any
+    /// time. If the client truncated the pool, it receives a protocol error
+    /// and [`ShmAccessError::Truncated`] is returned instead of crashing on
+    /// `SIGBUS`.
+    pub fn with_buffer_contents<T>(
+        &self,
+        handle: &mut DisplayHandle,
+        buffer: Resource,
+        f: impl FnOnce(*const u8, usize, BufferData) -> T,
+    ) -> Result<T, ShmAccessError> {
+        let shm_buffer = self.buffers.get(&buffer).ok_or(ShmAccessError::NotShm)?;
+        let data = shm_buffer.data;
+
+        shm_buffer
+            .pool
+            .with_data(|ptr, len| f(ptr, len, data))
+            .map_err(|_| {
+                let error = ProtocolError::new(
+                    buffer.id,
+                    wl_shm::Error::InvalidFd,
+                    "error accessing SHM buffer",
+                );
+
+                handle.post_error(buffer.client, error);
+
+                ShmAccessError::Truncated
+            })
+    }
+}
+
+struct ShmDispatch;
+
+impl<D: ShmHandler> Dispatch<D> for ShmDispatch {
+    fn request(
+        &mut self,
+        state: &mut D,
+        _handle: &mut DisplayHandle,
+        resource: Resource,
+        message: Message,
+    ) -> Result<(), ProtocolError> {
+        let request = wl_shm::Request::from_message(message)
+            .map_err(|err| ProtocolError::malformed(resource.id, err))?;
+
+        match request {
+            wl_shm::Request::CreatePool { id, fd, size } => {
+                if size <= 0 {
+                    return Err(ProtocolError::new(
+                        resource.id,
+                        wl_shm::Error::InvalidStride,
+                        format!("invalid size ({size})"),
+                    ));
+                }
+
+                let pool = Pool::new(fd, size as usize).map_err(|err| {
+                    ProtocolError::new(
+                        resource.id,
+                        wl_shm::Error::InvalidFd,
+                        format!("failed mmap of pool: {err}"),
+                    )
+                })?;
+
+                state
+                    .shm_state()
+                    .pools
+                    .insert(Resource::new(resource.client, id), Arc::new(pool));
+            }
+            wl_shm::Request::Release => {}
+        }
+
+        Ok(())
+    }
+}
+
+struct PoolDispatch;
+
+impl<D: ShmHandler> Dispatch<D> for PoolDispatch {
+    fn request(
+        &mut self,
+        state: &mut D,
+        _handle: &mut DisplayHandle,
+        resource: Resource,
+        message: Message,
+    ) -> Result<(), ProtocolError> {
+        let request = wl_shm_pool::Request::from_message(message)
+            .map_err(|err| ProtocolError::malformed(resource.id, err))?;
+
+        let shm = state.shm_state();
+        let pool = shm
+            .pools
+            .get(&resource)
+            .expect("live wl_shm_pool has a pool");
+
+        match request {
+            wl_shm_pool::Request::CreateBuffer {
+                id,
+                offset,
+                width,
+                height,
+                stride,
+                format,
+            } => {
+                let format = match format {
+                    WEnum::Value(format) if shm.formats.contains(&format) => format,
+                    format => {
+                        return Err(ProtocolError::new(
+                            resource.id,
+                            wl_shm::Error::InvalidFormat,
+                            format!("invalid format {:#x}", format.into_raw()),
+                        ));
+                    }
+                };
+
+                let pool_size = pool.size() as i64;
+
+                if offset < 0
+                    || width <= 0
+                    || height <= 0
+                    || stride < width
+                    || i32::MAX / stride < height
+                    || offset as i64 > pool_size - stride as i64 * height as i64
+                {
+                    return Err(ProtocolError::new(
+                        resource.id,
+                        wl_shm::Error::InvalidStride,
+                        format!("invalid width, height or stride ({width}x{height}, {stride})"),
+                    ));
+                }
+
+                let buffer = ShmBuffer {
+                    pool: pool.clone(),
+                    data: BufferData {
+                        offset,
+                        width,
+                        height,
+                        stride,
+                        format,
+                    },
+                };
+
+                shm.buffers
+                    .insert(Resource::new(resource.client, id), buffer);
+            }
+            wl_shm_pool::Request::Destroy => {}
+            wl_shm_pool::Request::Resize { size } => {
+                if size < 0 || (size as usize) < pool.size() {
+                    return Err(ProtocolError::new(
+                        resource.id,
+                        wl_shm::Error::InvalidStride,
+                        "shrinking pool invalid",
+                    ));
+                }
+
+                pool.resize(size as usize).map_err(|err| {
+                    ProtocolError::new(
+                        resource.id,
+                        wl_shm::Error::InvalidFd,
+                        format!("failed mremap of pool: {err}"),
+                    )
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn destroyed(&mut self, state: &mut D, _handle: &mut DisplayHandle, resource: Resource) {
+        // Buffers keep the memory alive
+        state.shm_state().pools.remove(&resource);
+    }
+}
+
+struct BufferDispatch;
+
+impl<D: ShmHandler> Dispatch<D> for BufferDispatch {
+    fn request(
+        &mut self,
+        _state: &mut D,
+        _handle: &mut DisplayHandle,
+        _resource: Resource,
+        _message: Message,
+    ) -> Result<(), ProtocolError> {
+        // The only request is the destructor
+        Ok(())
+    }
+
+    fn destroyed(&mut self, state: &mut D, handle: &mut DisplayHandle, resource: Resource) {
+        if state.shm_state().buffers.remove(&resource).is_some() {
+            state.buffer_destroyed(handle, resource);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        testing::{Fixture, TestClient, protocol_error},
+        wire::ObjectId,
+    };
+    use rustix::fs::{self, MemfdFlags};
+    use std::{os::fd::OwnedFd, ptr};
+
+    /// Creates a pool of `size` bytes, returning the file behind it.
+    async fn pool(size: i32) -> (Fixture, TestClient, ObjectId, OwnedFd) {
+        let mut fixture = Fixture::new();
+        let mut client = fixture.connect().await;
+
+        let fd = fs::memfd_create("alow-test", MemfdFlags::CLOEXEC).unwrap();
+        fs::ftruncate(&fd, size as u64).unwrap();
+
+        let shm = client.bind(&wl_shm::INTERFACE, 1);
+        let pool = client.create(&wl_shm_pool::INTERFACE, 1);
+        client.queue(
+            shm,
+            wl_shm::Request::CreatePool {
+                id: pool,
+                fd: fd.try_clone().unwrap(),
+                size,
+            },
+        );
+
+        (fixture, client, pool, fd)
+    }
+
+    fn create_buffer(
+        client: &mut TestClient,
+        pool: ObjectId,
+        offset: i32,
+        (width, height): (i32, i32),
+        stride: i32,
+    ) -> ObjectId {
+        let buffer = client.create(&wl_buffer::INTERFACE, 1);
+        client.queue(
+            pool,
+            wl_shm_pool::Request::CreateBuffer {
+                id: buffer,
+                offset,
+                width,
+                height,
+                stride,
+                format: WEnum::Value(wl_shm::Format::Argb8888),
+            },
+        );
+
+        buffer
+    }
+
+    /// Returns the code of the error raised on the pool by creating a buffer
+    /// in a 4 KiB pool.
+    async fn create_buffer_error(offset: i32, size: (i32, i32), stride: i32) -> Option<u32> {
+        let (mut fixture, mut client, pool, _fd) = pool(4096).await;
+        create_buffer(&mut client, pool, offset, size, stride);
+
+        let mut events = fixture.roundtrip(&mut client).await;
+        protocol_error(&mut events).map(|(object, code)| {
+            assert_eq!(object, pool);
+            code
+        })
+    }
+
+    #[tokio::test]
+    async fn truncated_pool() {
+        let (mut fixture, mut client, pool, fd) = pool(4 * 4096).await;
+        let buffer = create_buffer(&mut client, pool, 0, (64, 64), 256);
+
+        let mut events = fixture.roundtrip(&mut client).await;
+        assert_eq!(protocol_error(&mut events), None);
+
+        let buffer = Resource::new(client.id, buffer);
+        let read = |ptr: *const u8, len: usize, _| {
+            (0..len)
+                .step_by(4096)
+                // SAFETY: the offset is within the mapping
+                .map(|offset| unsafe { ptr::read_volatile(ptr.add(offset)) })
+                .fold(0u8, u8::wrapping_add)
+        };
+
+        let contents =
+            fixture
+                .state
+                .shm
+                .with_buffer_contents(fixture.display.handle_mut(), buffer, read);
+        assert!(matches!(contents, Ok(0)));
+
+        fs::ftruncate(&fd, 0).unwrap();
+
+        let contents =
+            fixture
+                .state
+                .shm
+                .with_buffer_contents(fixture.display.handle_mut(), buffer, read);
+        assert!(matches!(contents, Err(ShmAccessError::Truncated)));
+
+        let mut events = fixture.roundtrip(&mut client).await;
+        assert_eq!(
+            protocol_error(&mut events),
+            Some((buffer.id, wl_shm::Error::InvalidFd.into())),
+        );
+    }
+
+    #[tokio::test]
+    async fn buffer_bounds() {
+        assert_eq!(create_buffer_error(0, (16, 16), 256).await, None);
+
+        let invalid = Some(wl_shm::Error::InvalidStride.into());
+
+        // Past the end of the pool
+        assert_eq!(create_buffer_error(1, (16, 16), 256).await, invalid);
+        // Rows overlapping
+        assert_eq!(create_buffer_error(0, (64, 1), 63).await, invalid);
+        // Size overflowing
+        assert_eq!(create_buffer_error(0, (1, 32768), 65536).await, invalid);
+    }
+
+    #[tokio::test]
+    async fn resize() {
+        let (mut fixture, mut client, pool, fd) = pool(4096).await;
+
+        fs::ftruncate(&fd, 8192).unwrap();
+        client.queue(pool, wl_shm_pool::Request::Resize { size: 8192 });
+
+        let mut events = fixture.roundtrip(&mut client).await;
+        assert_eq!(protocol_error(&mut events), None);
+
+        client.queue(pool, wl_shm_pool::Request::Resize { size: 4096 });
+
+        let mut events = fixture.roundtrip(&mut client).await;
+        assert_eq!(
+            protocol_error(&mut events),
+            Some((pool, wl_shm::Error::InvalidStride.into())),
+        );
+    }
+}
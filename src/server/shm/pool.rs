@@ -0,0 +1,223 @@
+use rustix::mm::{self, MapFlags, MremapFlags, ProtFlags};
+use std::{
+    cell::Cell,
+    ffi::c_void,
+    io, mem,
+    os::fd::OwnedFd,
+    ptr,
+    sync::{Once, OnceLock, RwLock},
+};
+
+/// Mapping being accessed by the current thread, checked by the `SIGBUS` handler.
+#[derive(Clone, Copy)]
+struct Access {
+    start: usize,
+    len: usize,
+    faulted: bool,
+}
+
+thread_local! {
+    static ACCESS: Cell<Access> = const {
+        Cell::new(Access {
+            start: 0,
+            len: 0,
+            faulted: false,
+        })
+    };
+}
+
+/// Handler that was installed before ours.
+static PREVIOUS_SIGBUS: OnceLock<libc::sigaction> = OnceLock::new();
+
+/// Memory of a `wl_shm_pool`, shared with the client.
+#[derive(Debug)]
+pub(crate) struct Pool {
+    map: RwLock<Mapping>,
+}
+
+#[derive(Debug)]
+struct Mapping {
+    ptr: *mut c_void,
+    len: usize,
+    /// Set once the client truncated the file, the memory is then replaced by zeroes.
+    broken: bool,
+}
+
+// SAFETY: the mapping is plain shared memory, access is synchronized by the lock
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+/// The client truncated the pool while it was being read.
+#[derive(Debug)]
+pub(crate) struct Truncated;
+
+impl Pool {
+    /// Maps `size` bytes of `fd`, the mapping stays valid after the fd is closed.
+    pub(crate) fn new(fd: OwnedFd, size: usize) -> io::Result<Self> {
+        // SAFETY: a new mapping does not alias any Rust memory
+        let ptr = unsafe {
+            mm::mmap(
+                ptr::null_mut(),
+                size,
+                ProtFlags::READ | ProtFlags::WRITE,
+                MapFlags::SHARED,
+                &fd,
+                0,
+            )?
+        };
+
+        Ok(Pool {
+            map: RwLock::new(Mapping {
+                ptr,
+                len: size,
+                broken: false,
+            }),
+        })
+    }
+
+    /// Returns the size of the pool.
+    pub(crate) fn size(&self) -> usize {
+        self.map.read().unwrap_or_else(|err| err.into_inner()).len
+    }
+
+    /// Grows the mapping to `size` bytes.
+    pub(crate) fn resize(&self, size: usize) -> io::Result<()> {
+        let mut map = self.map.write().unwrap_or_else(|err| err.into_inner());
+
+        // SAFETY: no reference into the mapping outlives the read lock
+        map.ptr = unsafe { mm::mremap(map.ptr, map.len, size, MremapFlags::MAYMOVE)? };
+        map.len = size;
+
+        Ok(())
+    }
+
+    /// Calls `f` with the address and length of the mapping.
+    ///
+    /// Reads past the end of a truncated file fault with `SIGBUS`, which is
+    /// caught for the duration of `f`: the pool is then filled with zeroes
+    /// from the faulting point on and `Truncated` is returned.
+    pub(crate) fn with_data<T>(
+        &self,
+        f: impl FnOnce(*const u8, usize) -> T,
+    ) -> Result<T, Truncated> {
+        install_sigbus_handler();
+
+        let map = self.map.read().unwrap_or_else(|err| err.into_inner());
+
+        if map.broken {
+            return Err(Truncated);
+        }
+
+        ACCESS.set(Access {
+            start: map.ptr as usize,
+            len: map.len,
+            faulted: false,
+        });
+
+        let value = f(map.ptr.cast(), map.len);
+
+        let access = ACCESS.replace(Access {
+            start: 0,
+            len: 0,
+            faulted: false,
+        });
+
+        drop(map);
+
+        if access.faulted {
+            self.map
+                .write()
+                .unwrap_or_else(|err| err.into_inner())
+                .broken = true;
+            return Err(Truncated);
+        }
+
+        Ok(value)
+    }
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        let map = self.map.get_mut().unwrap_or_else(|err| err.into_inner());
+
+        // SAFETY: the pool is the only owner of the mapping
+        let _ = unsafe { mm::munmap(map.ptr, map.len) };
+    }
+}
+
+/// Installs the process-wide `SIGBUS` handler, once.
+fn install_sigbus_handler() {
+    static INSTALL: Once = Once::new();
+
+    INSTALL.call_once(|| {
+        // SAFETY: the handler only touches a const thread local and async-signal-safe calls
+        unsafe {
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = sigbus_handler as *const () as usize;
+            action.sa_flags = libc::SA_SIGINFO | libc::SA_NODEFER;
+            libc::sigemptyset(&mut action.sa_mask);
+
+            let mut previous: libc::sigaction = mem::zeroed();
+
+            if libc::sigaction(libc::SIGBUS, &action, &mut previous) == 0 {
+                let _ = PREVIOUS_SIGBUS.set(previous);
+            }
+        }
+    });
+}
+
+extern "C" fn sigbus_handler(
+    signal: libc::c_int,
+    info: *mut libc::siginfo_t,
+    context: *mut c_void,
+) {
+    // SAFETY: the kernel passes a valid siginfo for SA_SIGINFO handlers
+    let addr = unsafe { (*info).si_addr() } as usize;
+    let access = ACCESS.get();
+
+    if access.len > 0 && (access.start..access.start + access.len).contains(&addr) {
+        // Replace the pages with zeroes so that the access can complete
+        // SAFETY: the range is the pool mapping, held by the thread that faulted
+        let result = unsafe {
+            mm::mmap_anonymous(
+                access.start as *mut c_void,
+                access.len,
+                ProtFlags::READ | ProtFlags::WRITE,
+                MapFlags::FIXED | MapFlags::PRIVATE,
+            )
+        };
+
+        if result.is_ok() {
+            ACCESS.set(Access {
+                faulted: true,
+                ..access
+            });
+            return;
+        }
+    }
+
+    // Not ours, hand it to the previous handler or die
+    // SAFETY: calling the previously installed handler with the original arguments
+    unsafe {
+        match PREVIOUS_SIGBUS.get() {
+            Some(previous)
+                if previous.sa_sigaction != libc::SIG_DFL
+                    && previous.sa_sigaction != libc::SIG_IGN =>
+            {
+                if previous.sa_flags & libc::SA_SIGINFO != 0 {
+                    let handler: extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut c_void) =
+                        mem::transmute(previous.sa_sigaction);
+                    handler(signal, info, context);
+                } else {
+                    let handler: extern "C" fn(libc::c_int) = mem::transmute(previous.sa_sigaction);
+                    handler(signal);
+                }
+            }
+            _ => {
+                // Signals sent with kill would be lost by just returning
+                libc::signal(libc::SIGBUS, libc::SIG_DFL);
+                libc::raise(signal);
+            }
+        }
+    }
+}
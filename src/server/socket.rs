@@ -1,9 +1,16 @@
-use rustix::fs;
+use super::credentials::Credentials;
+use crate::env;
+use rustix::{
+    fs, io as rio,
+    net::{AddressFamily, SocketFlags, SocketType, socketpair, sockopt},
+};
 use std::{
     borrow::Cow,
-    env, io,
+    io,
     os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd},
+    os::unix::process::CommandExt,
     path::{Path, PathBuf},
+    process::Command,
 };
 use thiserror::Error;
 use tokio::net::{UnixListener, UnixStream};
@@ -23,14 +30,36 @@ pub enum SocketError {
     Bind(#[source] io::Error),
     #[error("could not accept incoming connection: {0}")]
     Accept(#[source] io::Error),
+    #[error("could not query peer credentials: {0}")]
+    Credentials(#[source] io::Error),
+    #[error("no matching socket passed in LISTEN_FDS")]
+    NoListenFd,
+    #[error("file descriptor is not a listening Unix stream socket")]
+    InvalidListenFd,
+    #[error("could not adopt listening socket: {0}")]
+    Adopt(#[source] io::Error),
 }
 
+/// First file descriptor passed by socket activation.
+const LISTEN_FDS_START: RawFd = 3;
+
 /// Wayland server socket.
+///
+/// Sockets bound by this type remove their socket and lock files on drop.
+/// Sockets adopted with [`WaylandSocket::from_listen_fds`] or
+/// [`WaylandSocket::from_owned_fd`] never touch the filesystem, the files
+/// belong to whoever created them.
 #[derive(Debug)]
 pub struct WaylandSocket {
     listener: UnixListener,
 
     name: String,
+    _files: Option<SocketFiles>,
+}
+
+/// Files created when binding a socket.
+#[derive(Debug)]
+struct SocketFiles {
     bind_path: PathBuf,
     lock_path: PathBuf,
 
@@ -101,21 +130,95 @@ impl WaylandSocket {
         Ok(WaylandSocket {
             listener,
             name: name.into(),
-            bind_path,
-            lock_path,
-            _lock,
+            _files: Some(SocketFiles {
+                bind_path,
+                lock_path,
+                _lock,
+            }),
         })
     }
 
-    /// Returns the name of the socket.
+    /// Adopts a socket passed by systemd-style socket activation.
+    ///
+    /// `LISTEN_PID` must match the current process. With a `name`, the socket
+    /// is picked by its entry in `LISTEN_FDNAMES`, otherwise the first one is
+    /// used. Each socket can only be adopted once, the environment is left
+    /// untouched.
+    pub fn from_listen_fds(name: Option<&str>) -> Result<Self, SocketError> {
+        let pid = std::env::var("LISTEN_PID").map_err(|_| SocketError::NoListenFd)?;
+
+        if pid.parse() != Ok(std::process::id()) {
+            return Err(SocketError::NoListenFd);
+        }
+
+        let count: RawFd = std::env::var("LISTEN_FDS")
+            .ok()
+            .and_then(|count| count.parse().ok())
+            .ok_or(SocketError::NoListenFd)?;
+
+        let names = std::env::var("LISTEN_FDNAMES").unwrap_or_default();
+        let fd = match name {
+            Some(name) => names
+                .split(':')
+                .position(|candidate| candidate == name)
+                .map(|index| LISTEN_FDS_START + index as RawFd),
+            None => Some(LISTEN_FDS_START),
+        }
+        .filter(|fd| *fd < LISTEN_FDS_START + count)
+        .ok_or(SocketError::NoListenFd)?;
+
+        let fd = env::take_inherited_fd(fd).ok_or(SocketError::NoListenFd)?;
+
+        Self::from_owned_fd(fd)
+    }
+
+    /// Adopts an already bound and listening socket.
+    ///
+    /// The name is taken from the bound path, as the file name if the socket
+    /// lives directly in `XDG_RUNTIME_DIR` and as the full path otherwise.
+    pub fn from_owned_fd(fd: OwnedFd) -> Result<Self, SocketError> {
+        let valid = sockopt::socket_domain(&fd).is_ok_and(|domain| domain == AddressFamily::UNIX)
+            && sockopt::socket_type(&fd).is_ok_and(|kind| kind == SocketType::STREAM)
+            && sockopt::socket_acceptconn(&fd).unwrap_or(false);
+
+        if !valid {
+            return Err(SocketError::InvalidListenFd);
+        }
+
+        rio::ioctl_fionbio(&fd, true).map_err(|err| SocketError::Adopt(err.into()))?;
+
+        let listener = std::os::unix::net::UnixListener::from(fd);
+        let name = listener
+            .local_addr()
+            .ok()
+            .and_then(|addr| addr.as_pathname().map(socket_name))
+            .unwrap_or_default();
+
+        let listener = UnixListener::from_std(listener).map_err(SocketError::Adopt)?;
+
+        Ok(WaylandSocket {
+            listener,
+            name,
+            _files: None,
+        })
+    }
+
+    /// Returns the name of the socket, suitable for `WAYLAND_DISPLAY`.
+    ///
+    /// This is empty for adopted sockets that are not bound to a path.
     pub fn name(&self) -> &str {
         &self.name
     }
 
     /// Accepts a new connection.
-    pub async fn accept(&self) -> Result<UnixStream, SocketError> {
+    pub async fn accept(&self) -> Result<ClientConnection, SocketError> {
         let (stream, _) = self.listener.accept().await.map_err(SocketError::Accept)?;
-        Ok(stream)
+        let credentials = Credentials::from_socket(&stream).map_err(SocketError::Credentials)?;
+
+        Ok(ClientConnection {
+            stream,
+            credentials,
+        })
     }
 }
 
@@ -131,18 +234,127 @@ impl AsFd for WaylandSocket {
     }
 }
 
-impl Drop for WaylandSocket {
+impl Drop for SocketFiles {
     fn drop(&mut self) {
         let _ = fs::unlink(&self.bind_path);
         let _ = fs::unlink(&self.lock_path);
     }
 }
 
+/// Connection accepted by [`WaylandSocket`].
+#[derive(Debug)]
+pub struct ClientConnection {
+    stream: UnixStream,
+    credentials: Credentials,
+}
+
+impl ClientConnection {
+    /// Wraps an already connected stream, such as one end of a socket pair.
+    ///
+    /// For socket pairs, the credentials are those of the process that
+    /// created the pair.
+    pub fn from_stream(stream: UnixStream) -> io::Result<Self> {
+        let credentials = Credentials::from_socket(&stream)?;
+
+        Ok(ClientConnection {
+            stream,
+            credentials,
+        })
+    }
+
+    /// Wraps an already connected Unix stream socket.
+    pub fn from_owned_fd(fd: OwnedFd) -> io::Result<Self> {
+        let stream = std::os::unix::net::UnixStream::from(fd);
+        stream.set_nonblocking(true)?;
+
+        Self::from_stream(UnixStream::from_std(stream)?)
+    }
+
+    /// Returns the credentials of the connecting process.
+    pub fn credentials(&self) -> &Credentials {
+        &self.credentials
+    }
+
+    /// Returns the stream.
+    pub fn stream(&self) -> &UnixStream {
+        &self.stream
+    }
+
+    /// Splits the connection into the stream and the credentials.
+    pub fn into_parts(self) -> (UnixStream, Credentials) {
+        (self.stream, self.credentials)
+    }
+}
+
+/// Client end of a connection created by [`socket_pair`].
+#[derive(Debug)]
+pub struct ClientSocket {
+    fd: OwnedFd,
+}
+
+impl ClientSocket {
+    /// Passes the socket to a child process through `WAYLAND_SOCKET`.
+    ///
+    /// The descriptor is kept open in the child only, and closed in this
+    /// process once `command` is dropped.
+    pub fn pass_to(self, command: &mut Command) {
+        let fd = self.fd;
+
+        command.env("WAYLAND_SOCKET", fd.as_raw_fd().to_string());
+
+        // SAFETY: only calls fcntl, which is async-signal-safe
+        unsafe {
+            command.pre_exec(move || {
+                rio::fcntl_setfd(&fd, rio::FdFlags::empty()).map_err(io::Error::from)
+            });
+        }
+    }
+
+    /// Returns the underlying descriptor.
+    pub fn into_fd(self) -> OwnedFd {
+        self.fd
+    }
+}
+
+impl AsFd for ClientSocket {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+/// Creates a connected pair for a client spawned by the compositor.
+///
+/// The server end can be handed to
+/// [`DisplayHandle::insert_client`](super::display::DisplayHandle::insert_client),
+/// the client end to a child process with [`ClientSocket::pass_to`].
+pub fn socket_pair() -> io::Result<(ClientConnection, ClientSocket)> {
+    let (server, client) = socketpair(
+        AddressFamily::UNIX,
+        SocketType::STREAM,
+        SocketFlags::CLOEXEC,
+        None,
+    )?;
+
+    let connection = ClientConnection::from_owned_fd(server)?;
+
+    Ok((connection, ClientSocket { fd: client }))
+}
+
 /// Builds (bind_path, lock_path) from the given directory and socket name.
 fn build_paths(dir: &Path, name: &str) -> (PathBuf, PathBuf) {
     (dir.join(name), dir.join(format!("{name}.lock")))
 }
 
+/// Returns the `WAYLAND_DISPLAY` name of a socket bound at `path`.
+fn socket_name(path: &Path) -> String {
+    let in_runtime_dir = xdg_runtime_dir().is_ok_and(|dir| path.parent() == Some(&dir));
+
+    match path.file_name() {
+        Some(name) if in_runtime_dir => name.to_string_lossy().into_owned(),
+        _ => path.to_string_lossy().into_owned(),
+    }
+}
+
 /// Attempts to lock the file at the given path.
 ///
 /// If the file does not exist, it will be created.
@@ -165,13 +377,5 @@ fn lock_file(path: &Path) -> Result<OwnedFd, SocketError> {
 
 /// Returns the `XDG_RUNTIME_DIR` directory.
 fn xdg_runtime_dir() -> Result<PathBuf, SocketError> {
-    let dir = env::var("XDG_RUNTIME_DIR")
-        .map(PathBuf::from)
-        .map_err(|_| SocketError::RuntimeDirInvalid)?;
-
-    if !dir.is_absolute() {
-        return Err(SocketError::RuntimeDirInvalid);
-    }
-
-    Ok(dir)
+    env::xdg_runtime_dir().ok_or(SocketError::RuntimeDirInvalid)
 }
@@ -0,0 +1,583 @@
+mod popup;
+mod positioner;
+mod surface;
+mod toplevel;
+
+pub use self::{
+    popup::{POPUP_ROLE, Popup, PopupConfigure},
+    positioner::PositionerState,
+    surface::{XdgRole, XdgSurface},
+    toplevel::{TOPLEVEL_ROLE, Toplevel, ToplevelConfigure},
+};
+
+use self::{
+    popup::PopupDispatch, positioner::PositionerDispatch, surface::XdgSurfaceDispatch,
+    toplevel::ToplevelDispatch,
+};
+use super::{
+    client::{ClientId, Resource},
+    compositor::CompositorHandler,
+    display::{Dispatch, Display, DisplayHandle},
+    error::ProtocolError,
+};
+use crate::{
+    geometry::Point,
+    protocol::{
+        MessageGroup,
+        xdg_shell::{xdg_popup, xdg_positioner, xdg_surface, xdg_toplevel, xdg_wm_base},
+    },
+    wire::Message,
+};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// Version of the `xdg_wm_base` global.
+const VERSION: u32 = 6;
+
+/// Time a client has to answer a ping before it is considered unresponsive.
+pub const DEFAULT_PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// State of the `xdg_wm_base` global.
+pub struct XdgShellState {
+    /// Outstanding ping of each `xdg_wm_base`, with its serial.
+    wm_bases: HashMap<Resource, Option<(u32, Instant)>>,
+    positioners: HashMap<Resource, PositionerState>,
+    surfaces: HashMap<Resource, XdgSurface>,
+    /// `xdg_surface` of each `wl_surface`.
+    by_surface: HashMap<Resource, Resource>,
+    toplevels: HashMap<Resource, Toplevel>,
+    popups: HashMap<Resource, Popup>,
+    capabilities: Vec<xdg_toplevel::WmCapabilities>,
+    ping_timeout: Duration,
+}
+
+/// Access to [`XdgShellState`] and window management requests.
+///
+/// Requests from clients are hints, the default implementations ignore them.
+pub trait XdgShellHandler: CompositorHandler {
+    /// Returns the xdg-shell state.
+    fn xdg_shell_state(&mut self) -> &mut XdgShellState;
+
+    /// Called when a toplevel is created, to set up its initial configure.
+    ///
+    /// The initial configure is sent on the first commit of the surface.
+    fn new_toplevel(&mut self, handle: &mut DisplayHandle, toplevel: Resource) {
+        let _ = (handle, toplevel);
+    }
+
+    /// Called when a popup is created, to set up its initial configure.
+    ///
+    /// The initial configure is sent on the first commit of the surface, with
+    /// the unconstrained placement of the positioner unless changed, usually
+    /// to the result of [`PositionerState::constrain`].
+    fn new_popup(&mut self, handle: &mut DisplayHandle, popup: Resource) {
+        let _ = (handle, popup);
+    }
+
+    /// Called when a toplevel is destroyed.
+    fn toplevel_destroyed(&mut self, handle: &mut DisplayHandle, toplevel: Resource) {
+        let _ = (handle, toplevel);
+    }
+
+    /// Called when a popup is destroyed.
+    fn popup_destroyed(&mut self, handle: &mut DisplayHandle, popup: Resource) {
+        let _ = (handle, popup);
+    }
+
+    /// Called when a client answers a ping.
+    fn pong(&mut self, handle: &mut DisplayHandle, client: ClientId) {
+        let _ = (handle, client);
+    }
+
+    /// Called when the title of a toplevel changed.
+    fn title_changed(&mut self, handle: &mut DisplayHandle, toplevel: Resource) {
+        let _ = (handle, toplevel);
+    }
+
+    /// Called when the app id of a toplevel changed.
+    fn app_id_changed(&mut self, handle: &mut DisplayHandle, toplevel: Resource) {
+        let _ = (handle, toplevel);
+    }
+
+    /// Called when the parent of a toplevel changed.
+    fn parent_changed(&mut self, handle: &mut DisplayHandle, toplevel: Resource) {
+        let _ = (handle, toplevel);
+    }
+
+    /// Called when a client asks to start an interactive move.
+    fn move_request(
+        &mut self,
+        handle: &mut DisplayHandle,
+        toplevel: Resource,
+        seat: Resource,
+        serial: u32,
+    ) {
+        let _ = (handle, toplevel, seat, serial);
+    }
+
+    /// Called when a client asks to start an interactive resize.
+    fn resize_request(
+        &mut self,
+        handle: &mut DisplayHandle,
+        toplevel: Resource,
+        seat: Resource,
+        serial: u32,
+        edges: xdg_toplevel::ResizeEdge,
+    ) {
+        let _ = (handle, toplevel, seat, serial, edges);
+    }
+
+    /// Called when a client asks for the window menu at a surface-local position.
+    fn show_window_menu(
+        &mut self,
+        handle: &mut DisplayHandle,
+        toplevel: Resource,
+        seat: Resource,
+        serial: u32,
+        location: Point,
+    ) {
+        let _ = (handle, toplevel, seat, serial, location);
+    }
+
+    /// Called when a client asks for its toplevel to be maximized.
+    fn maximize_request(&mut self, handle: &mut DisplayHandle, toplevel: Resource) {
+        let _ = (handle, toplevel);
+    }
+
+    /// Called when a client asks for its toplevel to be unmaximized.
+    fn unmaximize_request(&mut self, handle: &mut DisplayHandle, toplevel: Resource) {
+        let _ = (handle, toplevel);
+    }
+
+    /// Called when a client asks for its toplevel to be fullscreen, on a
+    /// specific `wl_output` or one the compositor picks.
+    fn fullscreen_request(
+        &mut self,
+        handle: &mut DisplayHandle,
+        toplevel: Resource,
+        output: Option<Resource>,
+    ) {
+        let _ = (handle, toplevel, output);
+    }
+
+    /// Called when a client asks for its toplevel to leave fullscreen.
+    fn unfullscreen_request(&mut self, handle: &mut DisplayHandle, toplevel: Resource) {
+        let _ = (handle, toplevel);
+    }
+
+    /// Called when a client asks for its toplevel to be minimized.
+    fn minimize_request(&mut self, handle: &mut DisplayHandle, toplevel: Resource) {
+        let _ = (handle, toplevel);
+    }
+
+    /// Called when a client asks for an explicit grab for a popup not mapped yet.
+    ///
+    /// Compositors not granting the grab must dismiss the popup with
+    /// [`XdgShellState::popup_done`].
+    fn popup_grab(
+        &mut self,
+        handle: &mut DisplayHandle,
+        popup: Resource,
+        seat: Resource,
+        serial: u32,
+    ) {
+        let _ = (handle, popup, seat, serial);
+    }
+
+    /// Called when a client changed the positioner of a popup.
+    ///
+    /// The pending configure holds the unconstrained placement of the new
+    /// positioner, see [`PositionerState::constrain`]. The default
+    /// implementation sends it.
+    fn reposition_request(&mut self, handle: &mut DisplayHandle, popup: Resource, token: u32) {
+        let _ = token;
+        self.xdg_shell_state().send_popup_configure(handle, popup);
+    }
+}
+
+impl XdgShellState {
+    /// Creates the `xdg_wm_base` global.
+    pub fn new<D: XdgShellHandler>(display: &mut Display<D>) -> Self {
+        display.register(&xdg_wm_base::INTERFACE, WmBaseDispatch);
+        display.register(&xdg_positioner::INTERFACE, PositionerDispatch);
+        display.register(&xdg_surface::INTERFACE, XdgSurfaceDispatch);
+        display.register(&xdg_toplevel::INTERFACE, ToplevelDispatch);
+        display.register(&xdg_popup::INTERFACE, PopupDispatch);
+
+        display.create_global(&xdg_wm_base::INTERFACE, VERSION, |state, _, resource| {
+            state.xdg_shell_state().wm_bases.insert(resource, None);
+            Ok(())
+        });
+
+        XdgShellState {
+            wm_bases: HashMap::new(),
+            positioners: HashMap::new(),
+            surfaces: HashMap::new(),
+            by_surface: HashMap::new(),
+            toplevels: HashMap::new(),
+            popups: HashMap::new(),
+            capabilities: Vec::new(),
+            ping_timeout: DEFAULT_PING_TIMEOUT,
+        }
+    }
+
+    /// Returns an `xdg_surface`.
+    pub fn xdg_surface(&self, xdg_surface: Resource) -> Option<&XdgSurface> {
+        self.surfaces.get(&xdg_surface)
+    }
+
+    /// Returns the `xdg_surface` created for a `wl_surface`.
+    pub fn surface_xdg_surface(&self, surface: Resource) -> Option<&XdgSurface> {
+        self.surfaces.get(self.by_surface.get(&surface)?)
+    }
+
+    /// Returns a toplevel.
+    pub fn toplevel(&self, toplevel: Resource) -> Option<&Toplevel> {
+        self.toplevels.get(&toplevel)
+    }
+
+    /// Returns a toplevel, mutably, for instance to change its pending configure.
+    pub fn toplevel_mut(&mut self, toplevel: Resource) -> Option<&mut Toplevel> {
+        self.toplevels.get_mut(&toplevel)
+    }
+
+    /// Returns an iterator over all toplevels.
+    pub fn toplevels(&self) -> impl Iterator<Item = &Toplevel> {
+        self.toplevels.values()
+    }
+
+    /// Returns a popup.
+    pub fn popup(&self, popup: Resource) -> Option<&Popup> {
+        self.popups.get(&popup)
+    }
+
+    /// Returns a popup, mutably, for instance to change its pending configure.
+    pub fn popup_mut(&mut self, popup: Resource) -> Option<&mut Popup> {
+        self.popups.get_mut(&popup)
+    }
+
+    /// Returns an iterator over all popups.
+    pub fn popups(&self) -> impl Iterator<Item = &Popup> {
+        self.popups.values()
+    }
+
+    /// Sets the window management features advertised to toplevels.
+    ///
+    /// Toplevels configured afterwards receive the new set.
+    pub fn set_wm_capabilities(&mut self, capabilities: Vec<xdg_toplevel::WmCapabilities>) {
+        self.capabilities = capabilities;
+
+        for toplevel in self.toplevels.values_mut() {
+            toplevel.capabilities_sent = false;
+        }
+    }
+
+    /// Returns the time a client has to answer a ping.
+    pub fn ping_timeout(&self) -> Duration {
+        self.ping_timeout
+    }
+
+    /// Sets the time a client has to answer a ping.
+    pub fn set_ping_timeout(&mut self, timeout: Duration) {
+        self.ping_timeout = timeout;
+    }
+
+    /// Pings a client on each of its `xdg_wm_base` objects.
+    ///
+    /// Objects with a ping outstanding are not pinged again. Returns `false`
+    /// if the client has no `xdg_wm_base`.
+    pub fn ping(&mut self, handle: &mut DisplayHandle, client: ClientId) -> bool {
+        let mut found = false;
+
+        for (resource, ping) in &mut self.wm_bases {
+            if resource.client != client {
+                continue;
+            }
+
+            found = true;
+
+            if ping.is_none() {
+                let serial = handle.next_serial();
+                handle.send_event(*resource, xdg_wm_base::Event::Ping { serial });
+                *ping = Some((serial, Instant::now()));
+            }
+        }
+
+        found
+    }
+
+    /// Returns `true` if a ping to the client went unanswered for longer than
+    /// the timeout.
+    pub fn is_unresponsive(&self, client: ClientId) -> bool {
+        self.unresponsive_clients()
+            .any(|unresponsive| unresponsive == client)
+    }
+
+    /// Returns the clients that did not answer a ping within the timeout.
+    ///
+    /// Compositors check this periodically after pinging, for instance to
+    /// offer killing the client.
+    pub fn unresponsive_clients(&self) -> impl Iterator<Item = ClientId> + '_ {
+        self.wm_bases
+            .iter()
+            .filter(|(_, ping)| ping.is_some_and(|(_, sent)| sent.elapsed() > self.ping_timeout))
+            .map(|(resource, _)| resource.client)
+    }
+}
+
+struct WmBaseDispatch;
+
+impl<D: XdgShellHandler> Dispatch<D> for WmBaseDispatch {
+    fn request(
+        &mut self,
+        state: &mut D,
+        handle: &mut DisplayHandle,
+        resource: Resource,
+        message: Message,
+    ) -> Result<(), ProtocolError> {
+        let request = xdg_wm_base::Request::from_message(message)
+            .map_err(|err| ProtocolError::malformed(resource.id, err))?;
+
+        match request {
+            xdg_wm_base::Request::Destroy => {
+                let xdg = state.xdg_shell_state();
+
+                if xdg.surfaces.values().any(|s| s.wm_base() == resource) {
+                    return Err(ProtocolError::new(
+                        resource.id,
+                        xdg_wm_base::Error::DefunctSurfaces,
+                        "xdg_wm_base destroyed before its surfaces",
+                    ));
+                }
+            }
+            xdg_wm_base::Request::CreatePositioner { id } => {
+                state.xdg_shell_state().positioners.insert(
+                    Resource::new(resource.client, id),
+                    PositionerState::default(),
+                );
+            }
+            xdg_wm_base::Request::GetXdgSurface { id, surface } => {
+                let xdg_surface = Resource::new(resource.client, id);
+                let surface = Resource::new(resource.client, surface);
+
+                surface::create(state, resource, xdg_surface, surface)?;
+            }
+            xdg_wm_base::Request::Pong { serial } => {
+                let ping = state.xdg_shell_state().wm_bases.get_mut(&resource);
+
+                // Late or unexpected pongs are harmless
+                if let Some(ping) = ping
+                    && ping.is_some_and(|(expected, _)| expected == serial)
+                {
+                    *ping = None;
+                    state.pong(handle, resource.client);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn destroyed(&mut self, state: &mut D, _handle: &mut DisplayHandle, resource: Resource) {
+        state.xdg_shell_state().wm_bases.remove(&resource);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        protocol::wayland::{wl_compositor, wl_surface},
+        testing::{Fixture, TestClient, events_of, protocol_error},
+        wire::ObjectId,
+    };
+
+    pub(super) struct Setup {
+        pub(super) fixture: Fixture,
+        pub(super) client: TestClient,
+        pub(super) compositor: ObjectId,
+        pub(super) wm_base: ObjectId,
+    }
+
+    impl Setup {
+        pub(super) async fn new() -> Self {
+            let mut fixture = Fixture::new();
+            let mut client = fixture.connect().await;
+            let compositor = client.bind(&wl_compositor::INTERFACE, 6);
+            let wm_base = client.bind(&xdg_wm_base::INTERFACE, VERSION);
+
+            Setup {
+                fixture,
+                client,
+                compositor,
+                wm_base,
+            }
+        }
+
+        pub(super) fn resource(&self, id: ObjectId) -> Resource {
+            Resource::new(self.client.id, id)
+        }
+
+        /// Creates a `wl_surface` and its `xdg_surface`.
+        pub(super) fn xdg_surface(&mut self) -> (ObjectId, ObjectId) {
+            let surface = self.client.create(&wl_surface::INTERFACE, 6);
+            let xdg_surface = self.client.create(&xdg_surface::INTERFACE, VERSION);
+
+            self.client.queue(
+                self.compositor,
+                wl_compositor::Request::CreateSurface { id: surface },
+            );
+            self.client.queue(
+                self.wm_base,
+                xdg_wm_base::Request::GetXdgSurface {
+                    id: xdg_surface,
+                    surface,
+                },
+            );
+
+            (surface, xdg_surface)
+        }
+
+        /// Creates a toplevel, returning its `wl_surface`, `xdg_surface` and
+        /// `xdg_toplevel`.
+        pub(super) fn toplevel(&mut self) -> (ObjectId, ObjectId, ObjectId) {
+            let (surface, xdg_surface) = self.xdg_surface();
+            let toplevel = self.client.create(&xdg_toplevel::INTERFACE, VERSION);
+
+            self.client.queue(
+                xdg_surface,
+                xdg_surface::Request::GetToplevel { id: toplevel },
+            );
+
+            (surface, xdg_surface, toplevel)
+        }
+
+        /// Creates a positioner with a size and an anchor rectangle.
+        pub(super) fn positioner(&mut self) -> ObjectId {
+            let positioner = self.client.create(&xdg_positioner::INTERFACE, VERSION);
+
+            self.client.queue(
+                self.wm_base,
+                xdg_wm_base::Request::CreatePositioner { id: positioner },
+            );
+            self.client.queue(
+                positioner,
+                xdg_positioner::Request::SetSize {
+                    width: 10,
+                    height: 20,
+                },
+            );
+            self.client.queue(
+                positioner,
+                xdg_positioner::Request::SetAnchorRect {
+                    x: 0,
+                    y: 0,
+                    width: 1,
+                    height: 1,
+                },
+            );
+
+            positioner
+        }
+
+        pub(super) fn attach(&mut self, surface: ObjectId) {
+            let buffer = self.client.buffer();
+            self.client.queue(
+                surface,
+                wl_surface::Request::Attach {
+                    buffer: Some(buffer),
+                    x: 0,
+                    y: 0,
+                },
+            );
+        }
+
+        pub(super) fn commit(&mut self, surface: ObjectId) {
+            self.client.queue(surface, wl_surface::Request::Commit);
+        }
+
+        pub(super) fn ack(&mut self, xdg_surface: ObjectId, serial: u32) {
+            self.client
+                .queue(xdg_surface, xdg_surface::Request::AckConfigure { serial });
+        }
+
+        /// Returns the serials of the configures `xdg_surface` received.
+        pub(super) async fn configures(&mut self, xdg_surface: ObjectId) -> Vec<u32> {
+            let mut events = self.fixture.roundtrip(&mut self.client).await;
+            assert_eq!(protocol_error(&mut events), None);
+
+            events_of(&mut events, xdg_surface)
+                .into_iter()
+                .map(|xdg_surface::Event::Configure { serial }| serial)
+                .collect()
+        }
+
+        pub(super) async fn error(&mut self) -> Option<(ObjectId, u32)> {
+            let mut events = self.fixture.roundtrip(&mut self.client).await;
+            protocol_error(&mut events)
+        }
+    }
+
+    #[tokio::test]
+    async fn ping() {
+        let mut setup = Setup::new().await;
+        let client = setup.client.id;
+        setup.fixture.roundtrip(&mut setup.client).await;
+
+        let xdg = &mut setup.fixture.state.xdg_shell;
+        xdg.set_ping_timeout(Duration::from_millis(10));
+        assert!(xdg.ping(setup.fixture.display.handle_mut(), client));
+
+        let mut events = setup.fixture.roundtrip(&mut setup.client).await;
+        let pings: Vec<_> = events_of(&mut events, setup.wm_base);
+        let [xdg_wm_base::Event::Ping { serial }] = pings[..] else {
+            panic!("expected one ping, got {pings:?}");
+        };
+
+        assert!(!setup.fixture.state.xdg_shell.is_unresponsive(client));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(setup.fixture.state.xdg_shell.is_unresponsive(client));
+
+        // Only the serial of the ping answers it
+        let wm_base = setup.wm_base;
+        setup.client.queue(
+            wm_base,
+            xdg_wm_base::Request::Pong {
+                serial: serial.wrapping_add(1),
+            },
+        );
+        setup.fixture.roundtrip(&mut setup.client).await;
+        assert!(setup.fixture.state.xdg_shell.is_unresponsive(client));
+
+        setup
+            .client
+            .queue(wm_base, xdg_wm_base::Request::Pong { serial });
+        setup.fixture.roundtrip(&mut setup.client).await;
+        assert!(!setup.fixture.state.xdg_shell.is_unresponsive(client));
+    }
+
+    #[tokio::test]
+    async fn ping_without_wm_base() {
+        let mut fixture = Fixture::new();
+        let client = fixture.connect().await;
+
+        let handle = fixture.display.handle_mut();
+        assert!(!fixture.state.xdg_shell.ping(handle, client.id));
+    }
+
+    #[tokio::test]
+    async fn defunct_surfaces() {
+        let mut setup = Setup::new().await;
+        setup.xdg_surface();
+
+        let wm_base = setup.wm_base;
+        setup.client.queue(wm_base, xdg_wm_base::Request::Destroy);
+
+        assert_eq!(
+            setup.error().await,
+            Some((wm_base, xdg_wm_base::Error::DefunctSurfaces.into())),
+        );
+    }
+}
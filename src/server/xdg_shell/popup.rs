@@ -0,0 +1,415 @@
+use super::{
+    PositionerState, XdgShellHandler, XdgShellState,
+    surface::{Configure, XdgRole},
+};
+use crate::{
+    geometry::Rectangle,
+    protocol::{
+        MessageGroup,
+        xdg_shell::{xdg_popup, xdg_wm_base},
+    },
+    server::{
+        client::Resource,
+        display::{Dispatch, DisplayHandle},
+        error::ProtocolError,
+    },
+    wire::Message,
+};
+
+/// Role of surfaces with an `xdg_popup`.
+pub const POPUP_ROLE: &str = "xdg_popup";
+
+/// State of a popup sent in `xdg_popup.configure`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PopupConfigure {
+    /// Position relative to the parent's window geometry, and size.
+    pub geometry: Rectangle,
+    /// Token of the `xdg_popup.reposition` request answered by this configure.
+    pub reposition_token: Option<u32>,
+}
+
+/// An `xdg_popup`, such as a menu or tooltip.
+#[derive(Debug)]
+pub struct Popup {
+    resource: Resource,
+    xdg_surface: Resource,
+    surface: Resource,
+    /// Parent `xdg_surface` and its `wl_surface`.
+    parent: Option<(Resource, Resource)>,
+    positioner: PositionerState,
+    grab: Option<(Resource, u32)>,
+
+    pending: PopupConfigure,
+    current: PopupConfigure,
+}
+
+impl Popup {
+    /// Returns the `xdg_popup` object.
+    pub fn resource(&self) -> Resource {
+        self.resource
+    }
+
+    /// Returns the `xdg_surface`.
+    pub fn xdg_surface(&self) -> Resource {
+        self.xdg_surface
+    }
+
+    /// Returns the `wl_surface`.
+    pub fn surface(&self) -> Resource {
+        self.surface
+    }
+
+    /// Returns the `wl_surface` of the parent, unless set through another protocol.
+    pub fn parent_surface(&self) -> Option<Resource> {
+        self.parent.map(|(_, surface)| surface)
+    }
+
+    /// Returns the placement rules of the latest positioner.
+    pub fn positioner(&self) -> &PositionerState {
+        &self.positioner
+    }
+
+    /// Returns the `wl_seat` and serial of the requested grab, if any.
+    pub fn grab(&self) -> Option<(Resource, u32)> {
+        self.grab
+    }
+
+    /// Returns the state sent by the next configure.
+    pub fn pending(&self) -> &PopupConfigure {
+        &self.pending
+    }
+
+    /// Returns the state sent by the next configure, mutably.
+    ///
+    /// Changes are sent with [`XdgShellState::send_popup_configure`].
+    pub fn pending_mut(&mut self) -> &mut PopupConfigure {
+        &mut self.pending
+    }
+
+    /// Returns the state the client acknowledged and committed.
+    pub fn current(&self) -> &PopupConfigure {
+        &self.current
+    }
+
+    /// Applies the acknowledged configure on commit.
+    pub(super) fn apply(&mut self, acked: Option<Configure>) {
+        if let Some(Configure::Popup(configure)) = acked {
+            self.current = configure;
+        }
+    }
+}
+
+/// Handles `xdg_surface.get_popup`.
+pub(super) fn create<D: XdgShellHandler>(
+    state: &mut D,
+    handle: &mut DisplayHandle,
+    xdg_surface: Resource,
+    popup: Resource,
+    parent: Option<Resource>,
+    positioner: Resource,
+) -> Result<(), ProtocolError> {
+    let xdg = state.xdg_shell_state();
+    let data = xdg
+        .surfaces
+        .get(&xdg_surface)
+        .expect("live xdg_surface has a state");
+    let (surface, wm_base) = (data.surface(), data.wm_base());
+
+    let positioner = xdg.complete_positioner(wm_base, positioner)?;
+
+    let parent = match parent {
+        Some(parent) => {
+            let data = xdg
+                .surfaces
+                .get(&parent)
+                .ok_or(ProtocolError::invalid_object(parent.id.get()))?;
+
+            if data.role().is_none() {
+                return Err(ProtocolError::new(
+                    wm_base.id,
+                    xdg_wm_base::Error::InvalidPopupParent,
+                    format!("xdg_surface@{} has no role object", parent.id),
+                ));
+            }
+
+            Some((parent, data.surface()))
+        }
+        None => None,
+    };
+
+    let has_role = state
+        .compositor_state()
+        .surface_mut(surface)
+        .is_some_and(|surface| !surface.set_role(POPUP_ROLE));
+
+    if has_role {
+        return Err(ProtocolError::new(
+            wm_base.id,
+            xdg_wm_base::Error::Role,
+            format!("wl_surface@{} already has another role", surface.id),
+        ));
+    }
+
+    let xdg = state.xdg_shell_state();
+    let configure = PopupConfigure {
+        geometry: positioner.geometry(),
+        reposition_token: None,
+    };
+
+    xdg.popups.insert(
+        popup,
+        Popup {
+            resource: popup,
+            xdg_surface,
+            surface,
+            parent,
+            positioner,
+            grab: None,
+            pending: configure,
+            current: PopupConfigure::default(),
+        },
+    );
+
+    if let Some(data) = xdg.surfaces.get_mut(&xdg_surface) {
+        data.role = Some(XdgRole::Popup(popup));
+    }
+
+    state.new_popup(handle, popup);
+
+    Ok(())
+}
+
+impl XdgShellState {
+    /// Sends the pending state of a popup in a configure sequence, returning its serial.
+    pub fn send_popup_configure(
+        &mut self,
+        handle: &mut DisplayHandle,
+        popup: Resource,
+    ) -> Option<u32> {
+        let data = self.popups.get_mut(&popup)?;
+        let configure = data.pending;
+
+        if let Some(token) = data.pending.reposition_token.take() {
+            handle.send_event(popup, xdg_popup::Event::Repositioned { token });
+        }
+
+        let geometry = configure.geometry;
+        handle.send_event(
+            popup,
+            xdg_popup::Event::Configure {
+                x: geometry.loc.x,
+                y: geometry.loc.y,
+                width: geometry.size.width,
+                height: geometry.size.height,
+            },
+        );
+
+        let xdg_surface = data.xdg_surface;
+        self.send_surface_configure(handle, xdg_surface, Configure::Popup(configure))
+    }
+
+    /// Dismisses a popup, for instance when its grab ends.
+    pub fn popup_done(&self, handle: &mut DisplayHandle, popup: Resource) {
+        handle.send_event(popup, xdg_popup::Event::PopupDone);
+    }
+}
+
+pub(super) struct PopupDispatch;
+
+impl<D: XdgShellHandler> Dispatch<D> for PopupDispatch {
+    fn request(
+        &mut self,
+        state: &mut D,
+        handle: &mut DisplayHandle,
+        resource: Resource,
+        message: Message,
+    ) -> Result<(), ProtocolError> {
+        let request = xdg_popup::Request::from_message(message)
+            .map_err(|err| ProtocolError::malformed(resource.id, err))?;
+
+        let xdg = state.xdg_shell_state();
+        let popup = xdg
+            .popups
+            .get(&resource)
+            .expect("live xdg_popup has a state");
+        let xdg_surface = popup.xdg_surface;
+        let data = xdg
+            .surfaces
+            .get(&xdg_surface)
+            .expect("xdg_popup outlives its xdg_surface");
+        let (wm_base, committed) = (data.wm_base(), data.initial_configure_sent);
+
+        match request {
+            xdg_popup::Request::Destroy => {
+                let has_children = xdg.popups.values().any(|child| {
+                    child
+                        .parent
+                        .is_some_and(|(parent, _)| parent == xdg_surface)
+                });
+
+                if has_children {
+                    return Err(ProtocolError::new(
+                        wm_base.id,
+                        xdg_wm_base::Error::NotTheTopmostPopup,
+                        "xdg_popup destroyed before its child popups",
+                    ));
+                }
+            }
+            xdg_popup::Request::Grab { seat, serial } => {
+                if committed {
+                    return Err(ProtocolError::new(
+                        resource.id,
+                        xdg_popup::Error::InvalidGrab,
+                        "xdg_popup grabbed after being committed",
+                    ));
+                }
+
+                let seat = Resource::new(resource.client, seat);
+                if let Some(popup) = xdg.popups.get_mut(&resource) {
+                    popup.grab = Some((seat, serial));
+                }
+
+                state.popup_grab(handle, resource, seat, serial);
+            }
+            xdg_popup::Request::Reposition { positioner, token } => {
+                let positioner = Resource::new(resource.client, positioner);
+                let positioner = xdg.complete_positioner(wm_base, positioner)?;
+
+                if let Some(popup) = xdg.popups.get_mut(&resource) {
+                    popup.positioner = positioner;
+                    popup.pending.geometry = positioner.geometry();
+                    popup.pending.reposition_token = Some(token);
+                }
+
+                state.reposition_request(handle, resource, token);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn destroyed(&mut self, state: &mut D, handle: &mut DisplayHandle, resource: Resource) {
+        let xdg = state.xdg_shell_state();
+        let Some(popup) = xdg.popups.remove(&resource) else {
+            return;
+        };
+
+        if let Some(xdg_surface) = xdg.surfaces.get_mut(&popup.xdg_surface) {
+            xdg_surface.reset();
+        }
+
+        state.popup_destroyed(handle, resource);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        protocol::{
+            wayland::wl_seat,
+            xdg_shell::{xdg_positioner, xdg_surface, xdg_wm_base},
+        },
+        server::xdg_shell::tests::Setup,
+        testing::events_of,
+        wire::ObjectId,
+    };
+
+    /// Creates a popup of `parent`, returning its `wl_surface`, `xdg_surface`
+    /// and `xdg_popup`.
+    fn popup(
+        setup: &mut Setup,
+        parent: ObjectId,
+        positioner: ObjectId,
+    ) -> (ObjectId, ObjectId, ObjectId) {
+        let (surface, xdg_surface) = setup.xdg_surface();
+        let popup = setup.client.create(&xdg_popup::INTERFACE, 6);
+
+        setup.client.queue(
+            xdg_surface,
+            xdg_surface::Request::GetPopup {
+                id: popup,
+                parent: Some(parent),
+                positioner,
+            },
+        );
+
+        (surface, xdg_surface, popup)
+    }
+
+    #[tokio::test]
+    async fn initial_configure() {
+        let mut setup = Setup::new().await;
+        let (_, parent, _) = setup.toplevel();
+        let positioner = setup.positioner();
+        let (surface, xdg_surface, popup) = popup(&mut setup, parent, positioner);
+        setup.commit(surface);
+
+        let mut events = setup.fixture.roundtrip(&mut setup.client).await;
+        let configures: Vec<_> = events_of(&mut events, popup);
+        assert!(matches!(
+            configures[..],
+            [xdg_popup::Event::Configure {
+                width: 10,
+                height: 20,
+                ..
+            }],
+        ));
+
+        let surface_configures: Vec<xdg_surface::Event> = events_of(&mut events, xdg_surface);
+        assert_eq!(surface_configures.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn incomplete_positioner() {
+        let mut setup = Setup::new().await;
+        let (_, parent, _) = setup.toplevel();
+
+        let positioner = setup.client.create(&xdg_positioner::INTERFACE, 6);
+        let wm_base = setup.wm_base;
+        setup.client.queue(
+            wm_base,
+            xdg_wm_base::Request::CreatePositioner { id: positioner },
+        );
+        popup(&mut setup, parent, positioner);
+
+        assert_eq!(
+            setup.error().await,
+            Some((wm_base, xdg_wm_base::Error::InvalidPositioner.into())),
+        );
+    }
+
+    #[tokio::test]
+    async fn grab_after_commit() {
+        let mut setup = Setup::new().await;
+        let seat = setup.client.bind(&wl_seat::INTERFACE, 1);
+        let (_, parent, _) = setup.toplevel();
+        let positioner = setup.positioner();
+        let (surface, _, popup) = popup(&mut setup, parent, positioner);
+        setup.commit(surface);
+        setup
+            .client
+            .queue(popup, xdg_popup::Request::Grab { seat, serial: 0 });
+
+        assert_eq!(
+            setup.error().await,
+            Some((popup, xdg_popup::Error::InvalidGrab.into())),
+        );
+    }
+
+    #[tokio::test]
+    async fn not_the_topmost_popup() {
+        let mut setup = Setup::new().await;
+        let (_, parent, _) = setup.toplevel();
+        let positioner = setup.positioner();
+        let (_, first_xdg_surface, first) = popup(&mut setup, parent, positioner);
+        popup(&mut setup, first_xdg_surface, positioner);
+        setup.client.queue(first, xdg_popup::Request::Destroy);
+
+        let wm_base = setup.wm_base;
+        assert_eq!(
+            setup.error().await,
+            Some((wm_base, xdg_wm_base::Error::NotTheTopmostPopup.into())),
+        );
+    }
+}
@@ -0,0 +1,483 @@
+use super::{XdgShellHandler, XdgShellState};
+use crate::{
+    geometry::{Point, Rectangle, Size},
+    protocol::{
+        MessageGroup, WEnum,
+        xdg_shell::{
+            xdg_positioner::{self, Anchor, ConstraintAdjustment, Gravity},
+            xdg_wm_base,
+        },
+    },
+    server::{
+        client::Resource,
+        display::{Dispatch, DisplayHandle},
+        error::ProtocolError,
+    },
+    wire::Message,
+};
+
+/// Placement rules of a popup, as set on an `xdg_positioner`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PositionerState {
+    /// Size of the popup, empty until set.
+    pub size: Size,
+    /// Area of the parent's window geometry the popup is anchored to.
+    pub anchor_rect: Option<Rectangle>,
+    pub anchor: Anchor,
+    pub gravity: Gravity,
+    pub constraint_adjustment: ConstraintAdjustment,
+    pub offset: Point,
+    /// The popup should be repositioned when its parent moves or resizes.
+    pub reactive: bool,
+    /// Parent size the client expects in a future configure.
+    pub parent_size: Option<Size>,
+    /// Serial of the parent configure the client positioned the popup for.
+    pub parent_configure: Option<u32>,
+}
+
+impl Default for PositionerState {
+    fn default() -> Self {
+        PositionerState {
+            size: Size::default(),
+            anchor_rect: None,
+            anchor: Anchor::None,
+            gravity: Gravity::None,
+            constraint_adjustment: ConstraintAdjustment::NONE,
+            offset: Point::default(),
+            reactive: false,
+            parent_size: None,
+            parent_configure: None,
+        }
+    }
+}
+
+impl PositionerState {
+    /// Returns `true` once the size and anchor rectangle are set, as
+    /// required to create or reposition a popup.
+    pub fn is_complete(&self) -> bool {
+        !self.size.is_empty() && self.anchor_rect.is_some()
+    }
+
+    /// Returns the popup geometry relative to the parent's window geometry,
+    /// ignoring any constraint.
+    pub fn geometry(&self) -> Rectangle {
+        let rect = self.anchor_rect.unwrap_or_default();
+
+        let (left, right) = (rect.loc.x, rect.right());
+        let (top, bottom) = (rect.loc.y, rect.bottom());
+        let center_x = left.saturating_add(rect.size.width / 2);
+        let center_y = top.saturating_add(rect.size.height / 2);
+
+        let anchor = match self.anchor {
+            Anchor::None => Point::new(center_x, center_y),
+            Anchor::Top => Point::new(center_x, top),
+            Anchor::Bottom => Point::new(center_x, bottom),
+            Anchor::Left => Point::new(left, center_y),
+            Anchor::Right => Point::new(right, center_y),
+            Anchor::TopLeft => Point::new(left, top),
+            Anchor::BottomLeft => Point::new(left, bottom),
+            Anchor::TopRight => Point::new(right, top),
+            Anchor::BottomRight => Point::new(right, bottom),
+        };
+
+        // The gravity gives the direction the popup extends to from the anchor
+        let Size { width, height } = self.size;
+        let origin = match self.gravity {
+            Gravity::None => Point::new(-width / 2, -height / 2),
+            Gravity::Top => Point::new(-width / 2, -height),
+            Gravity::Bottom => Point::new(-width / 2, 0),
+            Gravity::Left => Point::new(-width, -height / 2),
+            Gravity::Right => Point::new(0, -height / 2),
+            Gravity::TopLeft => Point::new(-width, -height),
+            Gravity::BottomLeft => Point::new(-width, 0),
+            Gravity::TopRight => Point::new(0, -height),
+            Gravity::BottomRight => Point::new(0, 0),
+        };
+
+        let loc = anchor + origin + self.offset;
+        Rectangle::new(loc.x, loc.y, width, height)
+    }
+
+    /// Returns the popup geometry relative to the parent's window geometry,
+    /// adjusted to fit within `work_area`.
+    ///
+    /// `parent` is the window geometry of the parent, in the same coordinate
+    /// space as `work_area`. Adjustments are tried in the order of the
+    /// protocol: flip, slide, then resize, each axis on its own. Reactive
+    /// popups are placed again with this whenever their parent moves.
+    pub fn constrain(&self, parent: Rectangle, work_area: Rectangle) -> Rectangle {
+        let bounds = work_area.translate(Point::default() - parent.loc);
+        let adjustment = self.constraint_adjustment;
+
+        let mut positioner = *self;
+        let mut geometry = positioner.geometry();
+
+        // A flip is kept only if it resolves the constraint on that axis
+        for axis in [Axis::X, Axis::Y] {
+            let flip = match axis {
+                Axis::X => ConstraintAdjustment::FLIP_X,
+                Axis::Y => ConstraintAdjustment::FLIP_Y,
+            };
+
+            if adjustment.contains(flip) && !axis.fits(geometry, bounds) {
+                let flipped = positioner.flipped(axis);
+                let candidate = flipped.geometry();
+
+                if axis.fits(candidate, bounds) {
+                    positioner = flipped;
+                    geometry = candidate;
+                }
+            }
+        }
+
+        if adjustment.contains(ConstraintAdjustment::SLIDE_X) && !Axis::X.fits(geometry, bounds) {
+            // Aligned to the left edge if it overflows both
+            let overflow = geometry.right().saturating_sub(bounds.right()).max(0);
+            let x = geometry.loc.x.saturating_sub(overflow).max(bounds.loc.x);
+            geometry.loc.x = x;
+        }
+
+        if adjustment.contains(ConstraintAdjustment::SLIDE_Y) && !Axis::Y.fits(geometry, bounds) {
+            let overflow = geometry.bottom().saturating_sub(bounds.bottom()).max(0);
+            let y = geometry.loc.y.saturating_sub(overflow).max(bounds.loc.y);
+            geometry.loc.y = y;
+        }
+
+        if adjustment.contains(ConstraintAdjustment::RESIZE_X) && !Axis::X.fits(geometry, bounds) {
+            let left = geometry.loc.x.max(bounds.loc.x);
+            let right = geometry.right().min(bounds.right());
+
+            if right > left {
+                geometry = Rectangle::from_edges(left, geometry.loc.y, right, geometry.bottom());
+            }
+        }
+
+        if adjustment.contains(ConstraintAdjustment::RESIZE_Y) && !Axis::Y.fits(geometry, bounds) {
+            let top = geometry.loc.y.max(bounds.loc.y);
+            let bottom = geometry.bottom().min(bounds.bottom());
+
+            if bottom > top {
+                geometry = Rectangle::from_edges(geometry.loc.x, top, geometry.right(), bottom);
+            }
+        }
+
+        geometry
+    }
+
+    /// Returns the rules mirrored along an axis, as for a flip adjustment.
+    fn flipped(mut self, axis: Axis) -> Self {
+        use {Anchor as A, Gravity as G};
+
+        match axis {
+            Axis::X => {
+                self.anchor = match self.anchor {
+                    A::Left => A::Right,
+                    A::Right => A::Left,
+                    A::TopLeft => A::TopRight,
+                    A::TopRight => A::TopLeft,
+                    A::BottomLeft => A::BottomRight,
+                    A::BottomRight => A::BottomLeft,
+                    anchor => anchor,
+                };
+                self.gravity = match self.gravity {
+                    G::Left => G::Right,
+                    G::Right => G::Left,
+                    G::TopLeft => G::TopRight,
+                    G::TopRight => G::TopLeft,
+                    G::BottomLeft => G::BottomRight,
+                    G::BottomRight => G::BottomLeft,
+                    gravity => gravity,
+                };
+                self.offset.x = self.offset.x.saturating_neg();
+            }
+            Axis::Y => {
+                self.anchor = match self.anchor {
+                    A::Top => A::Bottom,
+                    A::Bottom => A::Top,
+                    A::TopLeft => A::BottomLeft,
+                    A::BottomLeft => A::TopLeft,
+                    A::TopRight => A::BottomRight,
+                    A::BottomRight => A::TopRight,
+                    anchor => anchor,
+                };
+                self.gravity = match self.gravity {
+                    G::Top => G::Bottom,
+                    G::Bottom => G::Top,
+                    G::TopLeft => G::BottomLeft,
+                    G::BottomLeft => G::TopLeft,
+                    G::TopRight => G::BottomRight,
+                    G::BottomRight => G::TopRight,
+                    gravity => gravity,
+                };
+                self.offset.y = self.offset.y.saturating_neg();
+            }
+        }
+
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Axis {
+    X,
+    Y,
+}
+
+impl Axis {
+    /// Returns `true` if `rect` lies within `bounds` along this axis.
+    fn fits(self, rect: Rectangle, bounds: Rectangle) -> bool {
+        match self {
+            Axis::X => rect.loc.x >= bounds.loc.x && rect.right() <= bounds.right(),
+            Axis::Y => rect.loc.y >= bounds.loc.y && rect.bottom() <= bounds.bottom(),
+        }
+    }
+}
+
+pub(super) struct PositionerDispatch;
+
+impl<D: XdgShellHandler> Dispatch<D> for PositionerDispatch {
+    fn request(
+        &mut self,
+        state: &mut D,
+        _handle: &mut DisplayHandle,
+        resource: Resource,
+        message: Message,
+    ) -> Result<(), ProtocolError> {
+        let request = xdg_positioner::Request::from_message(message)
+            .map_err(|err| ProtocolError::malformed(resource.id, err))?;
+
+        let invalid_input = |message: &str| {
+            ProtocolError::new(
+                resource.id,
+                xdg_positioner::Error::InvalidInput,
+                message.to_owned(),
+            )
+        };
+
+        let positioner = state
+            .xdg_shell_state()
+            .positioners
+            .get_mut(&resource)
+            .expect("live xdg_positioner has a state");
+
+        match request {
+            xdg_positioner::Request::Destroy => {}
+            xdg_positioner::Request::SetSize { width, height } => {
+                if width <= 0 || height <= 0 {
+                    return Err(invalid_input("size must be positive"));
+                }
+
+                positioner.size = Size::new(width, height);
+            }
+            xdg_positioner::Request::SetAnchorRect {
+                x,
+                y,
+                width,
+                height,
+            } => {
+                if width < 0 || height < 0 {
+                    return Err(invalid_input("anchor rect size must not be negative"));
+                }
+
+                positioner.anchor_rect = Some(Rectangle::new(x, y, width, height));
+            }
+            xdg_positioner::Request::SetAnchor { anchor } => {
+                let WEnum::Value(anchor) = anchor else {
+                    return Err(invalid_input("invalid anchor"));
+                };
+
+                positioner.anchor = anchor;
+            }
+            xdg_positioner::Request::SetGravity { gravity } => {
+                let WEnum::Value(gravity) = gravity else {
+                    return Err(invalid_input("invalid gravity"));
+                };
+
+                positioner.gravity = gravity;
+            }
+            xdg_positioner::Request::SetConstraintAdjustment {
+                constraint_adjustment,
+            } => positioner.constraint_adjustment = constraint_adjustment,
+            xdg_positioner::Request::SetOffset { x, y } => positioner.offset = Point::new(x, y),
+            xdg_positioner::Request::SetReactive => positioner.reactive = true,
+            xdg_positioner::Request::SetParentSize {
+                parent_width,
+                parent_height,
+            } => positioner.parent_size = Some(Size::new(parent_width, parent_height)),
+            xdg_positioner::Request::SetParentConfigure { serial } => {
+                positioner.parent_configure = Some(serial);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn destroyed(&mut self, state: &mut D, _handle: &mut DisplayHandle, resource: Resource) {
+        state.xdg_shell_state().positioners.remove(&resource);
+    }
+}
+
+impl XdgShellState {
+    /// Returns a complete positioner, or the error for using it to place a popup.
+    pub(super) fn complete_positioner(
+        &self,
+        wm_base: Resource,
+        positioner: Resource,
+    ) -> Result<PositionerState, ProtocolError> {
+        let state = self
+            .positioners
+            .get(&positioner)
+            .ok_or(ProtocolError::invalid_object(positioner.id.get()))?;
+
+        if !state.is_complete() {
+            return Err(ProtocolError::new(
+                wm_base.id,
+                xdg_wm_base::Error::InvalidPositioner,
+                format!("xdg_positioner@{} is incomplete", positioner.id),
+            ));
+        }
+
+        Ok(*state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A popup below and right of a button near the bottom right corner.
+    fn corner_menu(adjustment: ConstraintAdjustment) -> PositionerState {
+        PositionerState {
+            size: Size::new(80, 60),
+            anchor_rect: Some(Rectangle::new(300, 250, 50, 20)),
+            anchor: Anchor::BottomRight,
+            gravity: Gravity::BottomRight,
+            constraint_adjustment: adjustment,
+            ..PositionerState::default()
+        }
+    }
+
+    const PARENT: Rectangle = Rectangle::new(0, 0, 400, 400);
+
+    /// Flipping resolves the overflow on the right, but not at the bottom
+    /// where flipping overflows the top instead.
+    const WORK_AREA: Rectangle = Rectangle::new(0, 220, 400, 100);
+
+    #[test]
+    fn unconstrained() {
+        let positioner = corner_menu(ConstraintAdjustment::all());
+        let work_area = Rectangle::new(0, 0, 1000, 1000);
+
+        assert_eq!(positioner.geometry(), Rectangle::new(350, 270, 80, 60));
+        assert_eq!(
+            positioner.constrain(PARENT, work_area),
+            positioner.geometry()
+        );
+    }
+
+    #[test]
+    fn anchor_and_gravity() {
+        let mut positioner = corner_menu(ConstraintAdjustment::NONE);
+        positioner.anchor = Anchor::None;
+        positioner.gravity = Gravity::None;
+        assert_eq!(positioner.geometry(), Rectangle::new(285, 230, 80, 60));
+
+        positioner.anchor = Anchor::Top;
+        positioner.gravity = Gravity::TopLeft;
+        positioner.offset = Point::new(5, -5);
+        assert_eq!(positioner.geometry(), Rectangle::new(250, 185, 80, 60));
+    }
+
+    #[test]
+    fn every_adjustment_combination() {
+        for bits in 0..64 {
+            let adjustment = ConstraintAdjustment::from_bits_truncate(bits);
+            let geometry = corner_menu(adjustment).constrain(PARENT, WORK_AREA);
+
+            let (x, width) = if adjustment.contains(ConstraintAdjustment::FLIP_X) {
+                (220, 80)
+            } else if adjustment.contains(ConstraintAdjustment::SLIDE_X) {
+                (320, 80)
+            } else if adjustment.contains(ConstraintAdjustment::RESIZE_X) {
+                (350, 50)
+            } else {
+                (350, 80)
+            };
+
+            let (y, height) = if adjustment.contains(ConstraintAdjustment::SLIDE_Y) {
+                (260, 60)
+            } else if adjustment.contains(ConstraintAdjustment::RESIZE_Y) {
+                (270, 50)
+            } else {
+                (270, 60)
+            };
+
+            assert_eq!(
+                geometry,
+                Rectangle::new(x, y, width, height),
+                "adjustment {adjustment:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn flip_y() {
+        let positioner = corner_menu(ConstraintAdjustment::FLIP_Y);
+        let work_area = Rectangle::new(0, 0, 1000, 300);
+
+        assert_eq!(
+            positioner.constrain(PARENT, work_area),
+            Rectangle::new(350, 190, 80, 60)
+        );
+    }
+
+    #[test]
+    fn flip_inverts_offset() {
+        let mut positioner = corner_menu(ConstraintAdjustment::FLIP_X);
+        positioner.offset = Point::new(4, 2);
+
+        assert_eq!(
+            positioner.constrain(PARENT, WORK_AREA),
+            Rectangle::new(216, 272, 80, 60)
+        );
+    }
+
+    #[test]
+    fn slide_aligns_left_when_too_large() {
+        let mut positioner = corner_menu(ConstraintAdjustment::SLIDE_X);
+        positioner.size = Size::new(500, 60);
+
+        let geometry = positioner.constrain(PARENT, WORK_AREA);
+        assert_eq!(geometry.loc.x, 0);
+        assert_eq!(geometry.size.width, 500);
+    }
+
+    #[test]
+    fn slide_off_left_edge() {
+        let mut positioner = corner_menu(ConstraintAdjustment::SLIDE_X);
+        positioner.anchor_rect = Some(Rectangle::new(-100, 250, 50, 20));
+        positioner.gravity = Gravity::BottomLeft;
+
+        let geometry = positioner.constrain(PARENT, WORK_AREA);
+        assert_eq!(geometry.loc.x, 0);
+    }
+
+    #[test]
+    fn resize_keeps_unresolvable_size() {
+        let mut positioner = corner_menu(ConstraintAdjustment::RESIZE_X);
+        positioner.anchor_rect = Some(Rectangle::new(450, 250, 50, 20));
+
+        let geometry = positioner.constrain(PARENT, WORK_AREA);
+        assert_eq!(geometry, positioner.geometry());
+    }
+
+    #[test]
+    fn relative_to_parent() {
+        let positioner = corner_menu(ConstraintAdjustment::SLIDE_X);
+        let parent = Rectangle::new(-100, 1000, 400, 400);
+
+        // The right edge of the work area is at 400 relative to the parent
+        let geometry = positioner.constrain(parent, Rectangle::new(0, 0, 300, 2000));
+        assert_eq!(geometry, Rectangle::new(320, 270, 80, 60));
+    }
+}
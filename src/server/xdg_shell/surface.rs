@@ -0,0 +1,584 @@
+use super::{PopupConfigure, ToplevelConfigure, XdgShellHandler, XdgShellState, popup, toplevel};
+use crate::{
+    geometry::Rectangle,
+    protocol::{
+        MessageGroup,
+        xdg_shell::{xdg_surface, xdg_toplevel, xdg_wm_base},
+    },
+    server::{
+        client::Resource,
+        compositor::{BufferAssignment, RoleHooks},
+        display::{Dispatch, DisplayHandle},
+        error::ProtocolError,
+    },
+    wire::Message,
+};
+
+/// Role object of an `xdg_surface`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum XdgRole {
+    Toplevel(Resource),
+    Popup(Resource),
+}
+
+/// State sent in a configure sequence.
+#[derive(Debug, Clone)]
+pub(super) enum Configure {
+    Toplevel(ToplevelConfigure),
+    Popup(PopupConfigure),
+}
+
+/// An `xdg_surface`, the base of toplevels and popups.
+#[derive(Debug)]
+pub struct XdgSurface {
+    resource: Resource,
+    wm_base: Resource,
+    surface: Resource,
+    pub(super) role: Option<XdgRole>,
+
+    pending_geometry: Option<Rectangle>,
+    geometry: Option<Rectangle>,
+
+    /// Configures sent and not acknowledged yet, oldest first.
+    pub(super) configures: Vec<(u32, Configure)>,
+    /// Configure acknowledged and applied by the next commit.
+    acked: Option<Configure>,
+    /// A configure was acknowledged since the role object was created or
+    /// the surface unmapped.
+    configured: bool,
+    pub(super) initial_configure_sent: bool,
+    mapped: bool,
+}
+
+impl XdgSurface {
+    /// Returns the `xdg_surface` object.
+    pub fn resource(&self) -> Resource {
+        self.resource
+    }
+
+    /// Returns the `xdg_wm_base` the surface was created from.
+    pub fn wm_base(&self) -> Resource {
+        self.wm_base
+    }
+
+    /// Returns the `wl_surface`.
+    pub fn surface(&self) -> Resource {
+        self.surface
+    }
+
+    /// Returns the role object, once created.
+    pub fn role(&self) -> Option<XdgRole> {
+        self.role
+    }
+
+    /// Returns the committed window geometry, `None` for the surface bounds.
+    pub fn window_geometry(&self) -> Option<Rectangle> {
+        self.geometry
+    }
+
+    /// Returns `true` once the client acknowledged a configure.
+    pub fn is_configured(&self) -> bool {
+        self.configured
+    }
+
+    /// Returns `true` while the surface has a configured buffer committed.
+    pub fn is_mapped(&self) -> bool {
+        self.mapped
+    }
+
+    /// Resets the surface to its state before a role object was created.
+    pub(super) fn reset(&mut self) {
+        self.role = None;
+        self.unmap();
+    }
+
+    /// Forgets the configure sequence, the client has to start over with an
+    /// initial commit.
+    fn unmap(&mut self) {
+        self.configures.clear();
+        self.acked = None;
+        self.configured = false;
+        self.initial_configure_sent = false;
+        self.mapped = false;
+    }
+}
+
+/// Handles `xdg_wm_base.get_xdg_surface`.
+pub(super) fn create<D: XdgShellHandler>(
+    state: &mut D,
+    wm_base: Resource,
+    xdg_surface: Resource,
+    surface: Resource,
+) -> Result<(), ProtocolError> {
+    let Some(wl_surface) = state.compositor_state().surface(surface) else {
+        return Err(ProtocolError::invalid_object(surface.id.get()));
+    };
+
+    let has_role = wl_surface
+        .role()
+        .is_some_and(|role| role != toplevel::TOPLEVEL_ROLE && role != popup::POPUP_ROLE);
+    let has_buffer = wl_surface.has_buffer()
+        || matches!(wl_surface.pending().buffer, Some(BufferAssignment::New(_)));
+
+    let xdg = state.xdg_shell_state();
+
+    if has_role || xdg.by_surface.contains_key(&surface) {
+        return Err(ProtocolError::new(
+            wm_base.id,
+            xdg_wm_base::Error::Role,
+            format!("wl_surface@{} already has a role", surface.id),
+        ));
+    }
+
+    if has_buffer {
+        return Err(ProtocolError::new(
+            xdg_surface.id,
+            xdg_surface::Error::UnconfiguredBuffer,
+            format!("wl_surface@{} already has a buffer", surface.id),
+        ));
+    }
+
+    xdg.by_surface.insert(surface, xdg_surface);
+    xdg.surfaces.insert(
+        xdg_surface,
+        XdgSurface {
+            resource: xdg_surface,
+            wm_base,
+            surface,
+            role: None,
+            pending_geometry: None,
+            geometry: None,
+            configures: Vec::new(),
+            acked: None,
+            configured: false,
+            initial_configure_sent: false,
+            mapped: false,
+        },
+    );
+
+    state.compositor_state().set_surface_hooks(
+        surface,
+        RoleHooks {
+            pre_commit: pre_commit::<D>,
+            post_commit: post_commit::<D>,
+        },
+    );
+
+    Ok(())
+}
+
+/// Checks that the surface may be committed, following the configure sequence.
+fn pre_commit<D: XdgShellHandler>(
+    state: &mut D,
+    _handle: &mut DisplayHandle,
+    surface: Resource,
+) -> Result<(), ProtocolError> {
+    let attaches = state
+        .compositor_state()
+        .surface(surface)
+        .is_some_and(|surface| matches!(surface.pending().buffer, Some(BufferAssignment::New(_))));
+
+    let xdg = state.xdg_shell_state();
+    let Some(xdg_surface) = xdg
+        .by_surface
+        .get(&surface)
+        .and_then(|xdg_surface| xdg.surfaces.get(xdg_surface))
+    else {
+        return Ok(());
+    };
+
+    let Some(role) = xdg_surface.role else {
+        return Err(ProtocolError::new(
+            xdg_surface.resource.id,
+            xdg_surface::Error::NotConstructed,
+            "xdg_surface committed without a role object",
+        ));
+    };
+
+    if attaches && !xdg_surface.configured {
+        return Err(ProtocolError::new(
+            xdg_surface.resource.id,
+            xdg_surface::Error::UnconfiguredBuffer,
+            "buffer attached before the first configure was acknowledged",
+        ));
+    }
+
+    if let XdgRole::Toplevel(resource) = role
+        && let Some(toplevel) = xdg.toplevels.get(&resource)
+    {
+        let (min, max) = toplevel.pending_size_limits();
+        let inverted = |min: i32, max: i32| max > 0 && min > max;
+
+        if inverted(min.width, max.width) || inverted(min.height, max.height) {
+            return Err(ProtocolError::new(
+                resource.id,
+                xdg_toplevel::Error::InvalidSize,
+                "minimum size is larger than maximum size",
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Applies the acknowledged configure and sends the initial one.
+fn post_commit<D: XdgShellHandler>(state: &mut D, handle: &mut DisplayHandle, surface: Resource) {
+    let has_buffer = state
+        .compositor_state()
+        .surface(surface)
+        .is_some_and(|surface| surface.has_buffer());
+
+    let xdg = state.xdg_shell_state();
+    let Some(&resource) = xdg.by_surface.get(&surface) else {
+        return;
+    };
+    let Some(xdg_surface) = xdg.surfaces.get_mut(&resource) else {
+        return;
+    };
+    let Some(role) = xdg_surface.role else {
+        return;
+    };
+
+    if let Some(geometry) = xdg_surface.pending_geometry {
+        xdg_surface.geometry = Some(geometry);
+    }
+
+    let acked = xdg_surface.acked.take();
+    let initial = !xdg_surface.initial_configure_sent;
+
+    // Removing the buffer unmaps, the next commit is an initial one again
+    match has_buffer {
+        true => xdg_surface.mapped = true,
+        false if xdg_surface.mapped => xdg_surface.unmap(),
+        false => {}
+    }
+
+    match role {
+        XdgRole::Toplevel(resource) => {
+            if let Some(toplevel) = xdg.toplevels.get_mut(&resource) {
+                toplevel.apply(acked);
+            }
+
+            if initial {
+                xdg.send_toplevel_configure(handle, resource);
+            }
+        }
+        XdgRole::Popup(resource) => {
+            if let Some(popup) = xdg.popups.get_mut(&resource) {
+                popup.apply(acked);
+            }
+
+            if initial {
+                xdg.send_popup_configure(handle, resource);
+            }
+        }
+    }
+}
+
+impl XdgShellState {
+    /// Sends `xdg_surface.configure` ending a configure sequence, returning its serial.
+    pub(super) fn send_surface_configure(
+        &mut self,
+        handle: &mut DisplayHandle,
+        xdg_surface: Resource,
+        configure: Configure,
+    ) -> Option<u32> {
+        let data = self.surfaces.get_mut(&xdg_surface)?;
+        let serial = handle.next_serial();
+
+        handle.send_event(xdg_surface, xdg_surface::Event::Configure { serial });
+        data.configures.push((serial, configure));
+        data.initial_configure_sent = true;
+
+        Some(serial)
+    }
+}
+
+pub(super) struct XdgSurfaceDispatch;
+
+impl<D: XdgShellHandler> Dispatch<D> for XdgSurfaceDispatch {
+    fn request(
+        &mut self,
+        state: &mut D,
+        handle: &mut DisplayHandle,
+        resource: Resource,
+        message: Message,
+    ) -> Result<(), ProtocolError> {
+        let request = xdg_surface::Request::from_message(message)
+            .map_err(|err| ProtocolError::malformed(resource.id, err))?;
+
+        let xdg_surface = state
+            .xdg_shell_state()
+            .surfaces
+            .get_mut(&resource)
+            .expect("live xdg_surface has a state");
+
+        let already_constructed = || {
+            ProtocolError::new(
+                resource.id,
+                xdg_surface::Error::AlreadyConstructed,
+                "xdg_surface already has a role object",
+            )
+        };
+
+        match request {
+            xdg_surface::Request::Destroy => {
+                if xdg_surface.role.is_some() {
+                    return Err(ProtocolError::new(
+                        resource.id,
+                        xdg_surface::Error::DefunctRoleObject,
+                        "xdg_surface destroyed before its role object",
+                    ));
+                }
+            }
+            xdg_surface::Request::GetToplevel { id } => {
+                if xdg_surface.role.is_some() {
+                    return Err(already_constructed());
+                }
+
+                let toplevel = Resource::new(resource.client, id);
+                toplevel::create(state, handle, resource, toplevel)?;
+            }
+            xdg_surface::Request::GetPopup {
+                id,
+                parent,
+                positioner,
+            } => {
+                if xdg_surface.role.is_some() {
+                    return Err(already_constructed());
+                }
+
+                let popup = Resource::new(resource.client, id);
+                let parent = parent.map(|parent| Resource::new(resource.client, parent));
+                let positioner = Resource::new(resource.client, positioner);
+                popup::create(state, handle, resource, popup, parent, positioner)?;
+            }
+            xdg_surface::Request::SetWindowGeometry {
+                x,
+                y,
+                width,
+                height,
+            } => {
+                if width <= 0 || height <= 0 {
+                    return Err(ProtocolError::new(
+                        resource.id,
+                        xdg_surface::Error::InvalidSize,
+                        format!("invalid window geometry size ({width}x{height})"),
+                    ));
+                }
+
+                xdg_surface.pending_geometry = Some(Rectangle::new(x, y, width, height));
+            }
+            xdg_surface::Request::AckConfigure { serial } => {
+                if xdg_surface.role.is_none() {
+                    return Err(ProtocolError::new(
+                        resource.id,
+                        xdg_surface::Error::NotConstructed,
+                        "xdg_surface has no role object",
+                    ));
+                }
+
+                let Some(index) = xdg_surface
+                    .configures
+                    .iter()
+                    .position(|(sent, _)| *sent == serial)
+                else {
+                    return Err(ProtocolError::new(
+                        xdg_surface.wm_base.id,
+                        xdg_wm_base::Error::InvalidSurfaceState,
+                        format!("invalid configure serial {serial}"),
+                    ));
+                };
+
+                // Acknowledging a configure skips the older ones
+                let (_, configure) = xdg_surface.configures.remove(index);
+                xdg_surface.configures.drain(..index);
+
+                xdg_surface.acked = Some(configure);
+                xdg_surface.configured = true;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn destroyed(&mut self, state: &mut D, _handle: &mut DisplayHandle, resource: Resource) {
+        let xdg = state.xdg_shell_state();
+
+        if let Some(xdg_surface) = xdg.surfaces.remove(&resource) {
+            xdg.by_surface.remove(&xdg_surface.surface);
+            state
+                .compositor_state()
+                .remove_surface_hooks(xdg_surface.surface);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        protocol::wayland::{wl_compositor, wl_surface},
+        server::xdg_shell::tests::Setup,
+    };
+
+    #[tokio::test]
+    async fn already_constructed() {
+        let mut setup = Setup::new().await;
+        let (_, xdg_surface, _) = setup.toplevel();
+
+        let toplevel = setup.client.create(&xdg_toplevel::INTERFACE, 6);
+        setup.client.queue(
+            xdg_surface,
+            xdg_surface::Request::GetToplevel { id: toplevel },
+        );
+
+        assert_eq!(
+            setup.error().await,
+            Some((xdg_surface, xdg_surface::Error::AlreadyConstructed.into())),
+        );
+    }
+
+    #[tokio::test]
+    async fn buffer_before_configure() {
+        let mut setup = Setup::new().await;
+        let (surface, xdg_surface, _) = setup.toplevel();
+        setup.attach(surface);
+        setup.commit(surface);
+
+        assert_eq!(
+            setup.error().await,
+            Some((xdg_surface, xdg_surface::Error::UnconfiguredBuffer.into())),
+        );
+    }
+
+    #[tokio::test]
+    async fn buffer_before_xdg_surface() {
+        let mut setup = Setup::new().await;
+        let surface = setup.client.create(&wl_surface::INTERFACE, 6);
+        let compositor = setup.compositor;
+        setup.client.queue(
+            compositor,
+            wl_compositor::Request::CreateSurface { id: surface },
+        );
+        setup.attach(surface);
+
+        let xdg_surface = setup.client.create(&xdg_surface::INTERFACE, 6);
+        let wm_base = setup.wm_base;
+        setup.client.queue(
+            wm_base,
+            xdg_wm_base::Request::GetXdgSurface {
+                id: xdg_surface,
+                surface,
+            },
+        );
+
+        assert_eq!(
+            setup.error().await,
+            Some((xdg_surface, xdg_surface::Error::UnconfiguredBuffer.into())),
+        );
+    }
+
+    #[tokio::test]
+    async fn defunct_role_object() {
+        let mut setup = Setup::new().await;
+        let (_, xdg_surface, _) = setup.toplevel();
+        setup
+            .client
+            .queue(xdg_surface, xdg_surface::Request::Destroy);
+
+        assert_eq!(
+            setup.error().await,
+            Some((xdg_surface, xdg_surface::Error::DefunctRoleObject.into())),
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_serial() {
+        let mut setup = Setup::new().await;
+        let (surface, xdg_surface, _) = setup.toplevel();
+        setup.commit(surface);
+
+        let [serial] = setup.configures(xdg_surface).await[..] else {
+            panic!("expected the initial configure");
+        };
+        setup.ack(xdg_surface, serial.wrapping_add(1));
+
+        let wm_base = setup.wm_base;
+        assert_eq!(
+            setup.error().await,
+            Some((wm_base, xdg_wm_base::Error::InvalidSurfaceState.into())),
+        );
+    }
+
+    #[tokio::test]
+    async fn ack_skips_older_configures() {
+        let mut setup = Setup::new().await;
+        let (surface, xdg_surface, toplevel) = setup.toplevel();
+        setup.commit(surface);
+
+        let mut serials = setup.configures(xdg_surface).await;
+
+        let toplevel = setup.resource(toplevel);
+        let handle = setup.fixture.display.handle_mut();
+        let xdg = &mut setup.fixture.state.xdg_shell;
+        for _ in 0..2 {
+            xdg.send_toplevel_configure(handle, toplevel).unwrap();
+        }
+
+        serials.extend(setup.configures(xdg_surface).await);
+        assert_eq!(serials.len(), 3);
+
+        setup.ack(xdg_surface, serials[1]);
+        assert_eq!(setup.error().await, None);
+
+        let xdg_surface_state = setup
+            .fixture
+            .state
+            .xdg_shell
+            .xdg_surface(setup.resource(xdg_surface))
+            .unwrap();
+        assert!(xdg_surface_state.is_configured());
+        assert_eq!(
+            xdg_surface_state
+                .configures
+                .iter()
+                .map(|(serial, _)| *serial)
+                .collect::<Vec<_>>(),
+            [serials[2]],
+        );
+
+        // The first configure was skipped, and may no longer be acknowledged
+        setup.ack(xdg_surface, serials[0]);
+
+        let wm_base = setup.wm_base;
+        assert_eq!(
+            setup.error().await,
+            Some((wm_base, xdg_wm_base::Error::InvalidSurfaceState.into())),
+        );
+    }
+
+    #[tokio::test]
+    async fn map_after_ack() {
+        let mut setup = Setup::new().await;
+        let (surface, xdg_surface, _) = setup.toplevel();
+        setup.commit(surface);
+
+        let [serial] = setup.configures(xdg_surface).await[..] else {
+            panic!("expected the initial configure");
+        };
+        setup.ack(xdg_surface, serial);
+        setup.attach(surface);
+        setup.commit(surface);
+
+        // Mapping does not start a new configure sequence
+        assert!(setup.configures(xdg_surface).await.is_empty());
+
+        let xdg = &setup.fixture.state.xdg_shell;
+        assert!(
+            xdg.xdg_surface(setup.resource(xdg_surface))
+                .unwrap()
+                .is_mapped()
+        );
+    }
+}
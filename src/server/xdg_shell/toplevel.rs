@@ -0,0 +1,518 @@
+use super::{
+    XdgShellHandler, XdgShellState,
+    surface::{Configure, XdgRole},
+};
+use crate::{
+    geometry::{Point, Size},
+    protocol::{
+        MessageGroup, WEnum,
+        xdg_shell::{
+            xdg_toplevel::{self, State},
+            xdg_wm_base,
+        },
+    },
+    server::{
+        client::Resource,
+        display::{Dispatch, DisplayHandle},
+        error::ProtocolError,
+    },
+    wire::Message,
+};
+
+/// Role of surfaces with an `xdg_toplevel`.
+pub const TOPLEVEL_ROLE: &str = "xdg_toplevel";
+
+/// State of a toplevel sent in `xdg_toplevel.configure`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ToplevelConfigure {
+    /// Size of the window geometry, zero to let the client decide.
+    pub size: Size,
+    pub states: Vec<State>,
+    /// Size the client should not exceed, such as the usable output area.
+    pub bounds: Option<Size>,
+}
+
+impl ToplevelConfigure {
+    /// Returns `true` if `state` is set.
+    pub fn contains(&self, state: State) -> bool {
+        self.states.contains(&state)
+    }
+
+    /// Sets or unsets `state`.
+    pub fn set(&mut self, state: State, enabled: bool) {
+        self.states.retain(|other| *other != state);
+
+        if enabled {
+            self.states.push(state);
+        }
+    }
+}
+
+/// An `xdg_toplevel`, a desktop window.
+#[derive(Debug)]
+pub struct Toplevel {
+    resource: Resource,
+    xdg_surface: Resource,
+    surface: Resource,
+    parent: Option<Resource>,
+    title: Option<String>,
+    app_id: Option<String>,
+
+    pending_min_size: Size,
+    pending_max_size: Size,
+    min_size: Size,
+    max_size: Size,
+
+    pending: ToplevelConfigure,
+    current: ToplevelConfigure,
+    pub(super) capabilities_sent: bool,
+}
+
+impl Toplevel {
+    /// Returns the `xdg_toplevel` object.
+    pub fn resource(&self) -> Resource {
+        self.resource
+    }
+
+    /// Returns the `xdg_surface`.
+    pub fn xdg_surface(&self) -> Resource {
+        self.xdg_surface
+    }
+
+    /// Returns the `wl_surface`.
+    pub fn surface(&self) -> Resource {
+        self.surface
+    }
+
+    /// Returns the parent `xdg_toplevel`, such as the main window of a dialog.
+    pub fn parent(&self) -> Option<Resource> {
+        self.parent
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    pub fn app_id(&self) -> Option<&str> {
+        self.app_id.as_deref()
+    }
+
+    /// Returns the committed minimum size, zero for no limit.
+    pub fn min_size(&self) -> Size {
+        self.min_size
+    }
+
+    /// Returns the committed maximum size, zero for no limit.
+    pub fn max_size(&self) -> Size {
+        self.max_size
+    }
+
+    /// Returns the state sent by the next configure.
+    pub fn pending(&self) -> &ToplevelConfigure {
+        &self.pending
+    }
+
+    /// Returns the state sent by the next configure, mutably.
+    ///
+    /// Changes are sent with [`XdgShellState::send_toplevel_configure`].
+    pub fn pending_mut(&mut self) -> &mut ToplevelConfigure {
+        &mut self.pending
+    }
+
+    /// Returns the state the client acknowledged and committed.
+    pub fn current(&self) -> &ToplevelConfigure {
+        &self.current
+    }
+
+    pub(super) fn pending_size_limits(&self) -> (Size, Size) {
+        (self.pending_min_size, self.pending_max_size)
+    }
+
+    /// Applies the double-buffered state on commit.
+    pub(super) fn apply(&mut self, acked: Option<Configure>) {
+        self.min_size = self.pending_min_size;
+        self.max_size = self.pending_max_size;
+
+        if let Some(Configure::Toplevel(configure)) = acked {
+            self.current = configure;
+        }
+    }
+}
+
+/// Handles `xdg_surface.get_toplevel`.
+pub(super) fn create<D: XdgShellHandler>(
+    state: &mut D,
+    handle: &mut DisplayHandle,
+    xdg_surface: Resource,
+    toplevel: Resource,
+) -> Result<(), ProtocolError> {
+    let xdg = state.xdg_shell_state();
+    let data = xdg
+        .surfaces
+        .get(&xdg_surface)
+        .expect("live xdg_surface has a state");
+    let (surface, wm_base) = (data.surface(), data.wm_base());
+
+    let has_role = state
+        .compositor_state()
+        .surface_mut(surface)
+        .is_some_and(|surface| !surface.set_role(TOPLEVEL_ROLE));
+
+    if has_role {
+        return Err(ProtocolError::new(
+            wm_base.id,
+            xdg_wm_base::Error::Role,
+            format!("wl_surface@{} already has another role", surface.id),
+        ));
+    }
+
+    let xdg = state.xdg_shell_state();
+    xdg.toplevels.insert(
+        toplevel,
+        Toplevel {
+            resource: toplevel,
+            xdg_surface,
+            surface,
+            parent: None,
+            title: None,
+            app_id: None,
+            pending_min_size: Size::default(),
+            pending_max_size: Size::default(),
+            min_size: Size::default(),
+            max_size: Size::default(),
+            pending: ToplevelConfigure::default(),
+            current: ToplevelConfigure::default(),
+            capabilities_sent: false,
+        },
+    );
+
+    if let Some(data) = xdg.surfaces.get_mut(&xdg_surface) {
+        data.role = Some(XdgRole::Toplevel(toplevel));
+    }
+
+    state.new_toplevel(handle, toplevel);
+
+    Ok(())
+}
+
+/// Returns the version an `xdg_toplevel.state` value was introduced in.
+fn state_since(state: State) -> u32 {
+    match state {
+        State::TiledLeft | State::TiledRight | State::TiledTop | State::TiledBottom => 2,
+        State::Suspended => 6,
+        _ => 1,
+    }
+}
+
+impl XdgShellState {
+    /// Sends the pending state of a toplevel in a configure sequence, returning its serial.
+    ///
+    /// States the client's version does not know are left out.
+    pub fn send_toplevel_configure(
+        &mut self,
+        handle: &mut DisplayHandle,
+        toplevel: Resource,
+    ) -> Option<u32> {
+        let data = self.toplevels.get_mut(&toplevel)?;
+        let version = handle.version(toplevel);
+        let configure = data.pending.clone();
+
+        if !data.capabilities_sent && version >= 5 {
+            let capabilities = self
+                .capabilities
+                .iter()
+                .flat_map(|capability| u32::from(*capability).to_ne_bytes())
+                .collect();
+
+            handle.send_event(
+                toplevel,
+                xdg_toplevel::Event::WmCapabilities { capabilities },
+            );
+            data.capabilities_sent = true;
+        }
+
+        if let Some(bounds) = configure.bounds {
+            handle.send_event(
+                toplevel,
+                xdg_toplevel::Event::ConfigureBounds {
+                    width: bounds.width,
+                    height: bounds.height,
+                },
+            );
+        }
+
+        let states = configure
+            .states
+            .iter()
+            .filter(|state| state_since(**state) <= version)
+            .flat_map(|state| u32::from(*state).to_ne_bytes())
+            .collect();
+
+        handle.send_event(
+            toplevel,
+            xdg_toplevel::Event::Configure {
+                width: configure.size.width,
+                height: configure.size.height,
+                states,
+            },
+        );
+
+        let xdg_surface = data.xdg_surface;
+        self.send_surface_configure(handle, xdg_surface, Configure::Toplevel(configure))
+    }
+
+    /// Asks the client to close a toplevel.
+    pub fn send_close(&self, handle: &mut DisplayHandle, toplevel: Resource) {
+        handle.send_event(toplevel, xdg_toplevel::Event::Close);
+    }
+}
+
+pub(super) struct ToplevelDispatch;
+
+impl<D: XdgShellHandler> Dispatch<D> for ToplevelDispatch {
+    fn request(
+        &mut self,
+        state: &mut D,
+        handle: &mut DisplayHandle,
+        resource: Resource,
+        message: Message,
+    ) -> Result<(), ProtocolError> {
+        let request = xdg_toplevel::Request::from_message(message)
+            .map_err(|err| ProtocolError::malformed(resource.id, err))?;
+
+        let xdg = state.xdg_shell_state();
+        let invalid_size = |width: i32, height: i32| {
+            ProtocolError::new(
+                resource.id,
+                xdg_toplevel::Error::InvalidSize,
+                format!("size must not be negative ({width}x{height})"),
+            )
+        };
+
+        match request {
+            xdg_toplevel::Request::Destroy => {}
+            xdg_toplevel::Request::SetParent { parent } => {
+                let parent = parent.map(|parent| Resource::new(resource.client, parent));
+
+                if let Some(parent) = parent {
+                    if !xdg.toplevels.contains_key(&parent) {
+                        return Err(ProtocolError::invalid_object(parent.id.get()));
+                    }
+
+                    // The parent chain must not loop back to this toplevel
+                    let mut ancestor = Some(parent);
+                    while let Some(next) = ancestor {
+                        if next == resource {
+                            return Err(ProtocolError::new(
+                                resource.id,
+                                xdg_toplevel::Error::InvalidParent,
+                                format!("xdg_toplevel@{} is a descendant", parent.id),
+                            ));
+                        }
+
+                        ancestor = xdg.toplevels.get(&next).and_then(Toplevel::parent);
+                    }
+                }
+
+                toplevel_mut(xdg, resource).parent = parent;
+                state.parent_changed(handle, resource);
+            }
+            xdg_toplevel::Request::SetTitle { title } => {
+                toplevel_mut(xdg, resource).title = Some(title);
+                state.title_changed(handle, resource);
+            }
+            xdg_toplevel::Request::SetAppId { app_id } => {
+                toplevel_mut(xdg, resource).app_id = Some(app_id);
+                state.app_id_changed(handle, resource);
+            }
+            xdg_toplevel::Request::ShowWindowMenu { seat, serial, x, y } => {
+                let seat = Resource::new(resource.client, seat);
+                state.show_window_menu(handle, resource, seat, serial, Point::new(x, y));
+            }
+            xdg_toplevel::Request::Move { seat, serial } => {
+                let seat = Resource::new(resource.client, seat);
+                state.move_request(handle, resource, seat, serial);
+            }
+            xdg_toplevel::Request::Resize {
+                seat,
+                serial,
+                edges,
+            } => {
+                let WEnum::Value(edges) = edges else {
+                    return Err(ProtocolError::new(
+                        resource.id,
+                        xdg_toplevel::Error::InvalidResizeEdge,
+                        "invalid resize edge",
+                    ));
+                };
+
+                let seat = Resource::new(resource.client, seat);
+                state.resize_request(handle, resource, seat, serial, edges);
+            }
+            xdg_toplevel::Request::SetMaxSize { width, height } => {
+                if width < 0 || height < 0 {
+                    return Err(invalid_size(width, height));
+                }
+
+                toplevel_mut(xdg, resource).pending_max_size = Size::new(width, height);
+            }
+            xdg_toplevel::Request::SetMinSize { width, height } => {
+                if width < 0 || height < 0 {
+                    return Err(invalid_size(width, height));
+                }
+
+                toplevel_mut(xdg, resource).pending_min_size = Size::new(width, height);
+            }
+            xdg_toplevel::Request::SetMaximized => state.maximize_request(handle, resource),
+            xdg_toplevel::Request::UnsetMaximized => state.unmaximize_request(handle, resource),
+            xdg_toplevel::Request::SetFullscreen { output } => {
+                let output = output.map(|output| Resource::new(resource.client, output));
+                state.fullscreen_request(handle, resource, output);
+            }
+            xdg_toplevel::Request::UnsetFullscreen => state.unfullscreen_request(handle, resource),
+            xdg_toplevel::Request::SetMinimized => state.minimize_request(handle, resource),
+        }
+
+        Ok(())
+    }
+
+    fn destroyed(&mut self, state: &mut D, handle: &mut DisplayHandle, resource: Resource) {
+        let xdg = state.xdg_shell_state();
+        let Some(toplevel) = xdg.toplevels.remove(&resource) else {
+            return;
+        };
+
+        if let Some(xdg_surface) = xdg.surfaces.get_mut(&toplevel.xdg_surface) {
+            xdg_surface.reset();
+        }
+
+        // Children are moved to the parent of the destroyed toplevel
+        for child in xdg.toplevels.values_mut() {
+            if child.parent == Some(resource) {
+                child.parent = toplevel.parent;
+            }
+        }
+
+        state.toplevel_destroyed(handle, resource);
+    }
+}
+
+fn toplevel_mut(xdg: &mut XdgShellState, toplevel: Resource) -> &mut Toplevel {
+    xdg.toplevels
+        .get_mut(&toplevel)
+        .expect("live xdg_toplevel has a state")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        server::xdg_shell::tests::Setup,
+        testing::{events_of, protocol_error},
+    };
+
+    #[tokio::test]
+    async fn configure_applied_on_commit() {
+        let mut setup = Setup::new().await;
+        let (surface, xdg_surface, toplevel) = setup.toplevel();
+        setup.commit(surface);
+        let _ = setup.configures(xdg_surface).await;
+
+        let resource = setup.resource(toplevel);
+        let xdg = &mut setup.fixture.state.xdg_shell;
+        let pending = xdg.toplevel_mut(resource).unwrap().pending_mut();
+        pending.size = Size::new(100, 200);
+        pending.set(State::Activated, true);
+
+        let serial = xdg
+            .send_toplevel_configure(setup.fixture.display.handle_mut(), resource)
+            .unwrap();
+
+        let mut events = setup.fixture.roundtrip(&mut setup.client).await;
+        let configures: Vec<_> = events_of(&mut events, toplevel);
+        assert!(matches!(
+            configures[..],
+            [xdg_toplevel::Event::Configure {
+                width: 100,
+                height: 200,
+                ..
+            }],
+        ));
+
+        setup.ack(xdg_surface, serial);
+        assert_eq!(setup.error().await, None);
+
+        // Acknowledged, but not committed yet
+        let xdg = &setup.fixture.state.xdg_shell;
+        assert_eq!(
+            xdg.toplevel(resource).unwrap().current().size,
+            Size::default()
+        );
+
+        setup.commit(surface);
+        assert_eq!(setup.error().await, None);
+
+        let current = setup
+            .fixture
+            .state
+            .xdg_shell
+            .toplevel(resource)
+            .unwrap()
+            .current();
+        assert_eq!(current.size, Size::new(100, 200));
+        assert!(current.contains(State::Activated));
+    }
+
+    #[tokio::test]
+    async fn inverted_size_limits() {
+        let mut setup = Setup::new().await;
+        let (surface, _, toplevel) = setup.toplevel();
+
+        setup.client.queue(
+            toplevel,
+            xdg_toplevel::Request::SetMinSize {
+                width: 200,
+                height: 0,
+            },
+        );
+        setup.client.queue(
+            toplevel,
+            xdg_toplevel::Request::SetMaxSize {
+                width: 100,
+                height: 0,
+            },
+        );
+        setup.commit(surface);
+
+        let mut events = setup.fixture.roundtrip(&mut setup.client).await;
+        assert_eq!(
+            protocol_error(&mut events),
+            Some((toplevel, xdg_toplevel::Error::InvalidSize.into())),
+        );
+    }
+
+    #[tokio::test]
+    async fn parent_loop() {
+        let mut setup = Setup::new().await;
+        let (_, _, first) = setup.toplevel();
+        let (_, _, second) = setup.toplevel();
+
+        setup.client.queue(
+            second,
+            xdg_toplevel::Request::SetParent {
+                parent: Some(first),
+            },
+        );
+        setup.client.queue(
+            first,
+            xdg_toplevel::Request::SetParent {
+                parent: Some(second),
+            },
+        );
+
+        assert_eq!(
+            setup.error().await,
+            Some((first, xdg_toplevel::Error::InvalidParent.into())),
+        );
+    }
+}
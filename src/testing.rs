@@ -0,0 +1,304 @@
+use crate::{
+    client::connection::Connection,
+    protocol::{
+        MessageGroup, WEnum,
+        wayland::{
+            wl_buffer, wl_callback, wl_display, wl_registry, wl_seat::Capability, wl_shm,
+            wl_shm_pool,
+        },
+    },
+    server::{
+        client::{ClientId, Resource},
+        compositor::{CompositorHandler, CompositorState},
+        data_device::{DataDeviceHandler, DataDeviceState},
+        display::{Display, DisplayHandle},
+        output::{OutputHandler, OutputState},
+        seat::{SeatHandler, SeatId, SeatState},
+        shm::{ShmHandler, ShmState},
+        socket::{WaylandSocket, socket_pair},
+        xdg_shell::{XdgShellHandler, XdgShellState},
+    },
+    wire::{Interface, Message, ObjectId},
+};
+use rustix::fs::{self, MemfdFlags};
+use std::{
+    os::fd::OwnedFd,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Display state with every global, for in-process tests.
+pub(crate) struct State {
+    pub compositor: CompositorState<State>,
+    pub xdg_shell: XdgShellState,
+    pub seat: SeatState,
+    pub data_device: DataDeviceState,
+    pub output: OutputState,
+    pub shm: ShmState,
+    /// Surfaces whose commit was applied, in order.
+    pub commits: Vec<Resource>,
+    /// Reads of compositor-owned selections.
+    pub selection_reads: Vec<(SeatId, String, OwnedFd)>,
+}
+
+impl CompositorHandler for State {
+    fn compositor_state(&mut self) -> &mut CompositorState<Self> {
+        &mut self.compositor
+    }
+
+    fn commit(&mut self, _handle: &mut DisplayHandle, surface: Resource) {
+        self.commits.push(surface);
+    }
+}
+
+impl XdgShellHandler for State {
+    fn xdg_shell_state(&mut self) -> &mut XdgShellState {
+        &mut self.xdg_shell
+    }
+}
+
+impl SeatHandler for State {
+    fn seat_state(&mut self) -> &mut SeatState {
+        &mut self.seat
+    }
+}
+
+impl DataDeviceHandler for State {
+    fn data_device_state(&mut self) -> &mut DataDeviceState {
+        &mut self.data_device
+    }
+
+    fn send_selection(
+        &mut self,
+        _handle: &mut DisplayHandle,
+        seat: SeatId,
+        mime_type: String,
+        fd: OwnedFd,
+    ) {
+        self.selection_reads.push((seat, mime_type, fd));
+    }
+}
+
+impl OutputHandler for State {
+    fn output_state(&mut self) -> &mut OutputState {
+        &mut self.output
+    }
+}
+
+impl ShmHandler for State {
+    fn shm_state(&mut self) -> &mut ShmState {
+        &mut self.shm
+    }
+}
+
+/// A display and its state, served to clients over socket pairs.
+pub(crate) struct Fixture {
+    pub display: Display<State>,
+    pub state: State,
+    /// Seat with a pointer and a keyboard.
+    pub seat: SeatId,
+}
+
+/// A client connected to a [`Fixture`].
+pub(crate) struct TestClient {
+    pub id: ClientId,
+    pub conn: Connection,
+    registry: ObjectId,
+    /// Name, interface and version of each advertised global.
+    globals: Vec<(u32, String, u32)>,
+}
+
+impl Fixture {
+    pub fn new() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+        let name = format!(
+            "alow-test-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        );
+        let socket = WaylandSocket::with_name_in_dir(&std::env::temp_dir(), name.into()).unwrap();
+        let mut display = Display::new(socket);
+
+        let mut seat = SeatState::new(&mut display);
+        let seat_id = seat.add_seat(&mut display, "seat0");
+
+        let handle = display.handle_mut();
+        seat.seat_mut(seat_id)
+            .unwrap()
+            .set_capabilities(handle, Capability::POINTER | Capability::KEYBOARD);
+
+        let state = State {
+            compositor: CompositorState::new(&mut display),
+            xdg_shell: XdgShellState::new(&mut display),
+            seat,
+            data_device: DataDeviceState::new(&mut display),
+            output: OutputState::new(&mut display),
+            shm: ShmState::new(&mut display, []),
+            commits: Vec::new(),
+            selection_reads: Vec::new(),
+        };
+
+        Fixture {
+            display,
+            state,
+            seat: seat_id,
+        }
+    }
+
+    /// Connects a client and fetches the globals.
+    pub async fn connect(&mut self) -> TestClient {
+        let (server, client) = socket_pair().unwrap();
+        let id = self.display.handle_mut().insert_client(server);
+        let mut conn = Connection::from_owned_fd(client.into_fd()).unwrap();
+
+        let registry = conn.create_object(&wl_registry::INTERFACE, 1).unwrap();
+        conn.queue(
+            ObjectId::DISPLAY,
+            wl_display::Request::GetRegistry { registry },
+        )
+        .unwrap();
+
+        let mut client = TestClient {
+            id,
+            conn,
+            registry,
+            globals: Vec::new(),
+        };
+
+        for event in self.roundtrip(&mut client).await {
+            if let Ok(wl_registry::Event::Global {
+                name,
+                interface,
+                version,
+            }) = wl_registry::Event::from_message(event)
+            {
+                client.globals.push((name, interface, version));
+            }
+        }
+
+        client
+    }
+
+    /// Dispatches until the server processed all requests of `client`, and
+    /// returns the events it received meanwhile.
+    ///
+    /// Stops early when the client is disconnected.
+    pub async fn roundtrip(&mut self, client: &mut TestClient) -> Vec<Message> {
+        let callback = client.create(&wl_callback::INTERFACE, 1);
+        client
+            .conn
+            .queue(ObjectId::DISPLAY, wl_display::Request::Sync { callback })
+            .unwrap();
+
+        loop {
+            tokio::select! {
+                result = client.conn.flush() => match result {
+                    Ok(()) => break,
+                    Err(_) => return Vec::new(),
+                },
+                _ = self.display.dispatch(&mut self.state) => {}
+            }
+        }
+
+        let mut events = Vec::new();
+
+        loop {
+            tokio::select! {
+                event = client.conn.recv() => match event {
+                    Ok(event) if event.object == callback => break,
+                    Ok(event) => events.push(event),
+                    Err(_) => break,
+                },
+                _ = self.display.dispatch(&mut self.state) => {}
+            }
+        }
+
+        events
+    }
+}
+
+impl TestClient {
+    /// Allocates the id of a new object.
+    pub fn create(&mut self, interface: &'static Interface, version: u32) -> ObjectId {
+        self.conn.create_object(interface, version).unwrap()
+    }
+
+    /// Queues a request.
+    pub fn queue<R: MessageGroup>(&mut self, object: ObjectId, request: R) {
+        self.conn.queue(object, request).unwrap();
+    }
+
+    /// Creates a 1x1 shm buffer.
+    pub fn buffer(&mut self) -> ObjectId {
+        let fd = fs::memfd_create("alow-test", MemfdFlags::CLOEXEC).unwrap();
+        fs::ftruncate(&fd, 4).unwrap();
+
+        let shm = self.bind(&wl_shm::INTERFACE, 1);
+        let pool = self.create(&wl_shm_pool::INTERFACE, 1);
+        let buffer = self.create(&wl_buffer::INTERFACE, 1);
+
+        self.queue(
+            shm,
+            wl_shm::Request::CreatePool {
+                id: pool,
+                fd,
+                size: 4,
+            },
+        );
+        self.queue(
+            pool,
+            wl_shm_pool::Request::CreateBuffer {
+                id: buffer,
+                offset: 0,
+                width: 1,
+                height: 1,
+                stride: 4,
+                format: WEnum::Value(wl_shm::Format::Argb8888),
+            },
+        );
+        self.queue(pool, wl_shm_pool::Request::Destroy);
+
+        buffer
+    }
+
+    /// Binds the global implementing `interface`.
+    pub fn bind(&mut self, interface: &'static Interface, version: u32) -> ObjectId {
+        let name = self
+            .globals
+            .iter()
+            .find(|(_, name, _)| name == interface.name)
+            .map(|(name, _, _)| *name)
+            .unwrap_or_else(|| panic!("no {} global", interface.name));
+
+        let id = self.create(interface, version);
+        let bind = wl_registry::Request::Bind {
+            name,
+            interface: interface.name.into(),
+            version,
+            id,
+        };
+        self.queue(self.registry, bind);
+
+        id
+    }
+}
+
+/// Takes the protocol error out of `events`, as its object and code.
+pub(crate) fn protocol_error(events: &mut Vec<Message>) -> Option<(ObjectId, u32)> {
+    events_of(events, ObjectId::DISPLAY)
+        .into_iter()
+        .find_map(|event| match event {
+            wl_display::Event::Error {
+                object_id, code, ..
+            } => Some((object_id, code)),
+            _ => None,
+        })
+}
+
+/// Takes the events sent by `object` out of `events`, decoded.
+pub(crate) fn events_of<E: MessageGroup>(events: &mut Vec<Message>, object: ObjectId) -> Vec<E> {
+    events
+        .extract_if(.., |event| event.object == object)
+        .map(|event| E::from_message(event).unwrap())
+        .collect()
+}
@@ -0,0 +1,150 @@
+use std::{fmt, num::NonZeroU32, os::fd::OwnedFd};
+
+/// Identifier of a protocol object.
+///
+/// Object ids are never zero; a null object is represented as `None`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ObjectId(NonZeroU32);
+
+impl ObjectId {
+    /// The `wl_display` singleton, which always has id 1.
+    pub const DISPLAY: ObjectId = ObjectId(NonZeroU32::MIN);
+
+    /// Creates an object id, returns `None` for the null id.
+    pub const fn new(id: u32) -> Option<Self> {
+        match NonZeroU32::new(id) {
+            Some(id) => Some(ObjectId(id)),
+            None => None,
+        }
+    }
+
+    /// Returns the raw protocol value.
+    pub const fn get(self) -> u32 {
+        self.0.get()
+    }
+}
+
+impl fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Signed 24.8 fixed-point number.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fixed(i32);
+
+impl Fixed {
+    /// Creates a fixed-point number from its raw wire representation.
+    pub const fn from_raw(raw: i32) -> Self {
+        Fixed(raw)
+    }
+
+    /// Returns the raw wire representation.
+    pub const fn to_raw(self) -> i32 {
+        self.0
+    }
+
+    /// Converts an integer, saturating at the representable range.
+    pub const fn from_int(value: i32) -> Self {
+        Fixed(value.saturating_mul(256))
+    }
+
+    /// Truncates to an integer, rounding towards zero.
+    pub const fn to_int(self) -> i32 {
+        self.0 / 256
+    }
+
+    /// Converts a floating point number, rounding to the nearest step.
+    pub fn from_f64(value: f64) -> Self {
+        Fixed((value * 256.0).round() as i32)
+    }
+
+    /// Converts to a floating point number.
+    pub fn to_f64(self) -> f64 {
+        f64::from(self.0) / 256.0
+    }
+}
+
+impl From<i32> for Fixed {
+    fn from(value: i32) -> Self {
+        Fixed::from_int(value)
+    }
+}
+
+impl From<f64> for Fixed {
+    fn from(value: f64) -> Self {
+        Fixed::from_f64(value)
+    }
+}
+
+impl fmt::Display for Fixed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.to_f64().fmt(f)
+    }
+}
+
+/// Type of a message argument, as declared by the protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ArgumentKind {
+    Int,
+    Uint,
+    Fixed,
+    String,
+    Object,
+    NewId,
+    Array,
+    Fd,
+}
+
+/// A decoded message argument.
+#[derive(Debug)]
+pub enum Argument {
+    Int(i32),
+    Uint(u32),
+    Fixed(Fixed),
+    /// Nullable UTF-8 string.
+    String(Option<String>),
+    /// Nullable object reference.
+    Object(Option<ObjectId>),
+    NewId(ObjectId),
+    Array(Vec<u8>),
+    /// File descriptor, transferred out of band.
+    Fd(OwnedFd),
+}
+
+impl Argument {
+    /// Returns the kind of this argument.
+    pub fn kind(&self) -> ArgumentKind {
+        match self {
+            Argument::Int(_) => ArgumentKind::Int,
+            Argument::Uint(_) => ArgumentKind::Uint,
+            Argument::Fixed(_) => ArgumentKind::Fixed,
+            Argument::String(_) => ArgumentKind::String,
+            Argument::Object(_) => ArgumentKind::Object,
+            Argument::NewId(_) => ArgumentKind::NewId,
+            Argument::Array(_) => ArgumentKind::Array,
+            Argument::Fd(_) => ArgumentKind::Fd,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn null_object_id() {
+        assert_eq!(ObjectId::new(0), None);
+        assert_eq!(ObjectId::new(1), Some(ObjectId::DISPLAY));
+    }
+
+    #[test]
+    fn fixed_conversions() {
+        assert_eq!(Fixed::from_int(3).to_raw(), 768);
+        assert_eq!(Fixed::from_f64(-1.5).to_raw(), -384);
+        assert_eq!(Fixed::from_raw(-384).to_int(), -1);
+        assert_eq!(Fixed::from_raw(1).to_f64(), 1.0 / 256.0);
+        assert_eq!(Fixed::from_int(i32::MAX).to_raw(), i32::MAX);
+    }
+}
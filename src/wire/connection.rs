@@ -0,0 +1,325 @@
+use super::{ArgumentKind, HEADER_SIZE, Header, Message, WireError};
+use rustix::{
+    cmsg_space,
+    net::{
+        RecvAncillaryBuffer, RecvAncillaryMessage, RecvFlags, ReturnFlags, SendAncillaryBuffer,
+        SendAncillaryMessage, SendFlags, recvmsg, sendmsg,
+    },
+};
+use std::{
+    collections::VecDeque,
+    io::{self, IoSlice, IoSliceMut},
+    mem::MaybeUninit,
+    os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd},
+    sync::Arc,
+};
+use tokio::{io::Interest, net::UnixStream};
+
+/// Maximum number of file descriptors attached to a single `sendmsg`/`recvmsg`.
+const MAX_FDS_PER_CALL: usize = 28;
+
+/// Maximum number of received file descriptors waiting to be consumed.
+pub const MAX_QUEUED_FDS: usize = 1024;
+
+/// Size of a single read from the socket.
+const READ_CHUNK: usize = 4096;
+
+/// A framed message whose arguments have not been decoded yet.
+#[derive(Debug)]
+pub struct RawMessage {
+    pub header: Header,
+    pub body: Vec<u8>,
+}
+
+impl RawMessage {
+    /// Decodes the arguments according to the given signature.
+    pub fn decode(
+        &self,
+        signature: &[ArgumentKind],
+        fds: &mut VecDeque<OwnedFd>,
+    ) -> Result<Message, WireError> {
+        Message::decode(&self.header, &self.body, signature, fds)
+    }
+}
+
+/// Wayland connection with file descriptor passing.
+///
+/// Wraps a connected stream, such as the one returned by
+/// [`WaylandSocket::accept`](crate::server::socket::WaylandSocket::accept),
+/// and transfers `fd` arguments as `SCM_RIGHTS` ancillary data.
+#[derive(Debug)]
+pub struct Connection {
+    reader: ReadHalf,
+    writer: WriteHalf,
+}
+
+impl Connection {
+    /// Creates a connection over a connected stream.
+    pub fn new(stream: UnixStream) -> Self {
+        let stream = Arc::new(stream);
+
+        Connection {
+            reader: ReadHalf {
+                stream: stream.clone(),
+                data: Vec::new(),
+                fds: VecDeque::new(),
+            },
+            writer: WriteHalf {
+                stream,
+                data: Vec::new(),
+                fds: VecDeque::new(),
+            },
+        }
+    }
+
+    /// Splits the connection so that reading and writing can happen independently.
+    pub fn into_split(self) -> (ReadHalf, WriteHalf) {
+        (self.reader, self.writer)
+    }
+
+    /// Reads the next framed message.
+    pub async fn read_raw(&mut self) -> Result<RawMessage, WireError> {
+        self.reader.read_raw().await
+    }
+
+    /// Reads and decodes the next message.
+    ///
+    /// `signature` looks up the argument kinds from the header.
+    pub async fn read_message<'a, F>(&mut self, signature: F) -> Result<Message, WireError>
+    where
+        F: FnOnce(&Header) -> Option<&'a [ArgumentKind]>,
+    {
+        self.reader.read_message(signature).await
+    }
+
+    /// Returns the received file descriptors not yet consumed by a message.
+    pub fn fds_mut(&mut self) -> &mut VecDeque<OwnedFd> {
+        self.reader.fds_mut()
+    }
+
+    /// Encodes a message into the outgoing buffer.
+    pub fn queue(&mut self, message: Message) -> Result<(), WireError> {
+        self.writer.queue(message)
+    }
+
+    /// Writes out all buffered messages.
+    pub async fn flush(&mut self) -> Result<(), WireError> {
+        self.writer.flush().await
+    }
+
+    /// Queues a message and flushes it.
+    pub async fn send(&mut self, message: Message) -> Result<(), WireError> {
+        self.writer.queue(message)?;
+        self.writer.flush().await
+    }
+}
+
+impl AsRawFd for Connection {
+    fn as_raw_fd(&self) -> RawFd {
+        self.reader.stream.as_raw_fd()
+    }
+}
+
+impl AsFd for Connection {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.reader.stream.as_fd()
+    }
+}
+
+/// Receiving half of a [`Connection`].
+#[derive(Debug)]
+pub struct ReadHalf {
+    stream: Arc<UnixStream>,
+
+    /// Received bytes not yet framed.
+    data: Vec<u8>,
+    /// Received file descriptors, in order of arrival.
+    fds: VecDeque<OwnedFd>,
+}
+
+impl ReadHalf {
+    /// Reads the next framed message.
+    ///
+    /// This is cancel safe: partially received messages stay buffered.
+    pub async fn read_raw(&mut self) -> Result<RawMessage, WireError> {
+        loop {
+            if let Some(message) = self.take_buffered()? {
+                return Ok(message);
+            }
+
+            if self.recv().await? == 0 {
+                return Err(WireError::Closed);
+            }
+        }
+    }
+
+    /// Reads and decodes the next message.
+    pub async fn read_message<'a, F>(&mut self, signature: F) -> Result<Message, WireError>
+    where
+        F: FnOnce(&Header) -> Option<&'a [ArgumentKind]>,
+    {
+        let raw = self.read_raw().await?;
+
+        let signature = signature(&raw.header).ok_or(WireError::UnknownMessage {
+            object: raw.header.object,
+            opcode: raw.header.opcode,
+        })?;
+
+        raw.decode(signature, &mut self.fds)
+    }
+
+    /// Returns the received file descriptors not yet consumed by a message.
+    pub fn fds_mut(&mut self) -> &mut VecDeque<OwnedFd> {
+        &mut self.fds
+    }
+
+    /// Splits a complete message off the front of the buffer.
+    fn take_buffered(&mut self) -> Result<Option<RawMessage>, WireError> {
+        let Some(header) = self.data.first_chunk::<HEADER_SIZE>() else {
+            return Ok(None);
+        };
+
+        let header = Header::parse(*header)?;
+        let size = header.size as usize;
+
+        if self.data.len() < size {
+            return Ok(None);
+        }
+
+        let body = self.data[HEADER_SIZE..size].to_vec();
+        self.data.drain(..size);
+
+        Ok(Some(RawMessage { header, body }))
+    }
+
+    /// Receives one chunk of data and file descriptors, returns the number of bytes.
+    async fn recv(&mut self) -> Result<usize, WireError> {
+        let mut buf = [0; READ_CHUNK];
+        let mut space = [MaybeUninit::uninit(); cmsg_space!(ScmRights(MAX_FDS_PER_CALL))];
+        let mut control = RecvAncillaryBuffer::new(&mut space);
+
+        let msg = loop {
+            self.stream.readable().await.map_err(WireError::Io)?;
+
+            let result = self.stream.try_io(Interest::READABLE, || {
+                recvmsg(
+                    &*self.stream,
+                    &mut [IoSliceMut::new(&mut buf)],
+                    &mut control,
+                    RecvFlags::CMSG_CLOEXEC | RecvFlags::DONTWAIT,
+                )
+                .map_err(io::Error::from)
+            });
+
+            match result {
+                Ok(msg) => break msg,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(WireError::Io(err)),
+            }
+        };
+
+        for message in control.drain() {
+            if let RecvAncillaryMessage::ScmRights(fds) = message {
+                self.fds.extend(fds);
+            }
+        }
+
+        // Descriptors were dropped by the kernel, the stream is out of sync
+        if msg.flags.contains(ReturnFlags::CTRUNC) || self.fds.len() > MAX_QUEUED_FDS {
+            return Err(WireError::TooManyFds);
+        }
+
+        self.data.extend_from_slice(&buf[..msg.bytes]);
+
+        Ok(msg.bytes)
+    }
+}
+
+/// Sending half of a [`Connection`].
+#[derive(Debug)]
+pub struct WriteHalf {
+    stream: Arc<UnixStream>,
+
+    /// Encoded bytes not yet written.
+    data: Vec<u8>,
+    /// File descriptors not yet sent.
+    fds: VecDeque<OwnedFd>,
+}
+
+impl WriteHalf {
+    /// Encodes a message into the outgoing buffer.
+    pub fn queue(&mut self, message: Message) -> Result<(), WireError> {
+        let mut fds = Vec::new();
+        message.encode(&mut self.data, &mut fds)?;
+        self.fds.extend(fds);
+        Ok(())
+    }
+
+    /// Appends already encoded messages to the outgoing buffer.
+    ///
+    /// `fds` must be the descriptors carried by those messages, in order.
+    pub fn queue_encoded(&mut self, data: &[u8], fds: Vec<OwnedFd>) {
+        self.data.extend_from_slice(data);
+        self.fds.extend(fds);
+    }
+
+    /// Returns the number of buffered bytes not yet written.
+    pub fn pending(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Writes out all buffered messages.
+    ///
+    /// File descriptors are always sent no later than the bytes of the message
+    /// carrying them. This is cancel safe: unwritten data stays buffered.
+    pub async fn flush(&mut self) -> Result<(), WireError> {
+        while !self.data.is_empty() {
+            self.stream.writable().await.map_err(WireError::Io)?;
+
+            let fds: Vec<_> = self
+                .fds
+                .iter()
+                .take(MAX_FDS_PER_CALL)
+                .map(AsFd::as_fd)
+                .collect();
+
+            // More descriptors follow, send them with as little data as possible
+            let len = match self.fds.len() > fds.len() {
+                true => 1,
+                false => self.data.len(),
+            };
+
+            let mut space = [MaybeUninit::uninit(); cmsg_space!(ScmRights(MAX_FDS_PER_CALL))];
+            let mut control = SendAncillaryBuffer::new(&mut space);
+
+            if !fds.is_empty() {
+                control.push(SendAncillaryMessage::ScmRights(&fds));
+            }
+
+            let result = self.stream.try_io(Interest::WRITABLE, || {
+                sendmsg(
+                    &*self.stream,
+                    &[IoSlice::new(&self.data[..len])],
+                    &mut control,
+                    SendFlags::NOSIGNAL | SendFlags::DONTWAIT,
+                )
+                .map_err(io::Error::from)
+            });
+
+            let sent_fds = fds.len();
+
+            match result {
+                Ok(written) => {
+                    self.data.drain(..written);
+                    self.fds.drain(..sent_fds);
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(WireError::Io(err)),
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,47 @@
+use super::ArgumentKind;
+use std::fmt;
+
+/// Static description of a protocol interface.
+pub struct Interface {
+    pub name: &'static str,
+    /// Highest supported version.
+    pub version: u32,
+    /// Requests, indexed by opcode.
+    pub requests: &'static [MessageDesc],
+    /// Events, indexed by opcode.
+    pub events: &'static [MessageDesc],
+}
+
+impl PartialEq for Interface {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for Interface {}
+
+impl fmt::Debug for Interface {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Interfaces reference each other, only print the identity
+        f.debug_struct("Interface")
+            .field("name", &self.name)
+            .field("version", &self.version)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Static description of a request or an event.
+#[derive(Debug)]
+pub struct MessageDesc {
+    pub name: &'static str,
+    /// Version the message was introduced in.
+    pub since: u32,
+    pub signature: &'static [ArgumentKind],
+    /// Interface of each `object` and typed `new_id` argument, parallel to
+    /// `signature`.
+    pub interfaces: &'static [Option<&'static Interface>],
+    /// Interface of the object created by a `new_id` argument, if statically known.
+    pub child_interface: Option<&'static Interface>,
+    /// Whether the message destroys the object it is sent to or from.
+    pub is_destructor: bool,
+}
@@ -0,0 +1,429 @@
+use super::{Argument, ArgumentKind, Fixed, ObjectId, WireError};
+use std::{collections::VecDeque, os::fd::OwnedFd};
+
+/// Size of the message header in bytes.
+pub const HEADER_SIZE: usize = 8;
+
+/// Largest encodable message, including the header.
+pub const MAX_MESSAGE_SIZE: usize = u16::MAX as usize & !3;
+
+/// Message header: sender object, opcode and total size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub object: ObjectId,
+    pub opcode: u16,
+    /// Total message size in bytes, including the header.
+    pub size: u16,
+}
+
+impl Header {
+    /// Parses and validates a header.
+    pub fn parse(bytes: [u8; HEADER_SIZE]) -> Result<Self, WireError> {
+        let object = u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        let word = u32::from_ne_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+
+        let object = ObjectId::new(object).ok_or(WireError::NullObject)?;
+        let opcode = word as u16;
+        let size = (word >> 16) as u16;
+
+        // Size covers the header and is always padded to 32 bits
+        if (size as usize) < HEADER_SIZE || !size.is_multiple_of(4) {
+            return Err(WireError::InvalidSize(size));
+        }
+
+        Ok(Header {
+            object,
+            opcode,
+            size,
+        })
+    }
+
+    /// Returns the wire representation of the header.
+    pub fn to_bytes(&self) -> [u8; HEADER_SIZE] {
+        let mut bytes = [0; HEADER_SIZE];
+        bytes[..4].copy_from_slice(&self.object.get().to_ne_bytes());
+        bytes[4..].copy_from_slice(
+            &((u32::from(self.size) << 16) | u32::from(self.opcode)).to_ne_bytes(),
+        );
+        bytes
+    }
+
+    /// Returns the length of the payload following the header.
+    pub fn body_len(&self) -> usize {
+        self.size as usize - HEADER_SIZE
+    }
+}
+
+/// A decoded Wayland message.
+#[derive(Debug)]
+pub struct Message {
+    /// Object the message is sent to (requests) or from (events).
+    pub object: ObjectId,
+    pub opcode: u16,
+    pub args: Vec<Argument>,
+}
+
+impl Message {
+    /// Creates a message.
+    pub fn new(object: ObjectId, opcode: u16, args: Vec<Argument>) -> Self {
+        Message {
+            object,
+            opcode,
+            args,
+        }
+    }
+
+    /// Decodes a message body according to the given signature.
+    ///
+    /// File descriptor arguments are taken from the front of `fds`.
+    pub fn decode(
+        header: &Header,
+        body: &[u8],
+        signature: &[ArgumentKind],
+        fds: &mut VecDeque<OwnedFd>,
+    ) -> Result<Self, WireError> {
+        if body.len() != header.body_len() {
+            return Err(WireError::Truncated);
+        }
+
+        let mut reader = Reader { body };
+        let mut args = Vec::with_capacity(signature.len());
+
+        for kind in signature {
+            let arg = match kind {
+                ArgumentKind::Int => Argument::Int(reader.word()? as i32),
+                ArgumentKind::Uint => Argument::Uint(reader.word()?),
+                ArgumentKind::Fixed => Argument::Fixed(Fixed::from_raw(reader.word()? as i32)),
+                ArgumentKind::String => Argument::String(reader.string()?),
+                ArgumentKind::Object => Argument::Object(ObjectId::new(reader.word()?)),
+                ArgumentKind::NewId => {
+                    Argument::NewId(ObjectId::new(reader.word()?).ok_or(WireError::NullNewId)?)
+                }
+                ArgumentKind::Array => Argument::Array(reader.array()?.to_vec()),
+                ArgumentKind::Fd => Argument::Fd(fds.pop_front().ok_or(WireError::MissingFd)?),
+            };
+
+            args.push(arg);
+        }
+
+        if !reader.body.is_empty() {
+            return Err(WireError::TrailingData(reader.body.len()));
+        }
+
+        Ok(Message {
+            object: header.object,
+            opcode: header.opcode,
+            args,
+        })
+    }
+
+    /// Encodes the message, appending bytes to `data` and file descriptors to `fds`.
+    ///
+    /// Nothing is appended if the message cannot be encoded.
+    pub fn encode(self, data: &mut Vec<u8>, fds: &mut Vec<OwnedFd>) -> Result<(), WireError> {
+        let start = data.len();
+        let fds_start = fds.len();
+
+        // Reserve the header, it is filled in once the size is known
+        data.extend_from_slice(&[0; HEADER_SIZE]);
+
+        for arg in self.args {
+            match arg {
+                Argument::Int(value) => push_word(data, value as u32),
+                Argument::Uint(value) => push_word(data, value),
+                Argument::Fixed(value) => push_word(data, value.to_raw() as u32),
+                Argument::String(None) => push_word(data, 0),
+                Argument::String(Some(value)) => {
+                    if value.contains('\0') {
+                        data.truncate(start);
+                        fds.truncate(fds_start);
+                        return Err(WireError::InvalidString);
+                    }
+
+                    push_word(data, value.len() as u32 + 1);
+                    data.extend_from_slice(value.as_bytes());
+                    data.push(0);
+                    pad(data, start);
+                }
+                Argument::Object(id) => push_word(data, id.map_or(0, ObjectId::get)),
+                Argument::NewId(id) => push_word(data, id.get()),
+                Argument::Array(value) => {
+                    push_word(data, value.len() as u32);
+                    data.extend_from_slice(&value);
+                    pad(data, start);
+                }
+                Argument::Fd(fd) => fds.push(fd),
+            }
+        }
+
+        let size = data.len() - start;
+
+        if size > MAX_MESSAGE_SIZE {
+            data.truncate(start);
+            fds.truncate(fds_start);
+            return Err(WireError::MessageTooLarge(size));
+        }
+
+        let header = Header {
+            object: self.object,
+            opcode: self.opcode,
+            size: size as u16,
+        };
+
+        data[start..start + HEADER_SIZE].copy_from_slice(&header.to_bytes());
+
+        Ok(())
+    }
+}
+
+/// Cursor over a message body.
+struct Reader<'a> {
+    body: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn word(&mut self) -> Result<u32, WireError> {
+        let (word, rest) = self
+            .body
+            .split_first_chunk::<4>()
+            .ok_or(WireError::Truncated)?;
+        self.body = rest;
+        Ok(u32::from_ne_bytes(*word))
+    }
+
+    /// Reads a length-prefixed, padded byte sequence.
+    fn array(&mut self) -> Result<&'a [u8], WireError> {
+        let len = self.word()? as usize;
+        let padded = len
+            .checked_next_multiple_of(4)
+            .ok_or(WireError::Truncated)?;
+
+        if padded > self.body.len() {
+            return Err(WireError::Truncated);
+        }
+
+        let (bytes, rest) = self.body.split_at(padded);
+        self.body = rest;
+        Ok(&bytes[..len])
+    }
+
+    fn string(&mut self) -> Result<Option<String>, WireError> {
+        let bytes = self.array()?;
+
+        // A zero length denotes the null string
+        let Some((0, bytes)) = bytes.split_last() else {
+            return match bytes.is_empty() {
+                true => Ok(None),
+                false => Err(WireError::InvalidString),
+            };
+        };
+
+        if bytes.contains(&0) {
+            return Err(WireError::InvalidString);
+        }
+
+        String::from_utf8(bytes.to_vec())
+            .map(Some)
+            .map_err(|_| WireError::InvalidString)
+    }
+}
+
+fn push_word(data: &mut Vec<u8>, word: u32) {
+    data.extend_from_slice(&word.to_ne_bytes());
+}
+
+/// Pads the message starting at `start` to a multiple of 4 bytes.
+fn pad(data: &mut Vec<u8>, start: usize) {
+    data.resize(start + (data.len() - start).next_multiple_of(4), 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OBJECT: ObjectId = ObjectId::DISPLAY;
+
+    fn header_bytes(size: u16) -> [u8; HEADER_SIZE] {
+        Header {
+            object: OBJECT,
+            opcode: 0,
+            size,
+        }
+        .to_bytes()
+    }
+
+    fn encode(args: Vec<Argument>) -> Vec<u8> {
+        let mut data = Vec::new();
+        Message::new(OBJECT, 3, args)
+            .encode(&mut data, &mut Vec::new())
+            .unwrap();
+        data
+    }
+
+    fn decode(data: &[u8], signature: &[ArgumentKind]) -> Result<Message, WireError> {
+        let header = Header::parse(data[..HEADER_SIZE].try_into().unwrap())?;
+        Message::decode(
+            &header,
+            &data[HEADER_SIZE..],
+            signature,
+            &mut VecDeque::new(),
+        )
+    }
+
+    #[test]
+    fn header_round_trip() {
+        let header = Header {
+            object: OBJECT,
+            opcode: 7,
+            size: 24,
+        };
+
+        assert_eq!(Header::parse(header.to_bytes()).unwrap(), header);
+        assert_eq!(header.body_len(), 16);
+    }
+
+    #[test]
+    fn header_too_small() {
+        for size in [0, 4] {
+            assert!(matches!(
+                Header::parse(header_bytes(size)),
+                Err(WireError::InvalidSize(s)) if s == size,
+            ));
+        }
+    }
+
+    #[test]
+    fn header_unaligned() {
+        for size in [9, 10, 11, 13] {
+            assert!(matches!(
+                Header::parse(header_bytes(size)),
+                Err(WireError::InvalidSize(s)) if s == size,
+            ));
+        }
+    }
+
+    #[test]
+    fn header_null_object() {
+        let mut bytes = header_bytes(8);
+        bytes[..4].fill(0);
+
+        assert!(matches!(Header::parse(bytes), Err(WireError::NullObject)));
+    }
+
+    #[test]
+    fn trailing_data() {
+        let data = encode(vec![Argument::Uint(1), Argument::Uint(2)]);
+
+        assert!(matches!(
+            decode(&data, &[ArgumentKind::Uint]),
+            Err(WireError::TrailingData(4)),
+        ));
+    }
+
+    #[test]
+    fn truncated_body() {
+        let data = encode(vec![Argument::Uint(1)]);
+
+        assert!(matches!(
+            decode(&data, &[ArgumentKind::Uint, ArgumentKind::Int]),
+            Err(WireError::Truncated),
+        ));
+    }
+
+    #[test]
+    fn string_padding() {
+        // Lengths include the nul terminator and are padded to 32 bits
+        for (value, size) in [("", 16), ("abc", 16), ("abcd", 20), ("abcdefg", 20)] {
+            let data = encode(vec![Argument::String(Some(value.into()))]);
+            assert_eq!(data.len(), size, "{value:?}");
+
+            let message = decode(&data, &[ArgumentKind::String]).unwrap();
+            assert!(matches!(
+                &message.args[..],
+                [Argument::String(Some(decoded))] if decoded == value,
+            ));
+        }
+    }
+
+    #[test]
+    fn null_string() {
+        let data = encode(vec![Argument::String(None), Argument::Uint(5)]);
+        assert_eq!(data.len(), 16);
+
+        let message = decode(&data, &[ArgumentKind::String, ArgumentKind::Uint]).unwrap();
+        assert!(matches!(
+            &message.args[..],
+            [Argument::String(None), Argument::Uint(5)],
+        ));
+    }
+
+    #[test]
+    fn array_padding() {
+        for (len, size) in [(0, 12), (1, 16), (4, 16), (5, 20)] {
+            let value: Vec<u8> = (1..=len).collect();
+            let data = encode(vec![Argument::Array(value.clone()), Argument::Int(-1)]);
+            assert_eq!(data.len(), size + 4, "{len}");
+
+            let message = decode(&data, &[ArgumentKind::Array, ArgumentKind::Int]).unwrap();
+            assert!(matches!(
+                &message.args[..],
+                [Argument::Array(decoded), Argument::Int(-1)] if *decoded == value,
+            ));
+        }
+    }
+
+    #[test]
+    fn encode_interior_nul() {
+        let mut data = vec![0xaa];
+        let message = Message::new(OBJECT, 0, vec![Argument::String(Some("a\0b".into()))]);
+
+        assert!(matches!(
+            message.encode(&mut data, &mut Vec::new()),
+            Err(WireError::InvalidString),
+        ));
+        assert_eq!(data, [0xaa]);
+    }
+
+    #[test]
+    fn decode_interior_nul() {
+        let mut data = encode(vec![Argument::String(Some("abcdef".into()))]);
+        data[HEADER_SIZE + 4 + 2] = 0;
+
+        assert!(matches!(
+            decode(&data, &[ArgumentKind::String]),
+            Err(WireError::InvalidString),
+        ));
+    }
+
+    #[test]
+    fn decode_missing_terminator() {
+        let mut data = encode(vec![Argument::String(Some("abc".into()))]);
+        data[HEADER_SIZE + 4 + 3] = b'd';
+
+        assert!(matches!(
+            decode(&data, &[ArgumentKind::String]),
+            Err(WireError::InvalidString),
+        ));
+    }
+
+    #[test]
+    fn decode_array_overrun() {
+        let mut data = encode(vec![Argument::Array(vec![1, 2, 3, 4])]);
+        data[HEADER_SIZE..HEADER_SIZE + 4].copy_from_slice(&5u32.to_ne_bytes());
+
+        assert!(matches!(
+            decode(&data, &[ArgumentKind::Array]),
+            Err(WireError::Truncated),
+        ));
+    }
+
+    #[test]
+    fn null_new_id() {
+        let data = encode(vec![Argument::Object(None)]);
+
+        assert!(matches!(
+            decode(&data, &[ArgumentKind::NewId]),
+            Err(WireError::NullNewId),
+        ));
+    }
+}
@@ -0,0 +1,104 @@
+mod argument;
+mod connection;
+mod interface;
+mod message;
+
+pub use argument::{Argument, ArgumentKind, Fixed, ObjectId};
+pub use connection::{Connection, MAX_QUEUED_FDS, RawMessage, ReadHalf, WriteHalf};
+pub use interface::{Interface, MessageDesc};
+pub use message::{HEADER_SIZE, Header, MAX_MESSAGE_SIZE, Message};
+
+use std::{collections::VecDeque, io};
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Errors returned when decoding or encoding messages.
+#[derive(Debug, Error)]
+pub enum WireError {
+    #[error("message sent to the null object")]
+    NullObject,
+    #[error("invalid message size: {0}")]
+    InvalidSize(u16),
+    #[error("message too large: {0} bytes")]
+    MessageTooLarge(usize),
+    #[error("message body shorter than its signature")]
+    Truncated,
+    #[error("{0} trailing bytes after the last argument")]
+    TrailingData(usize),
+    #[error("string is not nul-terminated UTF-8")]
+    InvalidString,
+    #[error("null new_id argument")]
+    NullNewId,
+    #[error("missing file descriptor for fd argument")]
+    MissingFd,
+    #[error("too many file descriptors received")]
+    TooManyFds,
+    #[error("stream cannot carry file descriptors")]
+    FdUnsupported,
+    #[error("message has fewer arguments than its signature")]
+    MissingArgument,
+    #[error("argument does not match the signature")]
+    UnexpectedArgument,
+    #[error("null value for a non-nullable argument")]
+    NullArgument,
+    #[error("unknown message: object {object}, opcode {opcode}")]
+    UnknownMessage { object: ObjectId, opcode: u16 },
+    #[error("connection closed by peer")]
+    Closed,
+    #[error("i/o error: {0}")]
+    Io(#[source] io::Error),
+}
+
+/// Reads a header from a byte stream.
+pub async fn read_header<R>(reader: &mut R) -> Result<Header, WireError>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut bytes = [0; HEADER_SIZE];
+    reader.read_exact(&mut bytes).await.map_err(WireError::Io)?;
+    Header::parse(bytes)
+}
+
+/// Reads a complete message from a byte stream, such as the one returned by
+/// [`WaylandSocket::accept`](crate::server::socket::WaylandSocket::accept).
+///
+/// `signature` looks up the argument kinds from the header. Plain streams
+/// cannot carry file descriptors, so `fd` arguments fail with [`WireError::MissingFd`].
+pub async fn read_message<'a, R, F>(reader: &mut R, signature: F) -> Result<Message, WireError>
+where
+    R: AsyncRead + Unpin,
+    F: FnOnce(&Header) -> Option<&'a [ArgumentKind]>,
+{
+    let header = read_header(reader).await?;
+
+    let mut body = vec![0; header.body_len()];
+    reader.read_exact(&mut body).await.map_err(WireError::Io)?;
+
+    let signature = signature(&header).ok_or(WireError::UnknownMessage {
+        object: header.object,
+        opcode: header.opcode,
+    })?;
+
+    Message::decode(&header, &body, signature, &mut VecDeque::new())
+}
+
+/// Writes a complete message to a byte stream.
+///
+/// Messages carrying `fd` arguments are rejected with [`WireError::FdUnsupported`].
+pub async fn write_message<W>(writer: &mut W, message: Message) -> Result<(), WireError>
+where
+    W: AsyncWrite + Unpin,
+{
+    if message
+        .args
+        .iter()
+        .any(|arg| matches!(arg, Argument::Fd(_)))
+    {
+        return Err(WireError::FdUnsupported);
+    }
+
+    let mut data = Vec::new();
+    message.encode(&mut data, &mut Vec::new())?;
+
+    writer.write_all(&data).await.map_err(WireError::Io)
+}